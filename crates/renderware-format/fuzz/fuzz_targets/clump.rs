@@ -0,0 +1,16 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use renderware_format::{dff, raw::BinaryStreamFile};
+
+// Whatever parses is run through the clump layer, which has to reject indices that point
+// nowhere rather than panic on them. Crashing inputs belong in `../regressions`.
+fuzz_target!(|data: &[u8]| {
+    let raw = match BinaryStreamFile::from_bytes(data) {
+        Ok(raw) => raw,
+        Err(_) => return,
+    };
+    if let Ok(clump) = dff::Clump::from_raw(&raw) {
+        let _ = clump.world_transforms();
+    }
+    let _ = dff::Model::from_raw(&raw);
+});
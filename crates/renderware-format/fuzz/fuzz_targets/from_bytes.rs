@@ -0,0 +1,12 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use renderware_format::raw::BinaryStreamFile;
+
+// Inputs that found a crash or an allocation bomb belong in `../regressions`, which
+// `BinaryStreamFile`'s tests run through.
+fuzz_target!(|data: &[u8]| {
+    let _ = BinaryStreamFile::from_bytes(data);
+    for section in BinaryStreamFile::lazy_sections(data).flatten() {
+        let _ = section.decode();
+    }
+});
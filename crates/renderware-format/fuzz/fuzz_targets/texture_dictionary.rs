@@ -0,0 +1,14 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use renderware_format::{raw::BinaryStreamFile, txd};
+
+// Whatever parses is decoded as a texture dictionary, both as RGBA8 and with DXT kept as it
+// is. Crashing inputs belong in `../regressions`.
+fuzz_target!(|data: &[u8]| {
+    let raw = match BinaryStreamFile::from_bytes(data) {
+        Ok(raw) => raw,
+        Err(_) => return,
+    };
+    let _ = txd::Texture::from_raw(&raw);
+    let _ = txd::Texture::from_raw_compressed(&raw);
+});
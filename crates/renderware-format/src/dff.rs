@@ -1,46 +1,89 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    num::TryFromIntError,
+};
 
 use itertools::Itertools;
 
 use crate::raw::{
-    constants::SectionType, Atomic, BinaryStreamFile, ClumpData, Frame, GeometryData, Section,
+    self, constants::SectionType, BinMesh, BinaryStreamFile, ClumpData, Error, RwString, Section,
 };
 
 pub use crate::raw::{
-    constants::{TextureAddressing, TextureFiltering},
-    Color, Lighting, Mat3, Triangle, Vec3,
+    constants::{BlendFunction, CameraProjection, LightType, TextureAddressing, TextureFiltering},
+    Color, Lighting, Mat3, Mat4, Sphere, Triangle, Vec3,
 };
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Transform {
     pub rotation: Mat3,
     pub translation: Vec3,
 }
-impl From<&Frame> for Transform {
-    fn from(frame: &Frame) -> Self {
+impl Transform {
+    pub const IDENTITY: Transform = Transform {
+        rotation: Mat3::IDENTITY,
+        translation: Vec3::ZERO,
+    };
+}
+impl From<&raw::Frame> for Transform {
+    fn from(frame: &raw::Frame) -> Self {
         Self {
             rotation: frame.rotation,
             translation: frame.translation,
         }
     }
 }
+impl std::ops::Mul for Transform {
+    type Output = Transform;
+
+    /// Applies `rhs` first, then `self`; i.e. `parent * child`.
+    fn mul(self, rhs: Transform) -> Transform {
+        Transform {
+            rotation: self.rotation * rhs.rotation,
+            translation: self.rotation * rhs.translation + self.translation,
+        }
+    }
+}
 
 #[derive(Debug, Clone, Copy)]
 pub struct Vertex {
     pub position: Vec3,
     pub normal: Vec3,
-    pub uv: [f32; 2],
+    // Texture coordinates for each of the model's UV sets; only the first `uv_set_count`
+    // are meaningful, and the rest are zero
+    pub uvs: [[f32; 2]; Vertex::MAX_UV_SETS],
     pub material_id: u16,
+    // Indices into the model's skin bones; zero if the model isn't skinned
+    pub bone_indices: [u8; 4],
+    // Weights of each of the bones in `bone_indices`, summing to 1 for skinned models
+    pub bone_weights: [f32; 4],
+    // Baked lighting for the day and night, if the model is prelit
+    pub day_color: Option<Color>,
+    pub night_color: Option<Color>,
 }
 impl Vertex {
+    // RenderWare allows up to eight texture coordinate sets per geometry
+    pub const MAX_UV_SETS: usize = 8;
+
     pub fn new(position: Vec3, normal: Vec3, uv: [f32; 2], material_id: u16) -> Self {
+        let mut uvs = [[0.0; 2]; Self::MAX_UV_SETS];
+        uvs[0] = uv;
         Self {
             position,
             normal,
-            uv,
+            uvs,
             material_id,
+            bone_indices: [0; 4],
+            bone_weights: [0.0; 4],
+            day_color: None,
+            night_color: None,
         }
     }
+
+    /// The texture coordinates from the first UV set.
+    pub fn uv(&self) -> [f32; 2] {
+        self.uvs[0]
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -57,12 +100,125 @@ pub struct Texture {
     pub alpha_name: String,
 }
 
+// An effect from the Material Effects plugin
+#[derive(Debug, Clone)]
+pub enum MaterialEffect {
+    BumpMap {
+        coefficient: f32,
+        bumped_texture: Option<Texture>,
+        bump_texture: Option<Texture>,
+    },
+    EnvMap {
+        coefficient: f32,
+        frame_buffer_alpha: bool,
+        texture: Option<Texture>,
+    },
+    Dual {
+        src_blend: BlendFunction,
+        dst_blend: BlendFunction,
+        texture: Option<Texture>,
+    },
+    UvTransform,
+}
+impl MaterialEffect {
+    // None for an empty effect slot
+    fn from_raw(effect: &raw::MaterialEffect) -> Option<Self> {
+        let texture =
+            |texture: &Option<Box<Section>>| texture.as_deref().and_then(section_to_texture);
+        Some(match effect {
+            raw::MaterialEffect::Null => return None,
+            raw::MaterialEffect::BumpMap {
+                coefficient,
+                bumped_texture,
+                bump_texture,
+            } => MaterialEffect::BumpMap {
+                coefficient: *coefficient,
+                bumped_texture: texture(bumped_texture),
+                bump_texture: texture(bump_texture),
+            },
+            raw::MaterialEffect::EnvMap {
+                coefficient,
+                frame_buffer_alpha,
+                texture: env_texture,
+            } => MaterialEffect::EnvMap {
+                coefficient: *coefficient,
+                frame_buffer_alpha: *frame_buffer_alpha,
+                texture: texture(env_texture),
+            },
+            raw::MaterialEffect::Dual {
+                src_blend,
+                dst_blend,
+                texture: dual_texture,
+            } => MaterialEffect::Dual {
+                src_blend: *src_blend,
+                dst_blend: *dst_blend,
+                texture: texture(dual_texture),
+            },
+            raw::MaterialEffect::UvTransform => MaterialEffect::UvTransform,
+        })
+    }
+}
+
+// San Andreas' vehicle reflections, from Rockstar's reflection material plugin
+#[derive(Debug, Clone)]
+pub struct Reflection {
+    // Applied to the environment map's coordinates
+    pub scale: [f32; 2],
+    pub offset: [f32; 2],
+    pub intensity: f32,
+}
+
+// San Andreas' vehicle specular highlights, from Rockstar's specular material plugin
+#[derive(Debug, Clone)]
+pub struct Specular {
+    pub level: f32,
+    pub texture_name: String,
+}
+
 #[derive(Debug, Clone)]
 pub struct Material {
     pub color: Color,
     pub is_textured: bool,
     pub lighting: Option<Lighting>,
     pub texture: Option<Texture>,
+    pub effects: Vec<MaterialEffect>,
+    // Only San Andreas' materials (and ports of them) have these
+    pub reflection: Option<Reflection>,
+    pub specular: Option<Specular>,
+}
+impl Material {
+    /// The environment map's coefficient and texture, if the material has one.
+    pub fn env_map(&self) -> Option<(f32, Option<&Texture>)> {
+        self.effects.iter().find_map(|e| match e {
+            MaterialEffect::EnvMap {
+                coefficient,
+                texture,
+                ..
+            } => Some((*coefficient, texture.as_ref())),
+            _ => None,
+        })
+    }
+
+    /// The dual pass texture, if the material has one.
+    pub fn dual_texture(&self) -> Option<&Texture> {
+        self.effects.iter().find_map(|e| match e {
+            MaterialEffect::Dual { texture, .. } => texture.as_ref(),
+            _ => None,
+        })
+    }
+
+    /// How the dual pass is blended, as its source and destination blend functions, if the
+    /// material has one.
+    pub fn dual_blend(&self) -> Option<(BlendFunction, BlendFunction)> {
+        self.effects.iter().find_map(|e| match e {
+            MaterialEffect::Dual {
+                src_blend,
+                dst_blend,
+                ..
+            } => Some((*src_blend, *dst_blend)),
+            _ => None,
+        })
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -70,179 +226,653 @@ pub struct Model {
     pub vertices: Vec<Vertex>,
     pub indices: Vec<u16>,
     pub topology: Topology,
+    // The number of UV sets each vertex has
+    pub uv_set_count: usize,
     pub materials: Vec<Material>,
     pub material_indices: Vec<usize>,
+    pub skin: Option<Skin>,
+    // The first target is the one the vertices were built from; any others are keyframes
+    // for morph animation
+    pub morph_targets: Vec<MorphTarget>,
 }
 
-impl Model {
-    fn from_geometry_split_no_split(
-        geometry: &crate::raw::Geometry,
-        material_list: Option<&crate::raw::Section>,
-    ) -> Model {
-        let (geometry_data, vertices, topology, materials) =
-            extract_mesh_data_from_geometry(geometry, material_list);
-
-        let indices = geometry_data
-            .triangles
-            .iter()
-            .flat_map(|t| [t.vertex1, t.vertex2, t.vertex3])
-            .collect();
-        let (materials, material_indices) = materials.unwrap_or_default();
+#[derive(Debug, Clone)]
+pub struct MorphTarget {
+    pub bounding_sphere: Sphere,
+    // One per vertex of the model; either may be empty if the target doesn't have them
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+}
+impl MorphTarget {
+    // `source_indices` maps each of the model's vertices to the geometry vertex it came from
+    fn from_raw(target: &raw::MorphTarget, source_indices: &[u16]) -> Self {
+        let pick = |values: &[Vec3]| {
+            if values.is_empty() {
+                vec![]
+            } else {
+                source_indices.iter().map(|i| values[*i as usize]).collect()
+            }
+        };
+        Self {
+            bounding_sphere: target.bounding_sphere,
+            positions: pick(&target.vertices),
+            normals: pick(&target.normals),
+        }
+    }
+}
 
-        Model {
-            vertices,
-            indices,
-            topology,
-            materials,
-            material_indices,
+#[derive(Debug, Clone)]
+pub struct Skin {
+    // One per bone, transforming from model space to the bone's space. Unlike the raw
+    // matrices, the fourth row is always (0, 0, 0, 1).
+    pub inverse_bone_matrices: Vec<Mat4>,
+    pub max_weights_per_vertex: u8,
+}
+impl From<&raw::Skin> for Skin {
+    fn from(skin: &raw::Skin) -> Self {
+        Self {
+            inverse_bone_matrices: skin
+                .inverse_bone_matrices
+                .iter()
+                .map(|m| {
+                    let mut m = *m;
+                    for column in 0..4 {
+                        m.0[column * 4 + 3] = if column == 3 { 1.0 } else { 0.0 };
+                    }
+                    m
+                })
+                .collect(),
+            max_weights_per_vertex: skin.max_weights_per_vertex,
         }
     }
+}
 
-    // HACK(philpax): Bevy doesn't support multiple diffuse materials per mesh,
-    // so we just partition the meshes by material ID and stitch them back together. #yolo
-    fn from_geometry_split_by_material(
-        geometry: &crate::raw::Geometry,
-        material_list: Option<&crate::raw::Section>,
-    ) -> Model {
-        let (geometry_data, vertices, topology, materials) =
-            extract_mesh_data_from_geometry(geometry, material_list);
-
-        let mut triangles = geometry_data.triangles.clone();
-        triangles.sort_by_key(|t| t.material_id);
-
-        let mut final_vertices = vec![];
-        let mut final_indices = vec![];
-        for (material_id, triangles) in triangles
-            .into_iter()
-            .group_by(|t| t.material_id)
-            .into_iter()
-        {
-            let triangles = triangles.collect_vec();
+// The sections of a geometry that go into building a model
+#[derive(Clone)]
+struct GeometrySections<'a> {
+    geometry: &'a raw::Geometry,
+    geometry_data: &'a raw::GeometryData,
+    materials: Option<(Vec<Material>, Vec<usize>)>,
+    // From the BinMesh if it has indices, and the geometry's own triangles otherwise. Their
+    // vertices and materials are all known to exist.
+    triangles: Vec<Triangle>,
+    skin: Option<&'a raw::Skin>,
+    night_colors: Option<&'a [Color]>,
+}
+impl<'a> GeometrySections<'a> {
+    // `path` is the path of the geometry section, for errors
+    fn from_section(section: &'a Section, path: &str) -> Result<Self, Error> {
+        let geometry = match section.get_child_struct_data() {
+            Some(ClumpData::Geometry(geometry)) => geometry,
+            _ => return Err(section.missing_child(SectionType::Struct, path)),
+        };
+        let unsupported = |feature: &str| Error::Unsupported {
+            feature: feature.to_string(),
+            offset: section.offset,
+            path: path.to_string(),
+        };
+        let geometry_data = geometry
+            .data
+            .as_ref()
+            .ok_or_else(|| unsupported("native geometry"))?;
+        if geometry.morph_targets.is_empty() {
+            return Err(unsupported("geometry without morph targets"));
+        }
 
-            // indices used by this submesh; the indices correspond to vertices of the original mesh
-            let our_indices = triangles
-                .iter()
-                .flat_map(|t| [t.vertex1, t.vertex2, t.vertex3])
-                .collect::<HashSet<_>>()
-                .into_iter()
-                .collect_vec();
+        let materials = section
+            .find_child_by_type(SectionType::MaterialList)
+            .map(|ml| materials_from_list(ml, &format!("{}/MaterialList", path)))
+            .transpose()?;
 
-            // generate a map between original mesh indices and indices in our concatenated submesh
-            let remap_table: HashMap<_, _> = our_indices
-                .iter()
-                .enumerate()
-                .map(|(new_index, old_index)| {
-                    (*old_index, (new_index + final_vertices.len()) as u16)
-                })
-                .collect();
-            let remap = |idx| *remap_table.get(&idx).unwrap();
-
-            // extend our concatenated submeshes' vertices with the vertices used by this submesh
-            final_vertices.extend(our_indices.iter().map(|i| Vertex {
-                material_id,
-                ..vertices[*i as usize]
-            }));
-
-            // extend our concatenated submeshes' indices with the indices used by this submesh,
-            // taking care to remap them to their new location within the mesh
-            final_indices.extend(
-                triangles
-                    .iter()
-                    .flat_map(|t| [remap(t.vertex1), remap(t.vertex2), remap(t.vertex3)]),
-            );
+        let extension = section.find_child_by_type(SectionType::Extension);
+        let plugin_section =
+            |section_type| extension.and_then(|e| e.find_child_by_type(section_type));
+        let plugin = |section_type| plugin_section(section_type).map(|s| &s.data);
+
+        // The BinMesh split is authoritative when present; its strips are unrolled into a
+        // list, as each material needs its own set of vertices anyway. Without one, the
+        // geometry's triangles are always a list, regardless of the TRI_STRIP flag.
+        let (triangles, triangles_offset, triangles_path) =
+            match plugin_section(SectionType::BinMeshPLG) {
+                Some(Section {
+                    data: ClumpData::BinMesh(bin_mesh),
+                    offset,
+                    ..
+                }) if bin_mesh.meshes.iter().any(|m| !m.indices.is_empty()) => (
+                    triangles_from_bin_mesh(bin_mesh)
+                        .map_err(|_| unsupported("BinMesh index above 65535"))?,
+                    *offset,
+                    format!("{}/Extension/BinMeshPLG", path),
+                ),
+                _ => (
+                    geometry_data.triangles.clone(),
+                    section.offset,
+                    path.to_string(),
+                ),
+            };
+
+        // Everything that builds the model indexes by these, so they're checked up front
+        let invalid_index = |what: &str, index: usize| Error::InvalidIndex {
+            what: what.to_string(),
+            index,
+            offset: triangles_offset,
+            path: triangles_path.clone(),
+        };
+        let vertex_count = geometry.morph_targets[0].vertices.len();
+        for triangle in &triangles {
+            let vertices = [triangle.vertex1, triangle.vertex2, triangle.vertex3];
+            if let Some(vertex) = vertices.iter().find(|v| **v as usize >= vertex_count) {
+                return Err(invalid_index("vertex", *vertex as usize));
+            }
+            if let Some((_, material_indices)) = &materials {
+                if triangle.material_id as usize >= material_indices.len() {
+                    return Err(invalid_index("material", triangle.material_id as usize));
+                }
+            }
         }
 
+        Ok(Self {
+            geometry,
+            geometry_data,
+            materials,
+            triangles,
+            skin: match plugin(SectionType::SkinPLG) {
+                Some(ClumpData::Skin(skin)) => Some(skin),
+                _ => None,
+            },
+            night_colors: match plugin(SectionType::ExtraVertColour) {
+                Some(ClumpData::ExtraVertColour(raw::ExtraVertColour {
+                    night_colors: Some(colors),
+                })) => Some(colors),
+                _ => None,
+            },
+        })
+    }
+}
+
+impl Model {
+    // HACK(philpax): Bevy doesn't support multiple diffuse materials per mesh,
+    // so we just partition the meshes by material ID and stitch them back together. #yolo
+    fn from_geometry_split_by_material(sections: &GeometrySections) -> Model {
+        let (vertices, triangles, topology, materials) = extract_mesh_data_from_geometry(sections);
+        let (final_vertices, final_indices, source_indices) =
+            split_by_material(&vertices, triangles);
+
         let (materials, material_indices) = materials.unwrap_or_default();
         Model {
             vertices: final_vertices,
             indices: final_indices,
             topology,
+            uv_set_count: uv_set_count(sections),
             materials,
             material_indices,
+            skin: sections.skin.map(Skin::from),
+            morph_targets: sections
+                .geometry
+                .morph_targets
+                .iter()
+                .map(|t| MorphTarget::from_raw(t, &source_indices))
+                .collect(),
         }
     }
 
-    fn from_geometry_split(
-        geometry: &crate::raw::Geometry,
-        material_list: Option<&crate::raw::Section>,
-    ) -> Model {
-        if cfg!(feature = "use-single-mesh") {
-            Self::from_geometry_split_no_split(geometry, material_list)
+    /// Returns the models of every rendered atomic, alongside the transform of
+    /// their frame relative to its parent. Use [`Clump::from_raw`] to get the full hierarchy.
+    pub fn from_raw(raw: &BinaryStreamFile) -> Result<Vec<(Transform, Model)>, Error> {
+        let clump = Clump::from_raw(raw)?;
+        Ok(clump
+            .atomics
+            .into_iter()
+            .filter(|a| a.render)
+            .map(|a| (clump.frames[a.frame_index].transform, a.model))
+            .collect())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub name: Option<String>,
+    pub parent: Option<usize>,
+    // Relative to the parent frame
+    pub transform: Transform,
+    // The ID of the bone this frame animates, if it's part of a skeleton
+    pub bone_id: Option<i32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Bone {
+    // The ID that animations use to refer to this bone
+    pub id: i32,
+    pub frame_index: usize,
+    // Index of the parent bone within the skeleton, if any
+    pub parent: Option<usize>,
+}
+
+/// The bones of a clump's animation hierarchy. Bones are in depth-first order, which is
+/// also the order that skins refer to them by.
+#[derive(Debug, Clone)]
+pub struct Skeleton {
+    pub bones: Vec<Bone>,
+}
+impl Skeleton {
+    // `frame_list` is only used for errors
+    fn from_hierarchy(
+        hierarchy: &raw::HAnimHierarchy,
+        frames: &[Frame],
+        frame_list: &Section,
+    ) -> Result<Skeleton, Error> {
+        use raw::constants::HAnimNodeFlags;
+
+        let mut parent = None;
+        let mut parent_stack = vec![];
+        let bones = hierarchy
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(index, node)| {
+                let bone = Bone {
+                    id: node.id,
+                    // Every bone must have a frame with a matching HAnimPLG
+                    frame_index: frames
+                        .iter()
+                        .position(|f| f.bone_id == Some(node.id))
+                        .ok_or_else(|| {
+                            frame_list.missing_child(SectionType::HAnimPLG, "Clump/FrameList")
+                        })?,
+                    parent,
+                };
+
+                if node.flags.contains(HAnimNodeFlags::PUSH) {
+                    parent_stack.push(parent);
+                }
+                parent = if node.flags.contains(HAnimNodeFlags::POP) {
+                    parent_stack.pop().flatten()
+                } else {
+                    Some(index)
+                };
+
+                Ok(bone)
+            })
+            .collect::<Result<_, Error>>()?;
+
+        Ok(Skeleton { bones })
+    }
+
+    pub fn bone(&self, id: i32) -> Option<&Bone> {
+        self.bones.iter().find(|b| b.id == id)
+    }
+
+    /// The frame animated by the bone with the given ID.
+    pub fn frame_index(&self, id: i32) -> Option<usize> {
+        self.bone(id).map(|b| b.frame_index)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Atomic {
+    pub frame_index: usize,
+    // Render if in view frustum
+    pub render: bool,
+    pub model: Model,
+}
+
+#[derive(Debug, Clone)]
+pub struct Light {
+    pub frame_index: usize,
+    pub light_type: LightType,
+    pub color: [f32; 3],
+    pub radius: f32,
+    // The angle from the centre of a spot light's cone to its edge, in radians
+    pub cone_angle: f32,
+}
+impl Light {
+    fn from_raw(frame_index: usize, light: &raw::Light, version: raw::RwVersion) -> Self {
+        let cone_angle = if version.light_has_minus_cos_angle() {
+            (-light.minus_cos_angle).clamp(-1.0, 1.0).acos()
         } else {
-            Self::from_geometry_split_by_material(geometry, material_list)
+            light.minus_cos_angle.atan()
+        };
+        Self {
+            frame_index,
+            light_type: light.light_type,
+            color: light.color,
+            radius: light.radius,
+            cone_angle,
         }
     }
+}
 
-    // todo: replace all the panics with errors
-    pub fn from_raw(raw: &BinaryStreamFile) -> Vec<(Transform, Model)> {
-        let clump = &raw.sections[0];
+#[derive(Debug, Clone)]
+pub struct Camera {
+    pub frame_index: usize,
+    pub projection: CameraProjection,
+    // Half the width and height of the view window, one unit in front of the camera
+    pub view_window: (f32, f32),
+    pub view_offset: (f32, f32),
+    pub near_plane: f32,
+    pub far_plane: f32,
+    pub fog_plane: f32,
+}
+impl Camera {
+    fn from_raw(frame_index: usize, camera: &raw::Camera) -> Self {
+        Self {
+            frame_index,
+            projection: camera.projection,
+            view_window: camera.view_window,
+            view_offset: camera.view_offset,
+            near_plane: camera.near_plane,
+            far_plane: camera.far_plane,
+            fog_plane: camera.fog_plane,
+        }
+    }
 
-        let atomics = clump
-            .find_children_by_type(SectionType::Atomic)
-            .filter_map(|s| match s.children[0].data {
-                ClumpData::Atomic(Atomic {
-                    frame_index,
-                    geometry_index,
-                    render,
-                }) if render => Some((frame_index as usize, geometry_index as usize)),
-                _ => None,
-            });
+    /// The vertical field of view of a perspective camera, in radians.
+    pub fn vertical_fov(&self) -> f32 {
+        2.0 * self.view_window.1.atan()
+    }
+}
 
-        let frames = match &clump
-            .find_child_by_type(SectionType::FrameList)
-            .and_then(Section::get_child_struct_data)
-        {
+#[derive(Debug, Clone)]
+pub struct Clump {
+    pub frames: Vec<Frame>,
+    pub atomics: Vec<Atomic>,
+    pub lights: Vec<Light>,
+    pub cameras: Vec<Camera>,
+    pub skeleton: Option<Skeleton>,
+}
+
+impl Clump {
+    pub fn from_raw(raw: &BinaryStreamFile) -> Result<Clump, Error> {
+        let clump = raw
+            .clumps()
+            .next()
+            .ok_or_else(|| raw.missing_section(SectionType::Clump))?;
+
+        let frame_list = clump.require_child_by_type(SectionType::FrameList, "Clump")?;
+        let raw_frames = match frame_list.get_child_struct_data() {
             Some(ClumpData::FrameList(v)) => &v[..],
-            _ => panic!("no frame list data"),
+            _ => return Err(frame_list.missing_child(SectionType::Struct, "Clump/FrameList")),
         };
 
-        let geometry_list = &clump
-            .find_child_by_type(SectionType::GeometryList)
-            .expect("failed to find geometry list");
+        // Each frame has an extension, in order, that may contain its name and bone
+        let mut frame_extensions = frame_list.find_children_by_type(SectionType::Extension);
+        let mut hierarchy = None;
+        let frames: Vec<_> = raw_frames
+            .iter()
+            .enumerate()
+            .map(|(index, frame)| {
+                let extension = frame_extensions.next();
+                let plugin = |section_type| {
+                    extension
+                        .and_then(|e| e.find_child_by_type(section_type))
+                        .map(|s| &s.data)
+                };
+
+                let name = match plugin(SectionType::NodeName) {
+                    Some(ClumpData::NodeName(name)) => Some(name.clone()),
+                    _ => None,
+                };
+                let bone_id = match plugin(SectionType::HAnimPLG) {
+                    Some(ClumpData::HAnim(hanim)) => {
+                        hierarchy = hierarchy.or(hanim.hierarchy.as_ref());
+                        Some(hanim.node_id)
+                    }
+                    _ => None,
+                };
+
+                // Parents always come before their children, which rules out cycles
+                let parent = frame.parent.map(|p| p as usize);
+                if let Some(parent) = parent.filter(|p| *p >= index) {
+                    return Err(Error::InvalidIndex {
+                        what: "parent frame".to_string(),
+                        index: parent,
+                        offset: frame_list.offset,
+                        path: format!("Clump/FrameList/Frame[{}]", index),
+                    });
+                }
 
+                Ok(Frame {
+                    name,
+                    parent,
+                    transform: frame.into(),
+                    bone_id,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        let skeleton = hierarchy
+            .map(|h| Skeleton::from_hierarchy(h, &frames, frame_list))
+            .transpose()?;
+        // Atomics, lights and cameras each belong to a frame
+        let check_frame_index = |frame_index: usize, section: &Section, path: &str| {
+            if frame_index < frames.len() {
+                Ok(frame_index)
+            } else {
+                Err(Error::InvalidIndex {
+                    what: "frame".to_string(),
+                    index: frame_index,
+                    offset: section.offset,
+                    path: path.to_string(),
+                })
+            }
+        };
+
+        let geometry_list = clump.require_child_by_type(SectionType::GeometryList, "Clump")?;
         let geometry_count = match geometry_list.get_child_struct_data() {
             Some(ClumpData::GeometryList { geometry_count }) => *geometry_count,
-            _ => panic!("no geometry list struct"),
+            _ => return Err(geometry_list.missing_child(SectionType::Struct, "Clump/GeometryList")),
         };
 
         let geometries: Vec<_> = geometry_list
             .find_children_by_type(SectionType::Geometry)
-            .map(|s| {
-                (
-                    match s.get_child_struct_data() {
-                        Some(ClumpData::Geometry(geometry)) => geometry,
-                        _ => panic!("no geometry data"),
-                    },
-                    s.find_child_by_type(SectionType::MaterialList),
+            .enumerate()
+            .map(|(index, s)| {
+                GeometrySections::from_section(
+                    s,
+                    &format!("Clump/GeometryList/Geometry[{}]", index),
                 )
             })
-            .collect();
-        assert_eq!(geometry_count as usize, geometries.len());
-
-        atomics
-            .map(|(frame_index, geometry_index)| (&frames[frame_index], geometries[geometry_index]))
-            .map(|(frame, (geometry, material_list))| {
-                (
-                    frame.into(),
-                    Model::from_geometry_split(geometry, material_list),
-                )
+            .collect::<Result<_, _>>()?;
+        if geometries.len() != geometry_count as usize {
+            return Err(geometry_list.missing_child(SectionType::Geometry, "Clump/GeometryList"));
+        }
+
+        let atomics = clump
+            .find_children_by_type(SectionType::Atomic)
+            .enumerate()
+            .map(|(index, s)| match s.get_child_struct_data() {
+                Some(ClumpData::Atomic(raw::Atomic {
+                    frame_index,
+                    geometry_index,
+                    render,
+                    ..
+                })) => Ok(Atomic {
+                    frame_index: check_frame_index(
+                        *frame_index as usize,
+                        s,
+                        &format!("Clump/Atomic[{}]", index),
+                    )?,
+                    render: *render,
+                    model: Model::from_geometry_split_by_material(
+                        geometries.get(*geometry_index as usize).ok_or_else(|| {
+                            geometry_list.missing_child(SectionType::Geometry, "Clump/GeometryList")
+                        })?,
+                    ),
+                }),
+                _ => Err(s.missing_child(SectionType::Struct, &format!("Clump/Atomic[{}]", index))),
             })
-            .collect()
+            .collect::<Result<_, _>>()?;
+
+        // Each light and camera is preceded by a struct with the index of its frame
+        let mut lights = vec![];
+        let mut cameras = vec![];
+        for (previous, section) in clump.children.iter().tuple_windows() {
+            let frame_index = match previous.data {
+                ClumpData::FrameIndex(frame_index) => {
+                    check_frame_index(frame_index as usize, previous, "Clump/Struct")?
+                }
+                _ => continue,
+            };
+            match section.get_child_struct_data() {
+                Some(ClumpData::Light(light)) => {
+                    lights.push(Light::from_raw(frame_index, light, section.version))
+                }
+                Some(ClumpData::Camera(camera)) => {
+                    cameras.push(Camera::from_raw(frame_index, camera))
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Clump {
+            frames,
+            atomics,
+            lights,
+            cameras,
+            skeleton,
+        })
+    }
+
+    /// Finds a frame by name. Names are compared case-insensitively, as the game does.
+    pub fn frame_index(&self, name: &str) -> Option<usize> {
+        self.frames.iter().position(|f| {
+            f.name
+                .as_ref()
+                .map(|n| n.eq_ignore_ascii_case(name))
+                .unwrap_or(false)
+        })
+    }
+
+    pub fn children(&self, frame_index: usize) -> impl Iterator<Item = usize> + '_ {
+        self.frames
+            .iter()
+            .enumerate()
+            .filter(move |(_, f)| f.parent == Some(frame_index))
+            .map(|(index, _)| index)
+    }
+
+    pub fn atomics_for_frame(&self, frame_index: usize) -> impl Iterator<Item = &Atomic> + '_ {
+        self.atomics
+            .iter()
+            .filter(move |a| a.frame_index == frame_index)
+    }
+
+    pub fn lights_for_frame(&self, frame_index: usize) -> impl Iterator<Item = &Light> + '_ {
+        self.lights
+            .iter()
+            .filter(move |l| l.frame_index == frame_index)
+    }
+
+    pub fn cameras_for_frame(&self, frame_index: usize) -> impl Iterator<Item = &Camera> + '_ {
+        self.cameras
+            .iter()
+            .filter(move |c| c.frame_index == frame_index)
+    }
+
+    /// The transform of the frame relative to the root of the clump.
+    pub fn world_transform(&self, frame_index: usize) -> Transform {
+        let mut frame = &self.frames[frame_index];
+        let mut transform = frame.transform;
+        while let Some(parent) = frame.parent {
+            frame = &self.frames[parent];
+            transform = frame.transform * transform;
+        }
+        transform
+    }
+
+    /// The transform of every frame relative to the root of the clump.
+    pub fn world_transforms(&self) -> Vec<Transform> {
+        // Parents come before their children, so they've always been done by the time
+        // their children need them
+        let mut transforms: Vec<Transform> = Vec::with_capacity(self.frames.len());
+        for frame in &self.frames {
+            let transform = match frame.parent {
+                Some(parent) => transforms[parent] * frame.transform,
+                None => frame.transform,
+            };
+            transforms.push(transform);
+        }
+        transforms
     }
 }
 
-type MeshData<'a> = (
-    &'a GeometryData,
+// Gives each material its own copy of the vertices its triangles use, so that every vertex
+// belongs to a single material. Returns the new vertices, their indices, and the index of
+// the vertex each of the new ones came from.
+pub(crate) fn split_by_material(
+    vertices: &[Vertex],
+    mut triangles: Vec<Triangle>,
+) -> (Vec<Vertex>, Vec<u16>, Vec<u16>) {
+    triangles.sort_by_key(|t| t.material_id);
+
+    let mut final_vertices = vec![];
+    let mut final_indices = vec![];
+    // The original index of each of the final vertices
+    let mut source_indices = vec![];
+    for (material_id, triangles) in triangles
+        .into_iter()
+        .group_by(|t| t.material_id)
+        .into_iter()
+    {
+        let triangles = triangles.collect_vec();
+
+        // indices used by this submesh; the indices correspond to vertices of the original mesh
+        let our_indices = triangles
+            .iter()
+            .flat_map(|t| [t.vertex1, t.vertex2, t.vertex3])
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect_vec();
+
+        // generate a map between original mesh indices and indices in our concatenated submesh
+        let remap_table: HashMap<_, _> = our_indices
+            .iter()
+            .enumerate()
+            .map(|(new_index, old_index)| (*old_index, (new_index + final_vertices.len()) as u16))
+            .collect();
+        let remap = |idx| *remap_table.get(&idx).unwrap();
+
+        // extend our concatenated submeshes' vertices with the vertices used by this submesh
+        final_vertices.extend(our_indices.iter().map(|i| Vertex {
+            material_id,
+            ..vertices[*i as usize]
+        }));
+        source_indices.extend(&our_indices);
+
+        // extend our concatenated submeshes' indices with the indices used by this submesh,
+        // taking care to remap them to their new location within the mesh
+        final_indices.extend(
+            triangles
+                .iter()
+                .flat_map(|t| [remap(t.vertex1), remap(t.vertex2), remap(t.vertex3)]),
+        );
+    }
+    (final_vertices, final_indices, source_indices)
+}
+
+fn uv_set_count(sections: &GeometrySections) -> usize {
+    sections
+        .geometry_data
+        .texture_sets
+        .len()
+        .min(Vertex::MAX_UV_SETS)
+}
+
+type MeshData = (
     Vec<Vertex>,
+    Vec<Triangle>,
     Topology,
     Option<(Vec<Material>, Vec<usize>)>,
 );
 
-fn extract_mesh_data_from_geometry<'a>(
-    geometry: &'a crate::raw::Geometry,
-    material_list: Option<&Section>,
-) -> MeshData<'a> {
-    let geometry_data = geometry.data.as_ref().expect("no geometry data");
+fn extract_mesh_data_from_geometry(sections: &GeometrySections) -> MeshData {
+    let GeometrySections {
+        geometry,
+        geometry_data,
+        materials,
+        triangles,
+        skin,
+        night_colors,
+    } = sections;
     let morph_target = &geometry.morph_targets[0];
     let texture_set = if geometry_data.texture_sets.is_empty() {
         vec![[0.0, 0.0]; morph_target.vertices.len()]
@@ -259,7 +889,7 @@ fn extract_mesh_data_from_geometry<'a>(
         morph_target.normals.clone()
     };
 
-    let vertices: Vec<Vertex> = morph_target
+    let mut vertices: Vec<Vertex> = morph_target
         .vertices
         .iter()
         .zip(normals.iter())
@@ -267,44 +897,125 @@ fn extract_mesh_data_from_geometry<'a>(
         .map(|((position, normal), uv)| Vertex::new(*position, *normal, *uv, 0))
         .collect();
 
-    // HACK(philpax): for some reason, we only get correct rendering with triangle list
-    // investigate properly at some point
-    // let is_tri_strip = geometry.format.contains(GeometryFormat::TRI_STRIP);
-    let is_tri_strip = false;
-    let topology = if is_tri_strip {
-        Topology::TriangleStrip
-    } else {
-        Topology::TriangleList
+    for (set, texture_set) in geometry_data
+        .texture_sets
+        .iter()
+        .enumerate()
+        .take(Vertex::MAX_UV_SETS)
+        .skip(1)
+    {
+        for (vertex, (u, v)) in vertices.iter_mut().zip(texture_set) {
+            vertex.uvs[set] = [*u, *v];
+        }
+    }
+
+    if let Some(day_colors) = &geometry_data.prelit_color {
+        // Without night colours, the day colours are used throughout
+        let night_colors = night_colors.unwrap_or(day_colors);
+        for ((vertex, day_color), night_color) in
+            vertices.iter_mut().zip(day_colors).zip(night_colors)
+        {
+            vertex.day_color = Some(*day_color);
+            vertex.night_color = Some(*night_color);
+        }
+    }
+
+    if let Some(skin) = skin {
+        for ((vertex, indices), weights) in vertices
+            .iter_mut()
+            .zip(&skin.bone_indices)
+            .zip(&skin.bone_weights)
+        {
+            vertex.bone_indices = *indices;
+            vertex.bone_weights = *weights;
+        }
+    }
+
+    let triangles = triangles.clone();
+    let topology = Topology::TriangleList;
+
+    (vertices, triangles, topology, materials.clone())
+}
+
+// Returns the materials of a material list and, for each entry of the list, the index of its
+// material; entries may refer back to earlier ones instead of having a material of their own.
+// `path` is the path of the material list, for errors.
+pub(crate) fn materials_from_list(
+    material_list: &Section,
+    path: &str,
+) -> Result<(Vec<Material>, Vec<usize>), Error> {
+    let materials: Vec<_> = material_list
+        .find_children_by_type(SectionType::Material)
+        .filter_map(section_to_material)
+        .collect();
+
+    let material_indices = match material_list.get_child_struct_data() {
+        Some(ClumpData::MaterialList { material_indices }) => material_indices,
+        _ => return Err(material_list.missing_child(SectionType::Struct, path)),
     };
+    let mut present_materials = vec![];
+    let mut result = vec![];
+    let mut last_index = 0;
+    for index in material_indices.iter().copied() {
+        if index == -1 {
+            present_materials.push(last_index);
+            result.push(last_index);
+            last_index += 1;
+        } else {
+            let material = usize::try_from(index)
+                .ok()
+                .and_then(|i| present_materials.get(i))
+                .ok_or_else(|| Error::InvalidIndex {
+                    what: "material".to_string(),
+                    index: index as usize,
+                    offset: material_list.offset,
+                    path: path.to_string(),
+                })?;
+            result.push(*material);
+        }
+    }
+    // Every entry without an index needs a material of its own
+    if last_index > materials.len() {
+        return Err(material_list.missing_child(SectionType::Material, path));
+    }
+    Ok((materials, result))
+}
 
-    let materials = material_list.map(|ml| {
-        let materials: Vec<_> = ml
-            .find_children_by_type(SectionType::Material)
-            .filter_map(section_to_material)
-            .collect();
+// Fails if an index or material doesn't fit in the 16 bits that models use
+fn triangles_from_bin_mesh(bin_mesh: &BinMesh) -> Result<Vec<Triangle>, TryFromIntError> {
+    let mut triangles = vec![];
+    for mesh in &bin_mesh.meshes {
+        let material_id = u16::try_from(mesh.material_index)?;
+        let indices = mesh
+            .indices
+            .iter()
+            .map(|i| u16::try_from(*i))
+            .collect::<Result<Vec<_>, _>>()?;
+        let triangle = |vertex1, vertex2, vertex3| Triangle {
+            vertex1,
+            vertex2,
+            vertex3,
+            material_id,
+        };
 
-        let material_indices = match ml.get_child_struct_data() {
-            Some(ClumpData::MaterialList { material_indices }) => {
-                let mut present_materials = vec![];
-                let mut result = vec![];
-                let mut last_index = 0;
-                for index in material_indices.iter().copied() {
-                    if index == -1 {
-                        present_materials.push(last_index);
-                        result.push(last_index);
-                        last_index += 1;
-                    } else {
-                        result.push(present_materials[index as usize]);
-                    }
+        if bin_mesh.is_tri_strip() {
+            for (i, w) in indices.windows(3).enumerate() {
+                // Strips are joined with degenerate triangles, which we can drop
+                if w[0] == w[1] || w[1] == w[2] || w[0] == w[2] {
+                    continue;
                 }
-                result
+                // Every other triangle in a strip has its winding order flipped
+                triangles.push(if i % 2 == 0 {
+                    triangle(w[0], w[1], w[2])
+                } else {
+                    triangle(w[1], w[0], w[2])
+                });
             }
-            _ => unreachable!("we should always have MaterialList data"),
-        };
-        (materials, material_indices)
-    });
-
-    (geometry_data, vertices, topology, materials)
+        } else {
+            triangles.extend(indices.chunks_exact(3).map(|c| triangle(c[0], c[1], c[2])));
+        }
+    }
+    Ok(triangles)
 }
 
 fn section_to_material(material: &Section) -> Option<Material> {
@@ -315,32 +1026,530 @@ fn section_to_material(material: &Section) -> Option<Material> {
 
     let texture = material
         .find_child_by_type(SectionType::Texture)
-        .and_then(|texture| {
-            let texture_data = match texture.get_child_struct_data()? {
-                ClumpData::Texture(t) => t,
-                _ => return None,
-            };
+        .and_then(section_to_texture);
 
-            let names: Vec<&String> = texture
-                .find_children_by_type(SectionType::String)
-                .filter_map(|s| match &s.data {
-                    ClumpData::String(s) => Some(s),
-                    _ => None,
-                })
-                .collect();
+    let extension = material.find_child_by_type(SectionType::Extension);
+    let plugin = |section_type| {
+        extension
+            .and_then(|e| e.find_child_by_type(section_type))
+            .map(|s| &s.data)
+    };
 
-            Some(Texture {
-                filtering: texture_data.filtering,
-                uv: texture_data.uv,
-                name: names[0].to_string(),
-                alpha_name: names[1].to_string(),
-            })
-        });
+    let effects = match plugin(SectionType::MaterialEffectsPLG) {
+        Some(ClumpData::MaterialEffects(effects)) => effects
+            .effects
+            .iter()
+            .filter_map(MaterialEffect::from_raw)
+            .collect(),
+        _ => vec![],
+    };
+    let reflection = match plugin(SectionType::ReflectionMaterial) {
+        Some(ClumpData::ReflectionMaterial(reflection)) => Some(Reflection {
+            scale: [reflection.scale.0, reflection.scale.1],
+            offset: [reflection.offset.0, reflection.offset.1],
+            intensity: reflection.intensity,
+        }),
+        _ => None,
+    };
+    let specular = match plugin(SectionType::SpecularMaterial) {
+        Some(ClumpData::SpecularMaterial(specular)) => Some(Specular {
+            level: specular.level,
+            texture_name: specular.texture_name.to_string(),
+        }),
+        _ => None,
+    };
 
     Some(Material {
         color: material_data.color,
         is_textured: material_data.is_textured,
         lighting: material_data.lighting,
         texture,
+        effects,
+        reflection,
+        specular,
     })
 }
+
+fn section_to_texture(texture: &Section) -> Option<Texture> {
+    let texture_data = match texture.get_child_struct_data()? {
+        ClumpData::Texture(t) => t,
+        _ => return None,
+    };
+
+    let names: Vec<&RwString> = texture
+        .find_children_by_type(SectionType::String)
+        .filter_map(|s| match &s.data {
+            ClumpData::String(s) => Some(s),
+            _ => None,
+        })
+        .collect();
+
+    Some(Texture {
+        filtering: texture_data.filtering,
+        uv: texture_data.uv,
+        name: names.first()?.to_string(),
+        alpha_name: names.get(1).map(|s| s.to_string()).unwrap_or_default(),
+    })
+}
+
+mod tests {
+    #[test]
+    fn parses_each_games_clumps() {
+        use crate::raw::{BinaryStreamFile, ClumpData, Error, RwVersion};
+        use crate::test_util::{floats, section_with_id};
+
+        // A clump with a single triangle, laid out as each game's version would have it
+        let clump = |library_id: u32, version: RwVersion| {
+            let section =
+                |section_type, data: &[u8]| section_with_id(section_type, library_id, data);
+
+            let mut material = vec![0, 0, 0, 0, 255, 255, 255, 255, 0, 0, 0, 0, 0, 0, 0, 0];
+            if version > RwVersion(0x3_0400) {
+                material.extend(floats(&[1.0, 1.0, 1.0]));
+            }
+            let material = section(0x01, &material);
+            let mut material_list = section(0x01, &[1, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF]);
+            material_list.extend(section(0x07, &material));
+
+            // Positions, normals and a texture set
+            let mut geometry = vec![0x16, 0, 0, 0, 1, 0, 0, 0, 3, 0, 0, 0, 1, 0, 0, 0];
+            if version < RwVersion(0x3_4000) {
+                geometry.extend(floats(&[1.0, 1.0, 1.0]));
+            }
+            geometry.extend(floats(&[0.0, 0.0, 1.0, 0.0, 0.0, 1.0]));
+            geometry.extend([1, 0, 0, 0, 0, 0, 2, 0]);
+            geometry.extend(floats(&[0.0, 0.0, 0.0, 1.0]));
+            geometry.extend([1, 0, 0, 0, 1, 0, 0, 0]);
+            geometry.extend(floats(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]));
+            geometry.extend(floats(&[0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0]));
+            let mut geometry_children = section(0x01, &geometry);
+            geometry_children.extend(section(0x08, &material_list));
+            geometry_children.extend(section(0x03, &[]));
+            let mut geometry_list = section(0x01, &[1, 0, 0, 0]);
+            geometry_list.extend(section(0x0F, &geometry_children));
+
+            let mut frame = floats(&[1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0]);
+            frame.extend(floats(&[0.0, 0.0, 0.0]));
+            frame.extend([0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0]);
+            let mut frame_list = section(0x01, &[&[1, 0, 0, 0], &frame[..]].concat());
+            frame_list.extend(section(0x03, &[]));
+
+            let mut atomic = section(0x01, &[0, 0, 0, 0, 0, 0, 0, 0, 5, 0, 0, 0, 0, 0, 0, 0]);
+            atomic.extend(section(0x03, &[]));
+
+            // A spot light with a 60 degree cone, attached to the only frame
+            let mut light = floats(&[10.0, 1.0, 0.5, 0.25, -0.5]);
+            light.extend([3, 0, 0x81, 0]);
+            let mut light = section(0x01, &light);
+            light.extend(section(0x03, &[]));
+
+            let mut counts = vec![1, 0, 0, 0];
+            if version > RwVersion(0x3_3000) {
+                counts.extend([1, 0, 0, 0, 0, 0, 0, 0]);
+            }
+            let mut children = section(0x01, &counts);
+            children.extend(section(0x0E, &frame_list));
+            children.extend(section(0x1A, &geometry_list));
+            children.extend(section(0x14, &atomic));
+            if version > RwVersion(0x3_3000) {
+                children.extend(section(0x01, &[0, 0, 0, 0]));
+                children.extend(section(0x12, &light));
+            }
+            section(0x10, &children)
+        };
+
+        let mut versions = vec![
+            (0x0401FFFF, RwVersion::GTA3),
+            (0x0C02FFFF, RwVersion::VICE_CITY),
+        ];
+        if cfg!(feature = "san_andreas_support") {
+            versions.push((0x1803FFFF, RwVersion::SAN_ANDREAS));
+        }
+        for (library_id, version) in versions {
+            let bytes = clump(library_id, version);
+            let raw = BinaryStreamFile::from_bytes(&bytes).unwrap();
+            assert_eq!(raw.sections[0].version, version);
+            assert_eq!(raw.to_bytes(), bytes);
+
+            let clump = super::Clump::from_raw(&raw).unwrap();
+            assert_eq!(clump.frames.len(), 1);
+            let model = &clump.atomics[0].model;
+            assert_eq!(model.vertices.len(), 3);
+            assert!(model.vertices.iter().any(|v| v.uv() == [0.0, 1.0]));
+            assert_eq!(
+                model.materials[0].color,
+                super::Color::new(255, 255, 255, 255)
+            );
+            if version > RwVersion(0x3_3000) {
+                let light = &clump.lights[0];
+                assert_eq!(light.light_type, super::LightType::Spot);
+                assert_eq!(light.color, [1.0, 0.5, 0.25]);
+                assert!((light.cone_angle - std::f32::consts::FRAC_PI_3).abs() < 1e-6);
+                assert_eq!(clump.lights_for_frame(0).count(), 1);
+            }
+
+            // Indices past the end of what they refer to are errors, rather than panics
+            let corrupt = |edit: &dyn Fn(&mut ClumpData)| {
+                let mut raw = BinaryStreamFile::from_bytes(&bytes).unwrap();
+                let mut sections = vec![&mut raw.sections[0]];
+                while let Some(section) = sections.pop() {
+                    edit(&mut section.data);
+                    sections.extend(&mut section.children);
+                }
+                super::Clump::from_raw(&raw)
+            };
+            let triangle = |edit: &dyn Fn(&mut super::Triangle)| {
+                corrupt(&|data| {
+                    if let ClumpData::Geometry(geometry) = data {
+                        edit(&mut geometry.data.as_mut().unwrap().triangles[0]);
+                    }
+                })
+            };
+            for result in [
+                triangle(&|t| t.vertex1 = 9),
+                triangle(&|t| t.material_id = 4),
+                corrupt(&|data| {
+                    if let ClumpData::MaterialList { material_indices } = data {
+                        material_indices.push(5);
+                    }
+                }),
+                corrupt(&|data| {
+                    if let ClumpData::Atomic(atomic) = data {
+                        atomic.frame_index = 1;
+                    }
+                }),
+            ] {
+                assert!(matches!(result, Err(Error::InvalidIndex { .. })));
+            }
+        }
+    }
+
+    #[test]
+    fn parses_gta3_clumps_with_default_features() {
+        use crate::raw::{BinaryStreamFile, RwVersion};
+        use crate::test_util::section_with_id;
+
+        // GTA III's files are 3.1, which needs no feature to parse
+        let section = |section_type, data: &[u8]| section_with_id(section_type, 0x0401FFFF, data);
+        let mut frame_list = section(0x01, &[0, 0, 0, 0]);
+        frame_list.extend(section(0x03, &[]));
+        let mut children = section(0x01, &[0, 0, 0, 0]);
+        children.extend(section(0x0E, &frame_list));
+        children.extend(section(0x1A, &section(0x01, &[0, 0, 0, 0])));
+        children.extend(section(0x03, &[]));
+        let bytes = section(0x10, &children);
+
+        let raw = BinaryStreamFile::from_bytes(&bytes).unwrap();
+        assert_eq!(raw.sections[0].version, RwVersion::GTA3);
+        let clump = super::Clump::from_raw(&raw).unwrap();
+        assert!(clump.frames.is_empty() && clump.atomics.is_empty());
+    }
+
+    #[test]
+    fn reads_night_colors_from_extra_vert_colour() {
+        use crate::raw::{BinaryStreamFile, Color};
+        use crate::test_util::{floats, section};
+
+        // A prelit triangle, with night colours from the plugin if there are any
+        let geometry = |night_colors: Option<&[u8]>| {
+            let mut geometry = vec![0x0A, 0, 0, 0, 1, 0, 0, 0, 3, 0, 0, 0, 1, 0, 0, 0];
+            geometry.extend(floats(&[1.0, 1.0, 1.0]));
+            geometry.extend([10, 20, 30, 255, 40, 50, 60, 255, 70, 80, 90, 255]);
+            geometry.extend([1, 0, 0, 0, 0, 0, 2, 0]);
+            geometry.extend(floats(&[0.0, 0.0, 0.0, 1.0]));
+            geometry.extend([1, 0, 0, 0, 0, 0, 0, 0]);
+            geometry.extend(floats(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]));
+            let extension = match night_colors {
+                Some(colors) => section(0x0253F2F9, &[&[1, 0, 0, 0], colors].concat()),
+                None => vec![],
+            };
+            let mut children = section(0x01, &geometry);
+            children.extend(section(0x03, &extension));
+            BinaryStreamFile::from_bytes(&section(0x0F, &children)).unwrap()
+        };
+        let model = |raw: &BinaryStreamFile| {
+            let sections = super::GeometrySections::from_section(&raw.sections[0], "Geometry");
+            super::Model::from_geometry_split_by_material(&sections.unwrap())
+        };
+
+        let night = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
+        let lit = model(&geometry(Some(&night)));
+        let vertex = lit.vertices.iter().find(|v| v.position.y == 1.0).unwrap();
+        assert_eq!(vertex.day_color, Some(Color::new(70, 80, 90, 255)));
+        assert_eq!(vertex.night_color, Some(Color::new(9, 10, 11, 12)));
+
+        // Without the plugin, the day colours are used at night too
+        let unlit = model(&geometry(None));
+        assert!(unlit.vertices.iter().all(|v| v.night_color == v.day_color));
+        assert!(unlit.vertices.iter().all(|v| v.day_color.is_some()));
+    }
+
+    #[test]
+    fn reads_every_texture_set() {
+        use crate::raw::BinaryStreamFile;
+        use crate::test_util::{floats, section};
+
+        // A triangle with two texture sets, given by the TEXTURED2 flag rather than a count
+        let mut geometry = vec![0x86, 0, 0, 0, 1, 0, 0, 0, 3, 0, 0, 0, 1, 0, 0, 0];
+        geometry.extend(floats(&[1.0, 1.0, 1.0]));
+        geometry.extend(floats(&[0.0, 0.0, 1.0, 0.0, 0.0, 1.0]));
+        geometry.extend(floats(&[0.5, 0.5, 0.5, 0.5, 0.5, 0.5]));
+        geometry.extend([1, 0, 0, 0, 0, 0, 2, 0]);
+        geometry.extend(floats(&[0.0, 0.0, 0.0, 1.0]));
+        geometry.extend([1, 0, 0, 0, 0, 0, 0, 0]);
+        geometry.extend(floats(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]));
+        let mut children = section(0x01, &geometry);
+        children.extend(section(0x03, &[]));
+        let raw = BinaryStreamFile::from_bytes(&section(0x0F, &children)).unwrap();
+
+        let sections = super::GeometrySections::from_section(&raw.sections[0], "Geometry");
+        let model = super::Model::from_geometry_split_by_material(&sections.unwrap());
+        assert_eq!(model.uv_set_count, 2);
+        let vertex = model.vertices.iter().find(|v| v.position.x == 1.0).unwrap();
+        assert_eq!(vertex.uvs[0], [1.0, 0.0]);
+        assert!(model.vertices.iter().all(|v| v.uvs[1] == [0.5, 0.5]));
+    }
+
+    #[test]
+    fn reads_reflection_and_specular_per_material() {
+        use crate::raw::BinaryStreamFile;
+        use crate::test_util::{floats, section};
+
+        // San Andreas' cars give each material its own reflection and specular plugins,
+        // or none at all
+        let material = |plugins: Option<(f32, &[u8; 24])>| {
+            let mut material = vec![0, 0, 0, 0, 255, 255, 255, 255, 0, 0, 0, 0, 0, 0, 0, 0];
+            material.extend(floats(&[1.0, 1.0, 1.0]));
+            let mut material = section(0x01, &material);
+            if let Some((intensity, texture_name)) = plugins {
+                let mut reflection = floats(&[1.0, 1.0, 0.0, 0.0, intensity]);
+                reflection.extend([0; 4]);
+                let mut specular = floats(&[intensity]);
+                specular.extend(texture_name);
+                let mut extension = section(0x0253F2FC, &reflection);
+                extension.extend(section(0x0253F2F6, &specular));
+                material.extend(section(0x03, &extension));
+            }
+            section(0x07, &material)
+        };
+        let mut children = vec![3, 0, 0, 0];
+        children.extend([0xFF; 12]);
+        let mut children = section(0x01, &children);
+        children.extend(material(Some((0.75, b"vehiclespecdot64\0\0\0\0\0\0\0\0"))));
+        children.extend(material(None));
+        children.extend(material(Some((
+            0.25,
+            b"carpaint\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0",
+        ))));
+        let raw = BinaryStreamFile::from_bytes(&section(0x08, &children)).unwrap();
+
+        let (materials, _) = super::materials_from_list(&raw.sections[0], "MaterialList").unwrap();
+        let reflection = materials[0].reflection.as_ref().unwrap();
+        assert_eq!((reflection.scale, reflection.intensity), ([1.0, 1.0], 0.75));
+        let specular = materials[0].specular.as_ref().unwrap();
+        assert_eq!(specular.texture_name, "vehiclespecdot64");
+        assert!(materials[1].reflection.is_none() && materials[1].specular.is_none());
+        assert_eq!(materials[2].reflection.as_ref().unwrap().intensity, 0.25);
+        let specular = materials[2].specular.as_ref().unwrap();
+        assert_eq!(
+            (specular.level, specular.texture_name.as_str()),
+            (0.25, "carpaint")
+        );
+    }
+
+    #[test]
+    fn remaps_morph_targets_to_model_vertices() {
+        use crate::raw::{self, Sphere, Vec3};
+
+        let v = |x| Vec3 { x, y: 0.0, z: 0.0 };
+        let target = raw::MorphTarget {
+            bounding_sphere: Sphere {
+                position: v(1.0),
+                radius: 2.0,
+            },
+            vertices: vec![v(0.0), v(1.0), v(2.0)],
+            normals: vec![],
+            has_vertices: 1,
+            has_normals: 0,
+        };
+
+        let target = super::MorphTarget::from_raw(&target, &[2, 0, 2]);
+        assert_eq!(target.positions, vec![v(2.0), v(0.0), v(2.0)]);
+        assert!(target.normals.is_empty());
+        assert_eq!(target.bounding_sphere.radius, 2.0);
+    }
+
+    #[test]
+    fn can_unroll_bin_mesh_strips() {
+        use crate::raw::{BinMesh, Mesh, Triangle, UnparsedData};
+
+        let bin_mesh = BinMesh {
+            flags: 1,
+            total_index_count: 9,
+            meshes: vec![
+                Mesh {
+                    material_index: 0,
+                    index_count: 4,
+                    indices: vec![0, 1, 2, 3],
+                },
+                Mesh {
+                    material_index: 1,
+                    index_count: 5,
+                    // Two strips joined by degenerate triangles
+                    indices: vec![4, 5, 6, 6, 7],
+                },
+            ],
+            remainder: UnparsedData(vec![]),
+        };
+
+        let triangle = |vertex1, vertex2, vertex3, material_id| Triangle {
+            vertex1,
+            vertex2,
+            vertex3,
+            material_id,
+        };
+        assert_eq!(
+            super::triangles_from_bin_mesh(&bin_mesh).unwrap(),
+            vec![
+                triangle(0, 1, 2, 0),
+                triangle(2, 1, 3, 0),
+                triangle(4, 5, 6, 1),
+            ]
+        );
+
+        // Models only have 16-bit indices, so anything larger can't be used
+        let mut bin_mesh = bin_mesh;
+        bin_mesh.meshes[0].indices[3] = 0x1_0000;
+        assert!(super::triangles_from_bin_mesh(&bin_mesh).is_err());
+    }
+
+    #[test]
+    fn can_compose_frame_transforms() {
+        use super::{Clump, Frame, Mat3, Transform, Vec3};
+
+        let frame = |parent, rotation, (x, y, z)| Frame {
+            name: None,
+            parent,
+            transform: Transform {
+                rotation,
+                translation: Vec3 { x, y, z },
+            },
+            bone_id: None,
+        };
+        // A quarter turn around Z: right becomes +Y, up becomes -X
+        let quarter_turn = Mat3([0.0, 1.0, 0.0, -1.0, 0.0, 0.0, 0.0, 0.0, 1.0]);
+        let clump = Clump {
+            frames: vec![
+                frame(None, quarter_turn, (10.0, 0.0, 0.0)),
+                frame(Some(0), Mat3::IDENTITY, (1.0, 0.0, 0.0)),
+                frame(Some(1), Mat3::IDENTITY, (0.0, 0.0, 2.0)),
+            ],
+            atomics: vec![],
+            lights: vec![],
+            cameras: vec![],
+            skeleton: None,
+        };
+
+        assert_eq!(
+            clump.world_transform(2),
+            Transform {
+                rotation: quarter_turn,
+                translation: Vec3 {
+                    x: 10.0,
+                    y: 1.0,
+                    z: 2.0
+                },
+            }
+        );
+        assert_eq!(clump.world_transforms()[2], clump.world_transform(2));
+        assert_eq!(clump.children(0).collect::<Vec<_>>(), vec![1]);
+    }
+
+    #[test]
+    fn rejects_frames_before_their_parents() {
+        use super::{Clump, Mat3, Vec3};
+        use crate::raw::{
+            self, constants::SectionType, BinaryStreamFile, ClumpData, Error, UnparsedData,
+        };
+        use crate::test_util::parsed_section as section;
+
+        let frame = |parent| raw::Frame {
+            rotation: Mat3::IDENTITY,
+            translation: Vec3::ZERO,
+            parent,
+            matrix_creation_flags: 0,
+        };
+        // A frame that's its own parent, and one whose parent comes after it
+        for parents in [vec![Some(0)], vec![None, Some(2), Some(1)]] {
+            let frames = parents.into_iter().map(frame).collect();
+            let frame_list = section(
+                SectionType::FrameList,
+                ClumpData::Unknown,
+                vec![section(
+                    SectionType::Struct,
+                    ClumpData::FrameList(frames),
+                    vec![],
+                )],
+            );
+            let raw = BinaryStreamFile {
+                sections: vec![section(
+                    SectionType::Clump,
+                    ClumpData::Unknown,
+                    vec![frame_list],
+                )],
+                padding: UnparsedData(vec![]),
+            };
+            assert!(matches!(
+                Clump::from_raw(&raw),
+                Err(Error::InvalidIndex { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn can_build_skeleton_from_hierarchy() {
+        use super::{Frame, SectionType, Skeleton, Transform};
+        use crate::raw::{constants::HAnimNodeFlags, HAnimHierarchy, HAnimNode};
+
+        // root -> (a -> b), c; the frames are deliberately not in hierarchy order
+        let ids = [30, 0, 10, 20];
+        let frames: Vec<_> = ids
+            .iter()
+            .map(|id| Frame {
+                name: None,
+                parent: None,
+                transform: Transform::IDENTITY,
+                bone_id: Some(*id),
+            })
+            .collect();
+        let node = |id, flags| HAnimNode {
+            id,
+            index: 0,
+            flags,
+        };
+        let hierarchy = HAnimHierarchy {
+            flags: 0,
+            key_frame_size: 36,
+            nodes: vec![
+                node(0, HAnimNodeFlags::empty()),
+                node(10, HAnimNodeFlags::PUSH),
+                node(20, HAnimNodeFlags::POP),
+                node(30, HAnimNodeFlags::POP),
+            ],
+        };
+
+        let frame_list = crate::raw::Section {
+            section_type: SectionType::FrameList,
+            version: crate::raw::RwVersion::VICE_CITY,
+            build: None,
+            offset: 0,
+            children: vec![],
+            data: crate::raw::ClumpData::Unknown,
+        };
+        let skeleton = Skeleton::from_hierarchy(&hierarchy, &frames, &frame_list).unwrap();
+        let parents: Vec<_> = skeleton.bones.iter().map(|b| b.parent).collect();
+        assert_eq!(parents, vec![None, Some(0), Some(1), Some(0)]);
+        assert_eq!(skeleton.frame_index(30), Some(0));
+        assert_eq!(skeleton.frame_index(40), None);
+    }
+}
@@ -2,3 +2,7 @@ pub mod dff;
 pub mod packer;
 pub mod raw;
 pub mod txd;
+pub mod world;
+
+#[cfg(test)]
+mod test_util;
@@ -1,12 +1,12 @@
 use std::collections::HashMap;
 
-use crate::{dff, txd};
+use crate::{
+    dff,
+    txd::{self, complete_mip_chain, PixelFormat},
+};
 
 use texture_packer as tp;
-use tp::texture::{
-    memory_rgba8_texture::{MemoryRGBA8Texture, RGBA8},
-    Texture,
-};
+use tp::texture::{memory_rgba8_texture::RGBA8, Texture};
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct Frame {
@@ -18,10 +18,40 @@ pub struct Frame {
 pub struct PackedTexture {
     pub width: u16,
     pub height: u16,
-    pub data: Vec<u8>,
+    // RGBA8 unless every texture that went into it shared a compressed format, which is kept
+    pub format: PixelFormat,
+    // Data in `format` for the full-size level, followed by each mip level, which are built
+    // from the mip levels of the textures that went into it
+    pub levels: Vec<Vec<u8>>,
     pub frames: Vec<Frame>,
 }
 
+// Stands in for a texture while packing, as only its size matters; the levels are copied in
+// afterwards, so that compressed ones can be copied block by block
+#[derive(Clone)]
+struct Placeholder {
+    width: u32,
+    height: u32,
+}
+
+impl Texture for Placeholder {
+    type Pixel = RGBA8;
+
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn get(&self, _x: u32, _y: u32) -> Option<RGBA8> {
+        None
+    }
+
+    fn set(&mut self, _x: u32, _y: u32, _val: RGBA8) {}
+}
+
 pub fn repack_model_textures(
     materials: &[dff::Material],
     material_indices: &[usize],
@@ -30,17 +60,32 @@ pub fn repack_model_textures(
     // Sort our materials so that the smallest is added to the packer first.
     let texture_data_by_name: HashMap<_, _> =
         textures.iter().map(|t| (t.name.clone(), t)).collect();
+    let format = packed_format(
+        &texture_data_by_name,
+        material_indices.iter().map(|idx| &materials[*idx]),
+    );
     let mut materials: Vec<_> = material_indices
         .iter()
         .copied()
         .map(|idx| {
             (
                 idx as u16,
-                material_to_texture_data(&texture_data_by_name, &materials[idx]),
+                material_to_texture_data(&texture_data_by_name, &materials[idx], format),
             )
         })
         .collect();
     materials.sort_by_key(|m| -((m.1 .1 * m.1 .2) as i32));
+    // Past the point where the smallest texture is a single pixel, neighbouring textures
+    // would bleed into each other
+    let max_level_count = materials
+        .iter()
+        .map(|(_, (levels, width, height))| {
+            levels
+                .len()
+                .min((32 - width.min(height).leading_zeros()) as usize)
+        })
+        .min()
+        .unwrap_or(1);
 
     // Start packing!
     let mut packer = tp::TexturePacker::new_skyline(tp::TexturePackerConfig {
@@ -49,39 +94,73 @@ pub fn repack_model_textures(
         allow_rotation: false,
         texture_padding: 0,
         texture_outlines: false,
+        trim: false,
         ..Default::default()
     });
-    for (idx, (buf, width, height)) in materials {
-        let mem_texture = MemoryRGBA8Texture::from_memory(&buf, width, height);
-        packer.pack_own(idx, mem_texture).unwrap();
+    for (idx, (_, width, height)) in &materials {
+        let placeholder = Placeholder {
+            width: *width,
+            height: *height,
+        };
+        packer.pack_own(*idx, placeholder).unwrap();
     }
     let packer = packer;
-
-    // Copy our packed texture into a buffer.
     let width = packer.width();
     let height = packer.height();
-    let mut new_texture_data = vec![];
-    for y in 0..height {
-        for x in 0..width {
-            let p = packer.get(x, y).unwrap_or(RGBA8 {
-                r: 0,
-                g: 0,
-                b: 0,
-                a: 255,
-            });
-            new_texture_data.extend_from_slice(&[p.r, p.g, p.b, p.a]);
-        }
-    }
+    let rects: HashMap<_, _> = materials
+        .iter()
+        .map(|(idx, _)| (*idx, packer.get_frame(idx).unwrap().frame))
+        .collect();
+
+    // Compressed levels can only be copied while every texture still starts and ends on a
+    // block boundary
+    let (block, block_bytes) = format.block_size();
+    let is_aligned = |level: usize| {
+        rects
+            .values()
+            .flat_map(|r| [r.x, r.y, r.w, r.h])
+            .chain([width, height])
+            .all(|v| v % (block << level) == 0)
+    };
+    let level_count = (0..max_level_count)
+        .take_while(|level| !format.is_compressed() || is_aligned(*level))
+        .count()
+        .max(1);
+
+    // Each level places the textures' own levels where their full-size ones went, scaled
+    // down to match, a block at a time
+    let levels = (0..level_count)
+        .map(|level| {
+            let blocks = |size: u32| ((size >> level).max(1)).div_ceil(block) as usize;
+            let (level_width, level_height) = (blocks(width), blocks(height));
+            let mut data = vec![0u8; level_width * level_height * block_bytes];
+            for (idx, (texture_levels, _, _)) in &materials {
+                let rect = rects[idx];
+                let (x, y) = ((rect.x >> level) / block, (rect.y >> level) / block);
+                let (x, y) = (x as usize, y as usize);
+                let texture_width = blocks(rect.w);
+                let row_width = texture_width.min(level_width - x) * block_bytes;
+                let source = &texture_levels[level];
+                for row in 0..blocks(rect.h).min(level_height - y) {
+                    let texture_row = row * texture_width * block_bytes;
+                    let row = ((y + row) * level_width + x) * block_bytes;
+                    data[row..row + row_width]
+                        .copy_from_slice(&source[texture_row..texture_row + row_width]);
+                }
+            }
+            data
+        })
+        .collect();
 
     PackedTexture {
         width: width.try_into().unwrap(),
         height: height.try_into().unwrap(),
-        data: new_texture_data,
+        format,
+        levels,
         frames: material_indices
             .iter()
             .map(|i| {
-                let frame = packer.get_frame(&(*i as u16)).unwrap();
-                let r = frame.frame;
+                let r = rects[&(*i as u16)];
                 let (width, height) = (width as f32, height as f32);
                 Frame {
                     top_left: (r.left() as f32 / width, r.top() as f32 / height),
@@ -92,14 +171,59 @@ pub fn repack_model_textures(
     }
 }
 
+// The format the packed texture can be kept in: that of its textures, if they're all
+// compressed the same way and can be copied over as they are, or RGBA8 otherwise
+fn packed_format<'a>(
+    texture_data_by_name: &HashMap<String, &txd::Texture>,
+    materials: impl Iterator<Item = &'a dff::Material>,
+) -> PixelFormat {
+    let mut format = None;
+    let mut is_translucent = false;
+    for material in materials {
+        let texture = material
+            .texture
+            .as_ref()
+            .and_then(|t| texture_data_by_name.get(&t.name));
+        let [_, _, _, alpha] = material.color.as_array();
+        match texture {
+            // Tinting needs the texture decoded
+            Some(texture)
+                if !texture.format.is_compressed()
+                    || material.color.as_array() != [255; 4]
+                    || texture.width % 4 != 0
+                    || texture.height % 4 != 0
+                    || format.is_some_and(|f| f != texture.format) =>
+            {
+                return PixelFormat::Rgba8
+            }
+            Some(texture) => format = Some(texture.format),
+            // Untextured materials are compressed to match
+            None => is_translucent |= alpha != 255,
+        }
+    }
+    match format {
+        // DXT1 only has a single bit of alpha
+        Some(PixelFormat::Bc1) if is_translucent => PixelFormat::Rgba8,
+        format => format.unwrap_or(PixelFormat::Rgba8),
+    }
+}
+
+// Returns every level of the material's texture in `format`, alongside the full-size level's
+// width and height. RGBA8 textures are tinted by the material's colour and go down to a single
+// pixel, while compressed ones are left as they are.
 fn material_to_texture_data(
     texture_data_by_name: &HashMap<String, &txd::Texture>,
     material: &dff::Material,
-) -> (Vec<u8>, u32, u32) {
+    format: PixelFormat,
+) -> (Vec<Vec<u8>>, u32, u32) {
     let base_color = material.color;
     if let Some(texture) = &material.texture {
         if let Some(texture) = texture_data_by_name.get(&texture.name) {
-            return texture_to_texture_data(base_color, texture);
+            if format.is_compressed() {
+                let (width, height) = (texture.width as u32, texture.height as u32);
+                return (texture.levels.clone(), width, height);
+            }
+            return texture_to_texture_data(base_color, &texture.decompressed());
         }
     }
 
@@ -111,28 +235,45 @@ fn material_to_texture_data(
         .flatten()
         .collect::<Vec<_>>();
 
-    (data, width, height)
+    let levels = complete_mip_chain(vec![data], width, height)
+        .into_iter()
+        .enumerate()
+        .map(|(level, data)| {
+            let (width, height) = ((width >> level).max(1), (height >> level).max(1));
+            format.compress(&data, width, height)
+        })
+        .collect();
+    (levels, width, height)
 }
 
-fn texture_to_texture_data(base_color: txd::Color, texture: &txd::Texture) -> (Vec<u8>, u32, u32) {
+fn texture_to_texture_data(
+    base_color: txd::Color,
+    texture: &txd::Texture,
+) -> (Vec<Vec<u8>>, u32, u32) {
     let base_color = base_color.as_array().map(remap_u8_to_f32);
     let mut buf: [u8; 4] = [0; 4];
-    let data: Vec<_> = texture
-        .data
-        .chunks_exact(4)
-        .flat_map(|col| {
-            buf.copy_from_slice(col);
-            let tex_color = buf.map(remap_u8_to_f32);
-            [
-                tex_color[0] * base_color[0],
-                tex_color[1] * base_color[1],
-                tex_color[2] * base_color[2],
-                tex_color[3] * base_color[3],
-            ]
-            .map(remap_f32_to_u8)
+    let levels = texture
+        .levels
+        .iter()
+        .map(|level| {
+            level
+                .chunks_exact(4)
+                .flat_map(|col| {
+                    buf.copy_from_slice(col);
+                    let tex_color = buf.map(remap_u8_to_f32);
+                    [
+                        tex_color[0] * base_color[0],
+                        tex_color[1] * base_color[1],
+                        tex_color[2] * base_color[2],
+                        tex_color[3] * base_color[3],
+                    ]
+                    .map(remap_f32_to_u8)
+                })
+                .collect()
         })
         .collect();
-    (data, texture.width as _, texture.height as _)
+    let (width, height) = (texture.width as u32, texture.height as u32);
+    (complete_mip_chain(levels, width, height), width, height)
 }
 
 fn remap_u8_to_f32(c: u8) -> f32 {
@@ -142,3 +283,91 @@ fn remap_u8_to_f32(c: u8) -> f32 {
 fn remap_f32_to_u8(c: f32) -> u8 {
     (c * 255.0) as u8
 }
+
+mod tests {
+    #[test]
+    fn keeps_compressed_textures_compressed() {
+        use crate::{
+            dff::{self, Material},
+            txd::{Color, PixelFormat, Texture, TextureAddressing, TextureFiltering},
+        };
+
+        // An 8x8 texture of a single colour, with its 4x4 mip level, in `format`
+        fn texture(name: &str, color: [u8; 4], format: PixelFormat) -> Texture {
+            let levels = [8, 4]
+                .into_iter()
+                .map(|size| format.compress(&color.repeat(size * size), size as u32, size as u32))
+                .collect();
+            Texture {
+                filtering: TextureFiltering::Linear,
+                uv: (TextureAddressing::Wrap, TextureAddressing::Wrap),
+                name: name.to_string(),
+                mask_name: String::new(),
+                width: 8,
+                height: 8,
+                raster_format: crate::txd::RasterFormat::new(0x0200),
+                format,
+                levels,
+            }
+        }
+        fn material(texture: Option<&str>, color: Color) -> Material {
+            Material {
+                color,
+                is_textured: texture.is_some(),
+                lighting: None,
+                texture: texture.map(|name| dff::Texture {
+                    filtering: TextureFiltering::Linear,
+                    uv: (TextureAddressing::Wrap, TextureAddressing::Wrap),
+                    name: name.to_string(),
+                    alpha_name: String::new(),
+                }),
+                effects: vec![],
+                reflection: None,
+                specular: None,
+            }
+        }
+        let white = Color::new(255, 255, 255, 255);
+        let textures = [
+            texture("red", [255, 0, 0, 255], PixelFormat::Bc1),
+            texture("blue", [0, 0, 255, 255], PixelFormat::Bc1),
+        ];
+        let materials = [
+            material(Some("red"), white),
+            material(Some("blue"), white),
+            material(None, Color::new(0, 255, 0, 255)),
+        ];
+
+        let packed = super::repack_model_textures(&materials, &[0, 1, 2], &textures);
+        assert_eq!(packed.format, PixelFormat::Bc1);
+        assert_eq!(packed.levels.len(), 2);
+        let (width, height) = (packed.width as u32, packed.height as u32);
+        for (level, data) in packed.levels.iter().enumerate() {
+            let (width, height) = (width >> level, height >> level);
+            assert_eq!(data.len(), PixelFormat::Bc1.data_size(width, height));
+
+            let pixels = PixelFormat::Bc1.decompress(data, width, height);
+            for (frame, color) in packed
+                .frames
+                .iter()
+                .zip([[255, 0, 0], [0, 0, 255], [0, 255, 0]])
+            {
+                let x = (frame.top_left.0 * width as f32) as u32;
+                let y = (frame.top_left.1 * height as f32) as u32;
+                let offset = ((y * width + x) * 4) as usize;
+                assert_eq!(pixels[offset..offset + 3], color, "level {}", level);
+            }
+        }
+
+        // Tinting a texture needs it decoded
+        let tinted = [
+            material(Some("red"), Color::new(128, 128, 128, 255)),
+            material(Some("blue"), white),
+        ];
+        let packed = super::repack_model_textures(&tinted, &[0, 1], &textures);
+        assert_eq!(packed.format, PixelFormat::Rgba8);
+        assert_eq!(
+            packed.levels[0].len(),
+            packed.width as usize * packed.height as usize * 4
+        );
+    }
+}
@@ -0,0 +1,105 @@
+use nom::{number::complete as nc, sequence::tuple};
+
+use super::{checked_count, IResult, UnparsedData};
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Mesh {
+    pub material_index: u32,
+    // Kept separately from the indices, as native meshes have a count without any indices
+    pub index_count: u32,
+    pub indices: Vec<u32>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct BinMesh {
+    // Bit 0 is set if each mesh's indices form a triangle strip; see `is_tri_strip`
+    pub flags: u32,
+    pub total_index_count: u32,
+    pub meshes: Vec<Mesh>,
+    // Anything after the meshes
+    pub remainder: UnparsedData,
+}
+impl BinMesh {
+    pub(crate) fn parse(input: &[u8]) -> IResult<&[u8], Self> {
+        let (input, (flags, mesh_count, total_index_count)) =
+            tuple((nc::le_u32, nc::le_u32, nc::le_u32))(input)?;
+
+        // Native geometry (e.g. PS2) keeps its indices in the native data instead,
+        // in which case there's nothing here but the mesh headers.
+        let has_indices =
+            input.len() as u64 >= mesh_count as u64 * 8 + total_index_count as u64 * 4;
+
+        let (input, meshes) = checked_count(
+            |input| {
+                let (input, (index_count, material_index)) =
+                    tuple((nc::le_u32, nc::le_u32))(input)?;
+                let (input, indices) = nom::combinator::cond(
+                    has_indices,
+                    checked_count(nc::le_u32, index_count as usize, 4),
+                )(input)?;
+                Ok((
+                    input,
+                    Mesh {
+                        material_index,
+                        index_count,
+                        indices: indices.unwrap_or_default(),
+                    },
+                ))
+            },
+            mesh_count as usize,
+            8,
+        )(input)?;
+
+        let remainder = UnparsedData(input.to_vec());
+        Ok((
+            &input[input.len()..],
+            BinMesh {
+                flags,
+                total_index_count,
+                meshes,
+                remainder,
+            },
+        ))
+    }
+
+    pub(crate) fn write(&self, out: &mut Vec<u8>) {
+        out.extend(self.flags.to_le_bytes());
+        out.extend((self.meshes.len() as u32).to_le_bytes());
+        out.extend(self.total_index_count.to_le_bytes());
+        for mesh in &self.meshes {
+            out.extend(mesh.index_count.to_le_bytes());
+            out.extend(mesh.material_index.to_le_bytes());
+            for index in &mesh.indices {
+                out.extend(index.to_le_bytes());
+            }
+        }
+        out.extend(&self.remainder.0);
+    }
+
+    /// Whether each mesh's indices form a triangle strip, rather than a triangle list.
+    pub fn is_tri_strip(&self) -> bool {
+        self.flags & 1 != 0
+    }
+}
+
+mod tests {
+    #[test]
+    fn round_trips_native_bin_meshes() {
+        use super::BinMesh;
+
+        // A PS2 mesh split: tri-strip and native flags, then two meshes with counts but no
+        // indices, and a trailing word
+        let words: [u32; 8] = [0x0002_0001, 2, 7, 4, 0, 3, 1, 0xDEAD_BEEF];
+        let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+
+        let (_, bin_mesh) = BinMesh::parse(&bytes).unwrap();
+        assert!(bin_mesh.is_tri_strip());
+        assert!(bin_mesh.meshes.iter().all(|m| m.indices.is_empty()));
+        assert_eq!(bin_mesh.meshes[1].index_count, 3);
+        assert_eq!(bin_mesh.remainder.0, 0xDEAD_BEEFu32.to_le_bytes());
+
+        let mut written = vec![];
+        bin_mesh.write(&mut written);
+        assert_eq!(written, bytes);
+    }
+}
@@ -1,12 +1,14 @@
 use std::{fs::File, io, path::Path};
 
-use super::{Error, Section, UnparsedData};
+use super::{constants::SectionType, Error, LazySections, ParseContext, Section, UnparsedData};
 
 use nom::Finish;
 
 #[derive(Debug, PartialEq)]
 pub struct BinaryStreamFile {
     pub sections: Vec<Section>,
+    // Zeroes after the last section, e.g. from an IMG archive filling out the last sector
+    pub padding: UnparsedData,
 }
 
 impl BinaryStreamFile {
@@ -23,13 +25,272 @@ impl BinaryStreamFile {
     }
 
     pub fn from_bytes(data: &[u8]) -> Result<Self, Error> {
-        // We only support one section for now
-        let (_, section) = Section::parse(data, None)
-            .map_err(|err| err.map_input(|i| UnparsedData(i.to_owned())))
-            .finish()?;
+        let context = ParseContext::new(data);
+
+        let mut input = data;
+        let mut sections = vec![];
+        while !input.iter().all(|b| *b == 0) {
+            let section;
+            (input, section) = Section::parse(input, context, &sections)
+                .finish()
+                .map_err(|err| err.into_error(data))?;
+            sections.push(section);
+        }
 
         Ok(BinaryStreamFile {
-            sections: vec![section],
+            sections,
+            padding: UnparsedData(input.to_vec()),
         })
     }
+
+    /// Walks the sections of `data` without copying it, decoding them only when asked to.
+    pub fn lazy_sections(data: &[u8]) -> LazySections<'_> {
+        LazySections::new(data)
+    }
+
+    pub fn clumps(&self) -> impl Iterator<Item = &Section> {
+        self.sections_by_type(SectionType::Clump)
+    }
+
+    pub fn worlds(&self) -> impl Iterator<Item = &Section> {
+        self.sections_by_type(SectionType::World)
+    }
+
+    pub fn texture_dictionaries(&self) -> impl Iterator<Item = &Section> {
+        self.sections_by_type(SectionType::TextureDictionary)
+    }
+
+    pub fn sections_by_type(&self, section_type: SectionType) -> impl Iterator<Item = &Section> {
+        self.sections
+            .iter()
+            .filter(move |s| s.section_type == section_type)
+    }
+
+    // The error for a file without a `section_type` section, reported at its first section
+    pub(crate) fn missing_section(&self, section_type: SectionType) -> Error {
+        let first = self.sections.first();
+        Error::MissingChild {
+            child: section_type,
+            offset: first.map_or(0, |s| s.offset),
+            path: first
+                .map(|s| format!("{:?}", s.section_type))
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn write<W: io::Write>(&self, mut writer: W) -> Result<(), Error> {
+        writer.write_all(&self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![];
+        for section in &self.sections {
+            section.write(&mut out);
+        }
+        out.extend(&self.padding.0);
+        out
+    }
+}
+
+mod tests {
+    #[test]
+    fn reports_errors_with_offset_and_path() {
+        use crate::test_util::section;
+
+        let bytes = section(0x10, &section(0xDEAD, &[]));
+        match super::BinaryStreamFile::from_bytes(&bytes) {
+            Err(super::Error::UnknownSectionType {
+                section_type,
+                offset,
+                path,
+            }) => {
+                assert_eq!(section_type, 0xDEAD);
+                assert_eq!(offset, 12);
+                assert_eq!(path, "Clump");
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // A geometry list whose struct is too short to hold the geometry count
+        let bytes = section(0x10, &section(0x1A, &section(0x01, &[1, 0])));
+        match super::BinaryStreamFile::from_bytes(&bytes) {
+            Err(super::Error::TruncatedData { offset, path }) => {
+                assert_eq!(offset, 36);
+                assert_eq!(path, "Clump/GeometryList/Struct");
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reports_missing_sections_at_the_first_section() {
+        use crate::raw::constants::SectionType;
+        use crate::test_util::section;
+
+        // A texture dictionary, read as a clump, a world and the dictionary it is
+        let bytes = section(0x16, &section(0x01, &[0, 0, 0, 0]));
+        let file = super::BinaryStreamFile::from_bytes(&bytes).unwrap();
+        for (result, section_type) in [
+            (crate::dff::Clump::from_raw(&file).err(), SectionType::Clump),
+            (
+                crate::world::World::from_raw(&file).err(),
+                SectionType::World,
+            ),
+        ] {
+            match result {
+                Some(super::Error::MissingChild {
+                    child,
+                    offset,
+                    path,
+                }) => {
+                    assert_eq!(child, section_type);
+                    assert_eq!(offset, 0);
+                    assert_eq!(path, "TextureDictionary");
+                }
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+        assert!(crate::txd::Texture::from_raw(&file).unwrap().is_empty());
+    }
+
+    #[test]
+    fn round_trips_sections() {
+        use crate::test_util::section;
+
+        fn material(color: [u8; 4]) -> Vec<u8> {
+            let mut data = vec![0, 0, 0, 0];
+            data.extend(color);
+            data.extend([0xEF, 0xBE, 0xAD, 0xDE, 1, 0, 0, 0]);
+            data.extend([1.0f32, 0.5, 0.25].iter().flat_map(|v| v.to_le_bytes()));
+
+            let mut texture = section(0x01, &[0x06, 0x11, 0x01, 0x00]);
+            texture.extend(section(0x02, b"tex\0"));
+            texture.extend(section(0x02, b"mask\0\0\0\0"));
+            texture.extend(section(0x03, &[]));
+
+            let mut extension = section(0x0253F2FD, &[1, 2, 3, 4, 5]);
+            extension.extend(section(0x0253F2F5, &[6, 7]));
+
+            let mut children = section(0x01, &data);
+            children.extend(section(0x06, &texture));
+            children.extend(section(0x03, &extension));
+            section(0x07, &children)
+        }
+
+        let bytes = material([0xFF, 0x80, 0x40, 0xFF]);
+        let mut file = super::BinaryStreamFile::from_bytes(&bytes).unwrap();
+        assert_eq!(file.to_bytes(), bytes);
+
+        // Editing the colour changes only the colour
+        let material_struct = &mut file.sections[0].children[0];
+        match &mut material_struct.data {
+            crate::raw::ClumpData::Material(material) => {
+                material.color = crate::raw::Color::new(0x10, 0x20, 0x30, 0x40)
+            }
+            other => panic!("unexpected data: {:?}", other),
+        }
+        assert_eq!(file.to_bytes(), material([0x10, 0x20, 0x30, 0x40]));
+    }
+
+    #[test]
+    fn round_trips_what_it_does_not_interpret() {
+        use crate::test_util::{floats, section};
+
+        // A clump struct without light and camera counts, which 3.3 files may leave out
+        let clump = section(0x10, &section(0x01, &[0, 0, 0, 0]));
+
+        // Names with whatever was left in the buffer after the terminator, including bytes that
+        // aren't UTF-8, and a string that isn't padded to a multiple of four bytes
+        let mut texture = section(0x01, &[0x06, 0x11, 0x00, 0x00]);
+        texture.extend(section(0x02, b"tex\0j\xFFnk\0\0"));
+        texture.extend(section(0x03, &[]));
+        let texture = section(0x06, &texture);
+
+        let mut raster = vec![8, 0, 0, 0, 0x06, 0x11, 0, 0];
+        raster.extend(b"white\0\xCD\xCD");
+        raster.extend([0xCD; 24]);
+        raster.extend([0; 32]);
+        raster.extend([0x00, 0x05, 0, 0, 0, 0, 0, 0]);
+        raster.extend([1, 0, 1, 0, 32, 1, 4, 0]);
+        raster.extend([4, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF]);
+        let mut raster_children = section(0x01, &raster);
+        raster_children.extend(section(0x03, &[]));
+        let mut dictionary = section(0x01, &[1, 0, 0, 0]);
+        dictionary.extend(section(0x15, &raster_children));
+        dictionary.extend(section(0x03, &[]));
+        let dictionary = section(0x16, &dictionary);
+
+        // A geometry with a format bit that has no flag, and a morph target whose vertex flag
+        // isn't 1
+        let mut geometry = vec![0x02, 0x02, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0];
+        geometry.extend(floats(&[1.0, 1.0, 1.0]));
+        geometry.extend(floats(&[0.0, 0.0, 0.0, 1.0]));
+        geometry.extend([2, 0, 0, 0, 0, 0, 0, 0]);
+        geometry.extend(floats(&[0.0, 0.0, 0.0]));
+        let mut geometry_list = section(0x01, &[1, 0, 0, 0]);
+        geometry_list.extend(section(0x0F, &section(0x01, &geometry)));
+        let geometry_list = section(0x1A, &geometry_list);
+
+        for bytes in [clump, texture, dictionary, geometry_list] {
+            let file = super::BinaryStreamFile::from_bytes(&bytes).unwrap();
+            assert_eq!(file.to_bytes(), bytes);
+        }
+    }
+
+    #[test]
+    fn parses_every_top_level_section() {
+        use crate::test_util::section;
+
+        // A UV animation dictionary, two clumps and some padding
+        let mut bytes = section(0x2B, &[1, 2, 3, 4]);
+        bytes.extend(section(
+            0x10,
+            &section(0x01, &[1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+        ));
+        bytes.extend(section(
+            0x10,
+            &section(0x01, &[2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+        ));
+        bytes.extend([0; 20]);
+
+        let file = super::BinaryStreamFile::from_bytes(&bytes).unwrap();
+        assert_eq!(file.sections.len(), 3);
+        assert_eq!(file.clumps().count(), 2);
+        assert_eq!(file.texture_dictionaries().count(), 0);
+        assert_eq!(file.padding.0.len(), 20);
+        assert_eq!(file.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn rejects_fuzzing_regressions() {
+        use crate::{dff, txd};
+
+        // Each is rejected by the parser or, if it parses, by the layer above that it targets,
+        // without panicking anywhere along the way
+        let directory = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz/regressions");
+        for entry in std::fs::read_dir(directory).unwrap() {
+            let path = entry.unwrap().path();
+            let bytes = std::fs::read(&path).unwrap();
+            let raw = match super::BinaryStreamFile::from_bytes(&bytes) {
+                Ok(raw) => raw,
+                Err(_) => continue,
+            };
+
+            let textures = [
+                txd::Texture::from_raw(&raw).is_err(),
+                txd::Texture::from_raw_compressed(&raw).is_err(),
+            ];
+            let models = [
+                dff::Clump::from_raw(&raw).is_err(),
+                dff::Model::from_raw(&raw).is_err(),
+            ];
+            let rejected = if raw.texture_dictionaries().next().is_some() {
+                textures
+            } else {
+                models
+            };
+            assert_eq!(rejected, [true; 2], "{:?} should fail to load", path);
+        }
+    }
 }
@@ -1,20 +1,28 @@
-use nom::{
-    bits::complete as bic, combinator::cond, number::complete as nc, sequence::tuple, IResult,
-};
+use nom::{combinator::cond, number::complete as nc, sequence::tuple};
 use num_traits::FromPrimitive;
 
-use super::{constants::*, Color, Frame, GeometryData, Lighting, MorphTarget, UnparsedData};
+use super::{
+    checked_count, constants::*, split_geometry_format, texture_sets_present, AtomicSection,
+    BinMesh, Color, ExtraVertColour, Frame, GeometryData, HAnim, IResult, Lighting,
+    MaterialEffects, MorphTarget, ParseContext, ParseError, ParseErrorKind, PlaneSection,
+    Ps2Raster, Ps2RasterHeader, Ps2TextureNative, RwString, RwVersion, Skin, UnparsedData, World,
+};
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct Texture {
     pub filtering: TextureFiltering,
     pub uv: (TextureAddressing, TextureAddressing),
     pub mipmaps_used: bool,
+    // Any other bits of the filtering and addressing flags, left in place
+    pub flags: u32,
 }
 
 #[derive(Debug, PartialEq)]
 pub struct Material {
+    // Unused by RenderWare, but kept so that the material can be written back as-is
+    pub flags: u32,
     pub color: Color,
+    pub unused: u32,
     pub is_textured: bool,
     pub lighting: Option<Lighting>,
 }
@@ -22,6 +30,10 @@ pub struct Material {
 #[derive(Debug, PartialEq)]
 pub struct Geometry {
     pub format: GeometryFormat,
+    // The counts as stored in the header; these must agree with `data` and `morph_targets`
+    pub texture_set_count: u8,
+    pub triangle_count: u32,
+    pub vertex_count: u32,
     pub lighting: Option<Lighting>,
     pub data: Option<GeometryData>,
     pub morph_targets: Vec<MorphTarget>,
@@ -30,8 +42,8 @@ pub struct Geometry {
 #[derive(Debug, PartialEq, Eq)]
 pub struct Clump {
     pub atomic_count: u32,
-    pub light_count: u32,
-    pub camera_count: u32,
+    // The light and camera counts, which files before 3.3 (and some after) leave out
+    pub light_and_camera_counts: Option<(u32, u32)>,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -40,15 +52,61 @@ pub struct Atomic {
     pub geometry_index: u32,
     // Render if in view frustum
     pub render: bool,
+    // The remaining flags, which aren't used by GTA
+    pub flags: u32,
+    pub unused: u32,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+// San Andreas' vehicle reflections
+#[derive(Debug, PartialEq)]
+pub struct ReflectionMaterial {
+    // Applied to the environment map's coordinates
+    pub scale: (f32, f32),
+    pub offset: (f32, f32),
+    pub intensity: f32,
+    // Where the game keeps a pointer to the environment texture at runtime
+    pub unused: u32,
+}
+
+// San Andreas' vehicle specular highlights
+#[derive(Debug, PartialEq)]
+pub struct SpecularMaterial {
+    pub level: f32,
+    pub texture_name: RwString,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Light {
+    pub radius: f32,
+    pub color: [f32; 3],
+    // The negated cosine of the cone's angle, from the centre to its edge; files older than
+    // 3.3 store the angle's tangent instead
+    pub minus_cos_angle: f32,
+    // Whether the light affects atomics (0x01) and/or world sectors (0x02)
+    pub flags: u16,
+    pub light_type: LightType,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Camera {
+    // Half the width and height of the view window, one unit in front of the camera
+    pub view_window: (f32, f32),
+    pub view_offset: (f32, f32),
+    pub near_plane: f32,
+    pub far_plane: f32,
+    pub fog_plane: f32,
+    pub projection: CameraProjection,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Raster {
     pub filtering: TextureFiltering,
     pub uv: (TextureAddressing, TextureAddressing),
+    // Any other bits of the filtering and addressing flags, left in place
+    pub flags: u32,
 
-    pub name: String,
-    pub mask_name: String,
+    pub name: RwString,
+    pub mask_name: RwString,
 
     pub raster_format: RasterFormat,
     pub has_alpha: bool,
@@ -60,7 +118,63 @@ pub struct Raster {
     pub raster_type: u8,
     pub compression: u8,
 
-    pub data: Vec<u8>,
+    // The colours of a paletted raster, which its data indexes into. Direct3D keeps 32
+    // entries for 4-bit palettes, of which only the first 16 are used.
+    pub palette: Vec<Color>,
+    // The full-size level, followed by each smaller mip level
+    pub levels: Vec<Vec<u8>>,
+    // Anything after the levels, as stored
+    pub remainder: UnparsedData,
+
+    pub platform: RasterPlatform,
+}
+
+// The platform a raster was built for, which decides how its levels are laid out, along
+// with anything that only that platform keeps
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum RasterPlatform {
+    D3d8,
+    // Only used by San Andreas
+    D3d9(D3d9Raster),
+    Xbox(XboxRaster),
+    // PlayStation 2 rasters are spread across several sections, and only gathered into a
+    // raster by `Raster::from_ps2_section`
+    Ps2(Ps2Raster),
+}
+impl RasterPlatform {
+    pub const XBOX: u32 = 5;
+    pub const PS2: u32 = 6;
+    // `PS2\0`, which is what the games' files use
+    pub const PS2_FOURCC: u32 = 0x0032_5350;
+}
+
+// Direct3D 9 rasters (as used by San Andreas) describe their format with a D3DFORMAT, and
+// keep flags where Direct3D 8 rasters keep the compression
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct D3d9Raster {
+    // A D3DFORMAT, or a FourCC such as `DXT1` for compressed formats
+    pub format: u32,
+    pub cube_texture: bool,
+    pub auto_mipmaps: bool,
+}
+
+// Xbox rasters store their levels back to back, swizzled unless they're compressed, with
+// DXT1 to DXT5 numbered from 0x0C to 0x10 in place of Direct3D 8's compression
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct XboxRaster {
+    pub cube_texture: bool,
+    // Anything after the levels that's counted in their size
+    pub padding: UnparsedData,
+}
+
+// The size of a level of an Xbox raster
+fn xbox_level_size(width: u32, height: u32, depth: u8, compression: u8) -> usize {
+    let blocks = || (width.div_ceil(4) * height.div_ceil(4)) as usize;
+    match compression {
+        0x0C => blocks() * 8,
+        0x0D..=0x10 => blocks() * 16,
+        _ => (width * height * depth as u32).div_ceil(8) as usize,
+    }
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -72,59 +186,78 @@ pub struct TextureDictionary {
 #[derive(Debug, PartialEq)]
 pub enum ClumpData {
     Struct(UnparsedData),
-    String(String),
+    String(RwString),
     Texture(Texture),
     Material(Material),
-    MaterialList { material_indices: Vec<i32> },
+    MaterialList {
+        material_indices: Vec<i32>,
+    },
     FrameList(Vec<Frame>),
     Geometry(Geometry),
     Clump(Clump),
     Atomic(Atomic),
+    // The frame of the light or camera that follows this struct within a clump
+    FrameIndex(u32),
+    Light(Light),
+    Camera(Camera),
     Raster(Raster),
+    Ps2TextureNative(Ps2TextureNative),
+    Ps2RasterHeader(Ps2RasterHeader),
     TextureDictionary(TextureDictionary),
-    GeometryList { geometry_count: u32 },
+    World(World),
+    PlaneSection(PlaneSection),
+    AtomicSection(AtomicSection),
+    GeometryList {
+        geometry_count: u32,
+    },
     NodeName(String),
+    BinMesh(BinMesh),
+    Skin(Skin),
+    HAnim(HAnim),
+    ExtraVertColour(ExtraVertColour),
+    MaterialEffects(MaterialEffects),
+    #[cfg(feature = "san_andreas_support")]
+    PipelineSet {
+        pipeline_id: u32,
+    },
+    #[cfg(feature = "san_andreas_support")]
+    Effect2d(Vec<super::Effect2d>),
+    ReflectionMaterial(ReflectionMaterial),
+    SpecularMaterial(SpecularMaterial),
+    // The payload of a section we don't understand, kept so that it can be written back
+    Unparsed(UnparsedData),
+    // A section whose payload is entirely made up of its children
     Unknown,
 }
 impl ClumpData {
     fn parse_texture(input: &[u8]) -> IResult<&[u8], Self> {
-        let (input, filtering) =
-            nom::combinator::map(nc::le_u8, |v| FromPrimitive::from_u8(v).unwrap())(input)?;
-
-        let (input, (u, v, mipmaps_used, _padding)): (_, (_, _, u8, u16)) =
-            nom::bits::bits::<_, _, nom::error::Error<(&[u8], usize)>, _, _>(tuple((
-                bic::take(4usize),
-                bic::take(4usize),
-                bic::take(1usize),
-                bic::take(15usize),
-            )))(input)?;
-
-        let uv = (
-            FromPrimitive::from_u8(u).unwrap(),
-            FromPrimitive::from_u8(v).unwrap(),
-        );
-        let mipmaps_used = mipmaps_used > 0;
-
+        let (input, (filtering, uv, flags)) = parse_filter_addressing(input)?;
         Ok((
             input,
             ClumpData::Texture(Texture {
                 filtering,
                 uv,
-                mipmaps_used,
+                mipmaps_used: flags & 0x1_0000 != 0,
+                flags: flags & !0x1_0000,
             }),
         ))
     }
 
-    fn parse_material(input: &[u8], version: u32) -> IResult<&[u8], Self> {
-        let (input, _flags) = nc::le_u32(input)?;
+    fn parse_material(input: &[u8], version: RwVersion) -> IResult<&[u8], Self> {
+        let (input, flags) = nc::le_u32(input)?;
         let (input, color) = Color::parse(input)?;
-        let (input, _unused) = nc::le_u32(input)?;
+        let (input, unused) = nc::le_u32(input)?;
         let (input, is_textured) = nom::combinator::map(nc::le_u32, |v| v > 0)(input)?;
-        let (input, lighting) = nom::combinator::cond(version > 0x3_0400, Lighting::parse)(input)?;
+        let (input, lighting) = nom::combinator::cond(
+            version.material_has_surface_properties(),
+            Lighting::parse,
+        )(input)?;
         Ok((
             input,
             ClumpData::Material(Material {
+                flags,
                 color,
+                unused,
                 is_textured,
                 lighting,
             }),
@@ -134,44 +267,52 @@ impl ClumpData {
     fn parse_material_list(input: &[u8]) -> IResult<&[u8], Self> {
         let (input, material_count) = nc::le_u32(input)?;
         let (input, material_indices) =
-            nom::multi::count(nc::le_i32, material_count as usize)(input)?;
+            checked_count(nc::le_i32, material_count as usize, 4)(input)?;
         Ok((input, ClumpData::MaterialList { material_indices }))
     }
 
     fn parse_frame_list(input: &[u8]) -> IResult<&[u8], Self> {
         let (input, frame_count) = nc::le_u32(input)?;
-        let (input, frames) = nom::multi::count(Frame::parse, frame_count as usize)(input)?;
+        let (input, frames) = checked_count(Frame::parse, frame_count as usize, 56)(input)?;
         Ok((input, ClumpData::FrameList(frames)))
     }
 
-    fn parse_geometry(input: &[u8], version: u32) -> IResult<&[u8], Self> {
+    fn parse_geometry(input: &[u8], version: RwVersion) -> IResult<&[u8], Self> {
         let (input, (format, triangle_count, vertices_count, morph_target_count)) =
             tuple((nc::le_u32, nc::le_u32, nc::le_u32, nc::le_u32))(input)?;
 
-        let texture_set_count = ((format & 0x00FF0000) >> 16) as u16;
-        let format = GeometryFormat::from_bits(format & 0x0000_FFFF).unwrap();
+        let (format, texture_set_count) = split_geometry_format(format);
+        let texture_sets_present = texture_sets_present(format, texture_set_count);
 
-        let (input, lighting) = nom::combinator::cond(version < 0x3_4000, Lighting::parse)(input)?;
+        let (input, lighting) = nom::combinator::cond(
+            version.geometry_has_surface_properties(),
+            Lighting::parse,
+        )(input)?;
         let (input, data) =
             nom::combinator::cond(!format.contains(GeometryFormat::NATIVE), |input| {
                 GeometryData::parse(
                     input,
                     format.contains(GeometryFormat::PRELIT),
-                    texture_set_count,
+                    texture_sets_present,
                     vertices_count,
                     triangle_count,
                 )
             })(input)?;
 
-        let (input, morph_targets) = nom::multi::count(
+        let (input, morph_targets) = checked_count(
             |data| MorphTarget::parse(data, vertices_count),
             morph_target_count as usize,
+            // A bounding sphere and the vertex and normal flags
+            24,
         )(input)?;
 
         Ok((
             input,
             ClumpData::Geometry(Geometry {
                 format,
+                texture_set_count,
+                triangle_count,
+                vertex_count: vertices_count,
                 lighting,
                 data,
                 morph_targets,
@@ -179,26 +320,74 @@ impl ClumpData {
         ))
     }
 
-    fn parse_clump(input: &[u8], version: u32) -> IResult<&[u8], Self> {
+    fn parse_clump(input: &[u8], version: RwVersion) -> IResult<&[u8], Self> {
         let (input, atomic_count) = nc::le_u32(input)?;
-        let (input, extra_counts) = cond(
-            version > 0x3_3000 && input.len() > 4,
+        let (input, light_and_camera_counts) = cond(
+            version.clump_has_lights_and_cameras() && input.len() > 4,
             tuple((nc::le_u32, nc::le_u32)),
         )(input)?;
-        let (light_count, camera_count) = extra_counts.unwrap_or_default();
 
         Ok((
             input,
             ClumpData::Clump(Clump {
                 atomic_count,
-                light_count,
-                camera_count,
+                light_and_camera_counts,
+            }),
+        ))
+    }
+
+    pub(crate) fn parse_frame_index(input: &[u8]) -> IResult<&[u8], Self> {
+        let (input, frame_index) = nc::le_u32(input)?;
+        Ok((input, ClumpData::FrameIndex(frame_index)))
+    }
+
+    fn parse_light(input: &[u8]) -> IResult<&[u8], Self> {
+        let (input, (radius, red, green, blue, minus_cos_angle, flags, light_type)) =
+            tuple((
+                nc::le_f32,
+                nc::le_f32,
+                nc::le_f32,
+                nc::le_f32,
+                nc::le_f32,
+                nc::le_u16,
+                nc::le_u16,
+            ))(input)?;
+
+        Ok((
+            input,
+            ClumpData::Light(Light {
+                radius,
+                color: [red, green, blue],
+                minus_cos_angle,
+                flags,
+                light_type: LightType::new(light_type),
+            }),
+        ))
+    }
+
+    fn parse_camera(input: &[u8]) -> IResult<&[u8], Self> {
+        let (input, (view_window, view_offset)) = tuple((
+            tuple((nc::le_f32, nc::le_f32)),
+            tuple((nc::le_f32, nc::le_f32)),
+        ))(input)?;
+        let (input, (near_plane, far_plane, fog_plane, projection)) =
+            tuple((nc::le_f32, nc::le_f32, nc::le_f32, nc::le_u32))(input)?;
+
+        Ok((
+            input,
+            ClumpData::Camera(Camera {
+                view_window,
+                view_offset,
+                near_plane,
+                far_plane,
+                fog_plane,
+                projection: CameraProjection::new(projection),
             }),
         ))
     }
 
     fn parse_atomic(input: &[u8]) -> IResult<&[u8], Self> {
-        let (input, (frame_index, geometry_index, flags, _unused)) =
+        let (input, (frame_index, geometry_index, flags, unused)) =
             tuple((nc::le_u32, nc::le_u32, nc::le_u32, nc::le_u32))(input)?;
 
         // 0x01 rpATOMICCOLLISIONTEST - A generic collision flag to indicate that the atomic should be considered in collision tests.
@@ -212,56 +401,148 @@ impl ClumpData {
                 frame_index,
                 geometry_index,
                 render,
+                flags: flags & !0x04,
+                unused,
             }),
         ))
     }
 
     fn parse_raster(input: &[u8]) -> IResult<&[u8], Self> {
+        let start = input;
         let (input, platform_id) = nc::le_u32(input)?;
-        // We don't support anything else right now
-        assert_eq!(platform_id, 8);
-
-        let (input, (filtering, u, v, _padding)): (_, (_, _, u8, u16)) =
-            nom::bits::bits::<_, _, nom::error::Error<(&[u8], usize)>, _, _>(tuple((
-                bic::take(8usize),
-                bic::take(4usize),
-                bic::take(4usize),
-                bic::take(16usize),
-            )))(input)?;
-        let filtering = FromPrimitive::from_u8(filtering).unwrap();
-        let uv = (
-            FromPrimitive::from_u8(u).unwrap(),
-            FromPrimitive::from_u8(v).unwrap(),
-        );
-
-        let (input, name) = parse_null_terminated_ascii(input, 32)?;
-        let (input, mask_name) = parse_null_terminated_ascii(input, 32)?;
+        let is_supported = match platform_id {
+            8 | RasterPlatform::XBOX => true,
+            #[cfg(feature = "san_andreas_support")]
+            9 => true,
+            // Only the platform and sampling are in this struct; the rest follows in sections
+            // of their own
+            RasterPlatform::PS2 | RasterPlatform::PS2_FOURCC => {
+                let (input, (filtering, uv, flags)) = parse_filter_addressing(input)?;
+                return Ok((
+                    input,
+                    ClumpData::Ps2TextureNative(Ps2TextureNative {
+                        platform_id,
+                        filtering,
+                        uv,
+                        flags,
+                    }),
+                ));
+            }
+            _ => false,
+        };
+        if !is_supported {
+            return Err(nom::Err::Failure(ParseError::new(
+                start,
+                ParseErrorKind::UnsupportedPlatform(platform_id),
+            )));
+        }
+
+        let (input, (filtering, uv, flags)) = parse_filter_addressing(input)?;
+
+        let (input, name) = parse_fixed_string(input, 32)?;
+        let (input, mask_name) = parse_fixed_string(input, 32)?;
 
         let (input, raster_format) = nc::le_u32(input)?;
         let raster_format = RasterFormat::new(raster_format);
 
-        let (input, has_alpha) = nc::le_u32(input)?;
-        let has_alpha = has_alpha > 0;
+        // Direct3D 8 stores whether there's alpha here, Direct3D 9 the format, and the Xbox
+        // whether there's alpha and whether it's a cube texture in a half each
+        let (input, alpha_or_format) = nc::le_u32(input)?;
 
         let (input, width) = nc::le_u16(input)?;
         let (input, height) = nc::le_u16(input)?;
         let (input, depth) = nc::le_u8(input)?;
         let (input, level_count) = nc::le_u8(input)?;
         let (input, raster_type) = nc::le_u8(input)?;
-        let (input, compression) = nc::le_u8(input)?;
+        let (input, compression_or_flags) = nc::le_u8(input)?;
+
+        let (has_alpha, compression, platform) = match platform_id {
+            9 => {
+                let flags = compression_or_flags;
+                let compression = match &alpha_or_format.to_le_bytes() {
+                    _ if flags & 0x08 == 0 => 0,
+                    b"DXT1" => 1,
+                    b"DXT3" => 3,
+                    b"DXT5" => 5,
+                    _ => 0,
+                };
+                let d3d9 = D3d9Raster {
+                    format: alpha_or_format,
+                    cube_texture: flags & 0x02 != 0,
+                    auto_mipmaps: flags & 0x04 != 0,
+                };
+                (flags & 0x01 != 0, compression, RasterPlatform::D3d9(d3d9))
+            }
+            RasterPlatform::XBOX => {
+                let xbox = XboxRaster {
+                    cube_texture: alpha_or_format >> 16 != 0,
+                    padding: UnparsedData(vec![]),
+                };
+                let has_alpha = alpha_or_format & 0xFFFF != 0;
+                (has_alpha, compression_or_flags, RasterPlatform::Xbox(xbox))
+            }
+            _ => (
+                alpha_or_format > 0,
+                compression_or_flags,
+                RasterPlatform::D3d8,
+            ),
+        };
+
+        // The Xbox gives the size of all of the levels together, ahead of the palette
+        let (input, xbox_size) =
+            cond(matches!(platform, RasterPlatform::Xbox(_)), nc::le_u32)(input)?;
+
+        let palette_size = match raster_format.palette_color_count() {
+            16 => 32,
+            count => count as usize,
+        };
+        let (input, palette) = checked_count(Color::parse, palette_size, 4)(input)?;
 
-        let (input, raster_size) = nc::le_u32(input)?;
-        let (input, raster_data) = nom::bytes::complete::take(raster_size)(input)?;
-        let data = raster_data.to_vec();
+        let (input, levels, platform) = match (xbox_size, platform) {
+            (Some(size), RasterPlatform::Xbox(mut xbox)) => {
+                let (input, data) = nom::bytes::complete::take(size)(input)?;
+                let mut data = data;
+                let mut levels = vec![];
+                for level in 0..level_count.max(1) as u32 {
+                    let size = |size: u16| (size as u32 >> level).max(1);
+                    let level_size = xbox_level_size(size(width), size(height), depth, compression);
+                    if data.len() < level_size {
+                        break;
+                    }
+                    levels.push(data[..level_size].to_vec());
+                    data = &data[level_size..];
+                }
+                // Anything that isn't a whole level is kept for writing; with no whole
+                // levels at all, it's left as the first level to be found wanting later
+                if levels.is_empty() {
+                    levels.push(std::mem::take(&mut data).to_vec());
+                }
+                xbox.padding = UnparsedData(data.to_vec());
+                (input, levels, RasterPlatform::Xbox(xbox))
+            }
+            (_, platform) => {
+                // Each level is preceded by its size; there's always at least one
+                fn parse_level(input: &[u8]) -> IResult<&[u8], Vec<u8>> {
+                    let (input, size) = nc::le_u32(input)?;
+                    let (input, data) = nom::bytes::complete::take(size)(input)?;
+                    Ok((input, data.to_vec()))
+                }
+                let (input, levels) =
+                    checked_count(parse_level, level_count.max(1) as usize, 4)(input)?;
+                (input, levels, platform)
+            }
+        };
 
-        // hack(philpax): assume that the input is exhausted, we don't care for the mipmaps
-        let input = &input[0..0];
+        // Nothing should follow the levels, but keep whatever does around for writing
+        let remainder = UnparsedData(input.to_vec());
+        let input = &input[input.len()..];
 
         Ok((
             input,
             ClumpData::Raster(Raster {
                 filtering,
                 uv,
+                flags,
 
                 name,
                 mask_name,
@@ -276,13 +557,17 @@ impl ClumpData {
                 raster_type,
                 compression,
 
-                data,
+                palette,
+                levels,
+                remainder,
+
+                platform,
             }),
         ))
     }
 
-    fn parse_texture_dictionary(input: &[u8], version: u32) -> IResult<&[u8], Self> {
-        if version < 0x3_6000 {
+    fn parse_texture_dictionary(input: &[u8], version: RwVersion) -> IResult<&[u8], Self> {
+        if !version.texture_dictionary_has_device_id() {
             let (input, texture_count) = nc::le_u32(input)?;
             Ok((
                 input,
@@ -308,6 +593,11 @@ impl ClumpData {
         Ok((input, ClumpData::GeometryList { geometry_count }))
     }
 
+    pub(crate) fn parse_atomic_section(input: &[u8], world_format: u32) -> IResult<&[u8], Self> {
+        let (input, sector) = AtomicSection::parse(input, world_format)?;
+        Ok((input, ClumpData::AtomicSection(sector)))
+    }
+
     pub(crate) const SUPPORTED_TYPES: &'static [SectionType] = &[
         SectionType::Texture,
         SectionType::Material,
@@ -316,32 +606,129 @@ impl ClumpData {
         SectionType::Geometry,
         SectionType::Clump,
         SectionType::Atomic,
+        SectionType::Light,
+        SectionType::Camera,
         SectionType::Raster,
         SectionType::TextureDictionary,
         SectionType::GeometryList,
+        SectionType::World,
+        SectionType::PlaneSection,
+        SectionType::AtomicSection,
+        SectionType::Extension,
     ];
     pub(crate) fn parse_struct(
         input: &[u8],
-        parent_type: SectionType,
-        version: u32,
+        parent_type: Option<SectionType>,
+        version: RwVersion,
     ) -> IResult<&[u8], Self> {
         Ok(match parent_type {
-            SectionType::Texture => Self::parse_texture(input)?,
-            SectionType::Material => Self::parse_material(input, version)?,
-            SectionType::MaterialList => Self::parse_material_list(input)?,
-            SectionType::FrameList => Self::parse_frame_list(input)?,
-            SectionType::Geometry => Self::parse_geometry(input, version)?,
-            SectionType::Clump => Self::parse_clump(input, version)?,
-            SectionType::Atomic => Self::parse_atomic(input)?,
-            SectionType::Raster => Self::parse_raster(input)?,
-            SectionType::TextureDictionary => Self::parse_texture_dictionary(input, version)?,
-            SectionType::GeometryList => Self::parse_geometry_list(input)?,
+            Some(SectionType::Texture) => Self::parse_texture(input)?,
+            Some(SectionType::Material) => Self::parse_material(input, version)?,
+            Some(SectionType::MaterialList) => Self::parse_material_list(input)?,
+            Some(SectionType::FrameList) => Self::parse_frame_list(input)?,
+            Some(SectionType::Geometry) => Self::parse_geometry(input, version)?,
+            Some(SectionType::Clump) => Self::parse_clump(input, version)?,
+            Some(SectionType::Atomic) => Self::parse_atomic(input)?,
+            Some(SectionType::Light) => Self::parse_light(input)?,
+            Some(SectionType::Camera) => Self::parse_camera(input)?,
+            Some(SectionType::Raster) => Self::parse_raster(input)?,
+            Some(SectionType::TextureDictionary) => Self::parse_texture_dictionary(input, version)?,
+            Some(SectionType::GeometryList) => Self::parse_geometry_list(input)?,
+            Some(SectionType::World) => {
+                let (input, world) = World::parse(input, version)?;
+                (input, ClumpData::World(world))
+            }
+            Some(SectionType::PlaneSection) => {
+                let (input, plane) = PlaneSection::parse(input)?;
+                (input, ClumpData::PlaneSection(plane))
+            }
             _ => (&[], ClumpData::Struct(super::UnparsedData(input.to_vec()))),
         })
     }
 
+    pub(crate) fn parse_ps2_raster_header(input: &[u8]) -> IResult<&[u8], Self> {
+        let (input, header) = Ps2RasterHeader::parse(input)?;
+        Ok((input, ClumpData::Ps2RasterHeader(header)))
+    }
+
     pub(crate) fn parse_string(input: &[u8]) -> IResult<&[u8], Self> {
-        Ok((&[], ClumpData::String(null_terminated_ascii(input))))
+        Ok((&[], ClumpData::String(RwString(input.to_vec()))))
+    }
+
+    pub(crate) fn parse_bin_mesh(input: &[u8]) -> IResult<&[u8], Self> {
+        let (input, bin_mesh) = BinMesh::parse(input)?;
+        Ok((input, ClumpData::BinMesh(bin_mesh)))
+    }
+
+    pub(crate) fn parse_skin(
+        input: &[u8],
+        version: RwVersion,
+        vertex_count: u32,
+    ) -> IResult<&[u8], Self> {
+        let (_, skin) = Skin::parse(input, version, vertex_count)?;
+        Ok((&[], ClumpData::Skin(skin)))
+    }
+
+    pub(crate) fn parse_extra_vert_colour(input: &[u8], vertex_count: u32) -> IResult<&[u8], Self> {
+        let (_, extra_vert_colour) = ExtraVertColour::parse(input, vertex_count)?;
+        Ok((&[], ClumpData::ExtraVertColour(extra_vert_colour)))
+    }
+
+    pub(crate) fn parse_material_effects<'a>(
+        input: &'a [u8],
+        context: ParseContext<'a>,
+    ) -> IResult<&'a [u8], Self> {
+        let (input, effects) = MaterialEffects::parse(input, context)?;
+        Ok((input, ClumpData::MaterialEffects(effects)))
+    }
+
+    pub(crate) fn parse_hanim(input: &[u8]) -> IResult<&[u8], Self> {
+        let (input, hanim) = HAnim::parse(input)?;
+        Ok((input, ClumpData::HAnim(hanim)))
+    }
+
+    #[cfg(feature = "san_andreas_support")]
+    pub(crate) fn parse_pipeline_set(input: &[u8]) -> IResult<&[u8], Self> {
+        let (input, pipeline_id) = nc::le_u32(input)?;
+        Ok((input, ClumpData::PipelineSet { pipeline_id }))
+    }
+
+    #[cfg(feature = "san_andreas_support")]
+    pub(crate) fn parse_effect_2d(input: &[u8]) -> IResult<&[u8], Self> {
+        let (input, effects) = super::Effect2d::parse_list(input)?;
+        Ok((input, ClumpData::Effect2d(effects)))
+    }
+
+    pub(crate) fn parse_reflection_material(input: &[u8]) -> IResult<&[u8], Self> {
+        let (input, (scale_x, scale_y, offset_x, offset_y, intensity, unused)) = tuple((
+            nc::le_f32,
+            nc::le_f32,
+            nc::le_f32,
+            nc::le_f32,
+            nc::le_f32,
+            nc::le_u32,
+        ))(input)?;
+        Ok((
+            input,
+            ClumpData::ReflectionMaterial(ReflectionMaterial {
+                scale: (scale_x, scale_y),
+                offset: (offset_x, offset_y),
+                intensity,
+                unused,
+            }),
+        ))
+    }
+
+    pub(crate) fn parse_specular_material(input: &[u8]) -> IResult<&[u8], Self> {
+        let (input, level) = nc::le_f32(input)?;
+        let (input, texture_name) = parse_fixed_string(input, 24)?;
+        Ok((
+            input,
+            ClumpData::SpecularMaterial(SpecularMaterial {
+                level,
+                texture_name,
+            }),
+        ))
     }
 
     pub(crate) fn parse_node_name(input: &[u8]) -> IResult<&[u8], Self> {
@@ -350,15 +737,392 @@ impl ClumpData {
             ClumpData::NodeName(String::from_utf8_lossy(input).to_string()),
         ))
     }
+
+    // Writes the payload of the section, not including its children
+    pub(crate) fn write(&self, out: &mut Vec<u8>, version: RwVersion) {
+        match self {
+            ClumpData::Struct(data) | ClumpData::Unparsed(data) => out.extend(&data.0),
+            ClumpData::String(string) => out.extend(&string.0),
+            ClumpData::Texture(texture) => write_filter_addressing(
+                out,
+                texture.filtering,
+                texture.uv,
+                texture.flags | if texture.mipmaps_used { 0x1_0000 } else { 0 },
+            ),
+            ClumpData::Material(material) => {
+                out.extend(material.flags.to_le_bytes());
+                material.color.write(out);
+                out.extend(material.unused.to_le_bytes());
+                out.extend((material.is_textured as u32).to_le_bytes());
+                if let Some(lighting) = &material.lighting {
+                    lighting.write(out);
+                }
+            }
+            ClumpData::MaterialList { material_indices } => {
+                out.extend((material_indices.len() as u32).to_le_bytes());
+                for index in material_indices {
+                    out.extend(index.to_le_bytes());
+                }
+            }
+            ClumpData::FrameList(frames) => {
+                out.extend((frames.len() as u32).to_le_bytes());
+                for frame in frames {
+                    frame.write(out);
+                }
+            }
+            ClumpData::Geometry(geometry) => {
+                let format = geometry.format.bits() | (geometry.texture_set_count as u32) << 16;
+                out.extend(format.to_le_bytes());
+                out.extend(geometry.triangle_count.to_le_bytes());
+                out.extend(geometry.vertex_count.to_le_bytes());
+                out.extend((geometry.morph_targets.len() as u32).to_le_bytes());
+                if let Some(lighting) = &geometry.lighting {
+                    lighting.write(out);
+                }
+                if let Some(data) = &geometry.data {
+                    data.write(out);
+                }
+                for morph_target in &geometry.morph_targets {
+                    morph_target.write(out);
+                }
+            }
+            ClumpData::Clump(clump) => {
+                out.extend(clump.atomic_count.to_le_bytes());
+                if let Some((light_count, camera_count)) = clump.light_and_camera_counts {
+                    out.extend(light_count.to_le_bytes());
+                    out.extend(camera_count.to_le_bytes());
+                }
+            }
+            ClumpData::FrameIndex(frame_index) => out.extend(frame_index.to_le_bytes()),
+            ClumpData::Light(light) => {
+                let [red, green, blue] = light.color;
+                for value in [light.radius, red, green, blue, light.minus_cos_angle] {
+                    out.extend(value.to_le_bytes());
+                }
+                out.extend(light.flags.to_le_bytes());
+                out.extend(light.light_type.value().to_le_bytes());
+            }
+            ClumpData::Camera(camera) => {
+                for value in [
+                    camera.view_window.0,
+                    camera.view_window.1,
+                    camera.view_offset.0,
+                    camera.view_offset.1,
+                    camera.near_plane,
+                    camera.far_plane,
+                    camera.fog_plane,
+                ] {
+                    out.extend(value.to_le_bytes());
+                }
+                out.extend(camera.projection.value().to_le_bytes());
+            }
+            ClumpData::Atomic(atomic) => {
+                let flags = atomic.flags | if atomic.render { 0x04 } else { 0 };
+                for value in [
+                    atomic.frame_index,
+                    atomic.geometry_index,
+                    flags,
+                    atomic.unused,
+                ] {
+                    out.extend(value.to_le_bytes());
+                }
+            }
+            ClumpData::Raster(raster) => {
+                // PlayStation 2 rasters are written as the sections they were gathered from
+                let platform_id: u32 = match raster.platform {
+                    RasterPlatform::D3d8 | RasterPlatform::Ps2(_) => 8,
+                    RasterPlatform::D3d9(_) => 9,
+                    RasterPlatform::Xbox(_) => RasterPlatform::XBOX,
+                };
+                out.extend(platform_id.to_le_bytes());
+                write_filter_addressing(out, raster.filtering, raster.uv, raster.flags);
+                raster.name.write_fixed(out, 32);
+                raster.mask_name.write_fixed(out, 32);
+                out.extend(raster.raster_format.bits().to_le_bytes());
+                let (alpha_or_format, compression_or_flags) = match &raster.platform {
+                    RasterPlatform::D3d9(d3d9) => (
+                        d3d9.format,
+                        raster.has_alpha as u8
+                            | (d3d9.cube_texture as u8) << 1
+                            | (d3d9.auto_mipmaps as u8) << 2
+                            | ((raster.compression != 0) as u8) << 3,
+                    ),
+                    RasterPlatform::Xbox(xbox) => (
+                        raster.has_alpha as u32 | (xbox.cube_texture as u32) << 16,
+                        raster.compression,
+                    ),
+                    _ => (raster.has_alpha as u32, raster.compression),
+                };
+                out.extend(alpha_or_format.to_le_bytes());
+                out.extend(raster.width.to_le_bytes());
+                out.extend(raster.height.to_le_bytes());
+                out.extend([
+                    raster.depth,
+                    raster.level_count,
+                    raster.raster_type,
+                    compression_or_flags,
+                ]);
+                match &raster.platform {
+                    RasterPlatform::Xbox(xbox) => {
+                        let size = raster.levels.iter().map(Vec::len).sum::<usize>()
+                            + xbox.padding.0.len();
+                        out.extend((size as u32).to_le_bytes());
+                        for color in &raster.palette {
+                            color.write(out);
+                        }
+                        for level in &raster.levels {
+                            out.extend(level);
+                        }
+                        out.extend(&xbox.padding.0);
+                    }
+                    _ => {
+                        for color in &raster.palette {
+                            color.write(out);
+                        }
+                        for level in &raster.levels {
+                            out.extend((level.len() as u32).to_le_bytes());
+                            out.extend(level);
+                        }
+                    }
+                }
+                out.extend(&raster.remainder.0);
+            }
+            ClumpData::Ps2TextureNative(native) => {
+                out.extend(native.platform_id.to_le_bytes());
+                write_filter_addressing(out, native.filtering, native.uv, native.flags);
+            }
+            ClumpData::Ps2RasterHeader(header) => header.write(out),
+            ClumpData::TextureDictionary(dictionary) => match dictionary.device_id {
+                Some(device_id) => {
+                    out.extend((dictionary.texture_count as u16).to_le_bytes());
+                    out.extend(device_id.to_le_bytes());
+                }
+                None => out.extend(dictionary.texture_count.to_le_bytes()),
+            },
+            ClumpData::World(world) => world.write(out),
+            ClumpData::PlaneSection(plane) => plane.write(out),
+            ClumpData::AtomicSection(sector) => sector.write(out),
+            ClumpData::GeometryList { geometry_count } => out.extend(geometry_count.to_le_bytes()),
+            ClumpData::NodeName(name) => out.extend(name.as_bytes()),
+            ClumpData::BinMesh(bin_mesh) => bin_mesh.write(out),
+            ClumpData::Skin(skin) => skin.write(out, version),
+            ClumpData::HAnim(hanim) => hanim.write(out),
+            ClumpData::ExtraVertColour(extra_vert_colour) => extra_vert_colour.write(out),
+            ClumpData::MaterialEffects(effects) => effects.write(out),
+            #[cfg(feature = "san_andreas_support")]
+            ClumpData::PipelineSet { pipeline_id } => out.extend(pipeline_id.to_le_bytes()),
+            #[cfg(feature = "san_andreas_support")]
+            ClumpData::Effect2d(effects) => super::Effect2d::write_list(effects, out),
+            ClumpData::ReflectionMaterial(reflection) => {
+                for value in [
+                    reflection.scale.0,
+                    reflection.scale.1,
+                    reflection.offset.0,
+                    reflection.offset.1,
+                    reflection.intensity,
+                ] {
+                    out.extend(value.to_le_bytes());
+                }
+                out.extend(reflection.unused.to_le_bytes());
+            }
+            ClumpData::SpecularMaterial(specular) => {
+                out.extend(specular.level.to_le_bytes());
+                specular.texture_name.write_fixed(out, 24);
+            }
+            ClumpData::Unknown => {}
+        }
+    }
+}
+
+// Converts a value read from `input` into an enum, failing if it isn't a known value
+fn to_enum<T: FromPrimitive>(input: &[u8], value: u32) -> Result<T, nom::Err<ParseError<&[u8]>>> {
+    T::from_u32(value).ok_or_else(|| {
+        nom::Err::Failure(ParseError::new(
+            input,
+            ParseErrorKind::Nom(nom::error::ErrorKind::MapOpt),
+        ))
+    })
+}
+
+pub(crate) type FilterAddressing = (
+    TextureFiltering,
+    (TextureAddressing, TextureAddressing),
+    u32,
+);
+
+// Filtering and addressing share a u32: the filter mode in the lowest byte, followed by
+// the U and V addressing modes; the other bits are returned as-is
+pub(crate) fn parse_filter_addressing(input: &[u8]) -> IResult<&[u8], FilterAddressing> {
+    let start = input;
+    let (input, flags) = nc::le_u32(input)?;
+    let filtering = to_enum(start, flags & 0xFF)?;
+    let uv = (
+        to_enum(start, flags >> 8 & 0xF)?,
+        to_enum(start, flags >> 12 & 0xF)?,
+    );
+    Ok((input, (filtering, uv, flags & 0xFFFF_0000)))
 }
 
-fn null_terminated_ascii(input: &[u8]) -> String {
-    String::from_utf8_lossy(input)
-        .trim_end_matches(char::from(0))
-        .to_string()
+pub(crate) fn write_filter_addressing(
+    out: &mut Vec<u8>,
+    filtering: TextureFiltering,
+    (u, v): (TextureAddressing, TextureAddressing),
+    flags: u32,
+) {
+    let value = flags | filtering as u32 | (u as u32) << 8 | (v as u32) << 12;
+    out.extend(value.to_le_bytes());
 }
 
-fn parse_null_terminated_ascii(input: &[u8], length: usize) -> IResult<&[u8], String> {
-    let (input, str) = nom::bytes::complete::take(length)(input)?;
-    Ok((input, null_terminated_ascii(str)))
+fn parse_fixed_string(input: &[u8], length: usize) -> IResult<&[u8], RwString> {
+    let (input, bytes) = nom::bytes::complete::take(length)(input)?;
+    Ok((input, RwString(bytes.to_vec())))
+}
+
+mod tests {
+    #[test]
+    fn parses_and_writes_lights_and_cameras() {
+        use super::{CameraProjection, ClumpData, LightType};
+        use crate::raw::BinaryStreamFile;
+        use crate::test_util::{floats, section};
+
+        // A clump with a light of a type added by a plugin and a parallel camera, both
+        // attached to its only frame
+        let mut light = floats(&[5.0, 1.0, 0.0, 0.5, -1.0]);
+        light.extend([1, 0, 0x83, 0]);
+        let mut light = section(0x01, &light);
+        light.extend(section(0x03, &[]));
+        let mut camera = floats(&[0.5, 0.25, 0.0, 0.0, 0.1, 100.0, 50.0]);
+        camera.extend([2, 0, 0, 0]);
+        let mut camera = section(0x01, &camera);
+        camera.extend(section(0x03, &[]));
+
+        let mut frame = floats(&[1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0]);
+        frame.extend(floats(&[0.0, 0.0, 0.0]));
+        frame.extend([0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0]);
+        let mut frame_list = section(0x01, &[&[1, 0, 0, 0], &frame[..]].concat());
+        frame_list.extend(section(0x03, &[]));
+
+        let mut children = section(0x01, &[0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0]);
+        children.extend(section(0x0E, &frame_list));
+        children.extend(section(0x1A, &section(0x01, &[0, 0, 0, 0])));
+        children.extend(section(0x01, &[0, 0, 0, 0]));
+        children.extend(section(0x12, &light));
+        children.extend(section(0x01, &[0, 0, 0, 0]));
+        children.extend(section(0x05, &camera));
+        children.extend(section(0x03, &[]));
+        let bytes = section(0x10, &children);
+
+        let raw = BinaryStreamFile::from_bytes(&bytes).unwrap();
+        assert_eq!(raw.to_bytes(), bytes);
+        match &raw.sections[0].children[4].children[0].data {
+            ClumpData::Light(light) => {
+                assert_eq!(light.light_type, LightType::Unknown(0x83));
+                assert_eq!((light.radius, light.color), (5.0, [1.0, 0.0, 0.5]));
+                assert_eq!(light.flags, 1);
+            }
+            other => panic!("unexpected data: {:?}", other),
+        }
+        match &raw.sections[0].children[6].children[0].data {
+            ClumpData::Camera(camera) => {
+                assert_eq!(camera.projection, CameraProjection::Parallel);
+                assert_eq!(camera.view_window, (0.5, 0.25));
+                assert_eq!((camera.near_plane, camera.far_plane), (0.1, 100.0));
+            }
+            other => panic!("unexpected data: {:?}", other),
+        }
+
+        let clump = crate::dff::Clump::from_raw(&raw).unwrap();
+        assert_eq!(clump.lights[0].light_type, LightType::Unknown(0x83));
+        assert_eq!(clump.cameras[0].projection, CameraProjection::Parallel);
+        assert_eq!(clump.cameras[0].fog_plane, 50.0);
+    }
+
+    #[test]
+    fn parses_and_writes_texture_sets() {
+        use super::ClumpData;
+        use crate::raw::BinaryStreamFile;
+        use crate::test_util::{floats, section};
+
+        // A triangle with `sets` texture sets, each with every coordinate set to its index
+        let geometry = |format: [u8; 4], sets: usize| {
+            let mut geometry = format.to_vec();
+            geometry.extend([1, 0, 0, 0, 3, 0, 0, 0, 1, 0, 0, 0]);
+            geometry.extend(floats(&[1.0, 1.0, 1.0]));
+            for set in 0..sets {
+                geometry.extend(floats(&[set as f32; 6]));
+            }
+            geometry.extend([1, 0, 0, 0, 0, 0, 2, 0]);
+            geometry.extend(floats(&[0.0, 0.0, 0.0, 1.0]));
+            geometry.extend([1, 0, 0, 0, 0, 0, 0, 0]);
+            geometry.extend(floats(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]));
+            let mut children = section(0x01, &geometry);
+            children.extend(section(0x03, &[]));
+            section(0x0F, &children)
+        };
+
+        // The count in the format's third byte takes precedence over the texture flags
+        for (format, sets) in [
+            ([0x06, 0, 0, 0], 1),
+            ([0x86, 0, 0, 0], 2),
+            ([0x06, 0, 3, 0], 3),
+            ([0x86, 0, 4, 0], 4),
+        ] {
+            let bytes = geometry(format, sets);
+            let raw = BinaryStreamFile::from_bytes(&bytes).unwrap();
+            assert_eq!(raw.to_bytes(), bytes);
+            match &raw.sections[0].children[0].data {
+                ClumpData::Geometry(geometry) => {
+                    let texture_sets = &geometry.data.as_ref().unwrap().texture_sets;
+                    assert_eq!(texture_sets.len(), sets);
+                    for (index, set) in texture_sets.iter().enumerate() {
+                        assert_eq!(set, &vec![(index as f32, index as f32); 3]);
+                    }
+                }
+                other => panic!("unexpected data: {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn parses_and_writes_reflection_and_specular_materials() {
+        use super::ClumpData;
+        use crate::raw::BinaryStreamFile;
+        use crate::test_util::{floats, section};
+
+        // A material's extension with both plugins; what follows the specular texture's
+        // name is kept as it was
+        let mut reflection = floats(&[2.0, 0.5, 0.25, 0.125, 0.75]);
+        reflection.extend([1, 2, 3, 4]);
+        let mut specular = floats(&[0.5]);
+        specular.extend(b"vehiclespecdot64\0\xCD\xCD\xCD\xCD\xCD\xCD\xCD");
+        let mut extension = section(0x0253F2FC, &reflection);
+        extension.extend(section(0x0253F2F6, &specular));
+        let mut material = vec![0, 0, 0, 0, 255, 255, 255, 255, 0, 0, 0, 0, 0, 0, 0, 0];
+        material.extend(floats(&[1.0, 1.0, 1.0]));
+        let mut material = section(0x01, &material);
+        material.extend(section(0x03, &extension));
+        let bytes = section(0x07, &material);
+
+        let raw = BinaryStreamFile::from_bytes(&bytes).unwrap();
+        assert_eq!(raw.to_bytes(), bytes);
+        let plugins = &raw.sections[0].children[1].children;
+        match &plugins[0].data {
+            ClumpData::ReflectionMaterial(reflection) => {
+                assert_eq!(
+                    (reflection.scale, reflection.offset),
+                    ((2.0, 0.5), (0.25, 0.125))
+                );
+                assert_eq!(reflection.intensity, 0.75);
+                assert_eq!(reflection.unused, 0x04030201);
+            }
+            other => panic!("unexpected data: {:?}", other),
+        }
+        match &plugins[1].data {
+            ClumpData::SpecularMaterial(specular) => {
+                assert_eq!(specular.level, 0.5);
+                assert_eq!(specular.texture_name.text(), "vehiclespecdot64");
+            }
+            other => panic!("unexpected data: {:?}", other),
+        }
+    }
 }
@@ -1,4 +1,6 @@
-use nom::{number::complete as nc, sequence::tuple, IResult};
+use nom::{number::complete as nc, sequence::tuple};
+
+use super::IResult;
 
 #[derive(PartialEq, Eq, Clone, Copy)]
 pub struct Color {
@@ -17,6 +19,10 @@ impl Color {
         Ok((input, Color { r, g, b, a }))
     }
 
+    pub(crate) fn write(&self, out: &mut Vec<u8>) {
+        out.extend(self.as_array());
+    }
+
     pub fn as_array(&self) -> [u8; 4] {
         [self.r, self.g, self.b, self.a]
     }
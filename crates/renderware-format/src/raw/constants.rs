@@ -24,6 +24,22 @@ bitflags! {
     }
 }
 
+impl GeometryFormat {
+    // Keeps any bits without a flag, so that they're written back as they were read
+    pub(crate) fn from_bits_retain(bits: u32) -> Self {
+        Self { bits }
+    }
+}
+
+bitflags! {
+    pub struct HAnimNodeFlags: u32 {
+        // This node is the last child of its parent
+        const POP = 0x00000001;
+        // This node has siblings that follow its children
+        const PUSH = 0x00000002;
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Copy, Clone, FromPrimitive)]
 pub enum SectionType {
     Struct = 0x00000001,
@@ -222,6 +238,95 @@ pub enum TextureAddressing {
     Border = 4,
 }
 
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum LightType {
+    // Parallel light, lighting everything from one direction
+    Directional,
+    // Lights everything equally
+    Ambient,
+    // Lights in all directions from a point, falling off with distance
+    Point,
+    // Lights within a cone
+    Spot,
+    // Lights within a cone, fading towards its edge
+    SoftSpot,
+    // A type added by a plugin or a later version, kept as stored
+    Unknown(u16),
+}
+impl LightType {
+    pub fn new(value: u16) -> LightType {
+        match value {
+            0x01 => Self::Directional,
+            0x02 => Self::Ambient,
+            0x80 => Self::Point,
+            0x81 => Self::Spot,
+            0x82 => Self::SoftSpot,
+            value => Self::Unknown(value),
+        }
+    }
+
+    pub fn value(&self) -> u16 {
+        match self {
+            Self::Directional => 0x01,
+            Self::Ambient => 0x02,
+            Self::Point => 0x80,
+            Self::Spot => 0x81,
+            Self::SoftSpot => 0x82,
+            Self::Unknown(value) => *value,
+        }
+    }
+}
+
+// The axis a world's plane sector is perpendicular to, stored as the byte offset of that
+// component within a vector
+#[derive(Debug, PartialEq, Eq, Copy, Clone, FromPrimitive)]
+pub enum PlaneAxis {
+    X = 0,
+    Y = 4,
+    Z = 8,
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone, FromPrimitive)]
+pub enum BlendFunction {
+    NaBlend = 0,
+    Zero = 1,
+    One = 2,
+    SrcColor = 3,
+    InvSrcColor = 4,
+    SrcAlpha = 5,
+    InvSrcAlpha = 6,
+    DestAlpha = 7,
+    InvDestAlpha = 8,
+    DestColor = 9,
+    InvDestColor = 10,
+    SrcAlphaSat = 11,
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum CameraProjection {
+    Perspective,
+    Parallel,
+    // Kept as stored, so that the camera can be written back
+    Unknown(u32),
+}
+impl CameraProjection {
+    pub fn new(value: u32) -> CameraProjection {
+        match value {
+            1 => Self::Perspective,
+            2 => Self::Parallel,
+            value => Self::Unknown(value),
+        }
+    }
+
+    pub fn value(&self) -> u32 {
+        match self {
+            Self::Perspective => 1,
+            Self::Parallel => 2,
+            Self::Unknown(value) => *value,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub enum RasterFormatScheme {
     // 1 bit alpha, RGB 5 bits each; also used for DXT1 with alpha
@@ -272,34 +377,35 @@ impl RasterFormat {
         Self(bits)
     }
 
-    pub fn scheme(&self) -> RasterFormatScheme {
-        if (self.0 & Self::_1555) != 0 {
-            RasterFormatScheme::_1555
-        } else if (self.0 & Self::_565) != 0 {
-            RasterFormatScheme::_565
-        } else if (self.0 & Self::_4444) != 0 {
-            RasterFormatScheme::_4444
-        } else if (self.0 & Self::LUM8) != 0 {
-            RasterFormatScheme::LUM8
-        } else if (self.0 & Self::_8888) != 0 {
-            RasterFormatScheme::_8888
-        } else if (self.0 & Self::_888) != 0 {
-            RasterFormatScheme::_888
-        } else if (self.0 & Self::_555) != 0 {
-            RasterFormatScheme::_555
-        } else {
-            panic!("unexpected scheme")
+    pub fn bits(&self) -> u32 {
+        self.0
+    }
+
+    // The scheme is a value in the second byte, rather than a set of flags
+    const SCHEME_MASK: u32 = 0x0F00;
+
+    /// The pixel format, or `None` for the default format and depth rasters.
+    pub fn scheme(&self) -> Option<RasterFormatScheme> {
+        match self.0 & Self::SCHEME_MASK {
+            Self::_1555 => Some(RasterFormatScheme::_1555),
+            Self::_565 => Some(RasterFormatScheme::_565),
+            Self::_4444 => Some(RasterFormatScheme::_4444),
+            Self::LUM8 => Some(RasterFormatScheme::LUM8),
+            Self::_8888 => Some(RasterFormatScheme::_8888),
+            Self::_888 => Some(RasterFormatScheme::_888),
+            Self::_555 => Some(RasterFormatScheme::_555),
+            _ => None,
         }
     }
 
     #[cfg(feature = "san_andreas_support")]
     pub fn auto_mipmap(&self) -> bool {
-        (self.0 | Self::EXT_AUTO_MIPMAP) != 0
+        (self.0 & Self::EXT_AUTO_MIPMAP) != 0
     }
 
     #[cfg(feature = "san_andreas_support")]
     pub fn mipmap_included(&self) -> bool {
-        (self.0 | Self::EXT_MIPMAP) != 0
+        (self.0 & Self::EXT_MIPMAP) != 0
     }
 
     pub fn palette_color_count(&self) -> u16 {
@@ -321,7 +427,7 @@ impl std::fmt::Debug for RasterFormat {
         #[cfg(feature = "san_andreas_support")]
         {
             ds.field("auto_mipmap", &self.auto_mipmap())
-                .field("mipmap_included", &self.mipmap_included())
+                .field("mipmap_included", &self.mipmap_included());
         }
 
         ds.finish()
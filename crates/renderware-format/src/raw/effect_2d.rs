@@ -0,0 +1,67 @@
+use nom::{number::complete as nc, sequence::tuple};
+
+use super::{checked_count, IResult, UnparsedData, Vec3};
+
+// San Andreas' 2D effects: lights, particles, ped attractors and so on, placed relative
+// to the geometry they're attached to
+#[derive(Debug, PartialEq)]
+pub struct Effect2d {
+    pub position: Vec3,
+    pub effect_type: u32,
+    // The effect's type-specific data, which we don't interpret
+    pub data: UnparsedData,
+}
+impl Effect2d {
+    pub(crate) fn parse_list(input: &[u8]) -> IResult<&[u8], Vec<Self>> {
+        let (input, effect_count) = nc::le_u32(input)?;
+        checked_count(
+            |input| {
+                let (input, position) = Vec3::parse(input)?;
+                let (input, (effect_type, size)) = tuple((nc::le_u32, nc::le_u32))(input)?;
+                let (input, data) = nom::bytes::complete::take(size)(input)?;
+                Ok((
+                    input,
+                    Effect2d {
+                        position,
+                        effect_type,
+                        data: UnparsedData(data.to_vec()),
+                    },
+                ))
+            },
+            effect_count as usize,
+            20,
+        )(input)
+    }
+
+    pub(crate) fn write_list(effects: &[Self], out: &mut Vec<u8>) {
+        out.extend((effects.len() as u32).to_le_bytes());
+        for effect in effects {
+            effect.position.write(out);
+            out.extend(effect.effect_type.to_le_bytes());
+            out.extend((effect.data.0.len() as u32).to_le_bytes());
+            out.extend(&effect.data.0);
+        }
+    }
+}
+
+mod tests {
+    #[test]
+    fn can_round_trip_effects() {
+        let mut bytes = vec![2, 0, 0, 0];
+        for (effect_type, data) in [(0u32, &[1u8, 2, 3, 4][..]), (3, &[])] {
+            bytes.extend([1.0f32, 2.0, 3.0].iter().flat_map(|v| v.to_le_bytes()));
+            bytes.extend(effect_type.to_le_bytes());
+            bytes.extend((data.len() as u32).to_le_bytes());
+            bytes.extend(data);
+        }
+
+        let (rest, effects) = super::Effect2d::parse_list(&bytes).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(effects[1].effect_type, 3);
+        assert_eq!(effects[0].data.0, vec![1, 2, 3, 4]);
+
+        let mut written = vec![];
+        super::Effect2d::write_list(&effects, &mut written);
+        assert_eq!(written, bytes);
+    }
+}
@@ -1,9 +1,158 @@
+use nom::{error::ErrorKind, Offset};
 use thiserror::Error;
 
+use super::{constants::SectionType, RwVersion};
+
 #[derive(Error, Debug)]
 pub enum Error {
-    #[error("parsing error")]
-    NomError(#[from] nom::error::Error<super::UnparsedData>),
-    #[error("IO error")]
+    #[error("unknown section type {section_type:#X} at offset {offset:#X} ({path})")]
+    UnknownSectionType {
+        section_type: u32,
+        offset: usize,
+        path: String,
+    },
+    #[error("unsupported platform {platform} at offset {offset:#X} ({path})")]
+    UnsupportedPlatform {
+        platform: u32,
+        offset: usize,
+        path: String,
+    },
+    // Parsed correctly, but describes something we can't handle, e.g. native geometry
+    #[error("unsupported {feature} at offset {offset:#X} ({path})")]
+    Unsupported {
+        feature: String,
+        offset: usize,
+        path: String,
+    },
+    #[error("truncated data at offset {offset:#X} ({path})")]
+    TruncatedData { offset: usize, path: String },
+    #[error("invalid data ({kind:?}) at offset {offset:#X} ({path})")]
+    InvalidData {
+        kind: ErrorKind,
+        offset: usize,
+        path: String,
+    },
+    #[error("unsupported RenderWare version {version} at offset {offset:#X} ({path})")]
+    UnsupportedVersion {
+        version: RwVersion,
+        offset: usize,
+        path: String,
+    },
+    #[error("sections nested too deeply at offset {offset:#X} ({path})")]
+    TooDeeplyNested { offset: usize, path: String },
+    // Refers to something that isn't there, e.g. a frame whose parent doesn't come before it
+    #[error("invalid {what} index {index} at offset {offset:#X} ({path})")]
+    InvalidIndex {
+        what: String,
+        index: usize,
+        offset: usize,
+        path: String,
+    },
+    #[error("missing {child:?} at offset {offset:#X} ({path})")]
+    MissingChild {
+        child: SectionType,
+        offset: usize,
+        path: String,
+    },
+    // Pixels handed over to be written that don't fill the image they're meant for
+    #[error("{len} bytes of RGBA8 data for a {width}x{height} image")]
+    InvalidImageSize { width: u16, height: u16, len: usize },
+    #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
 }
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum ParseErrorKind {
+    Nom(ErrorKind),
+    UnknownSectionType(u32),
+    UnsupportedPlatform(u32),
+    UnsupportedVersion(RwVersion),
+    TooDeeplyNested,
+}
+
+/// The error used within the parsers. Once it reaches the top of the file, it's turned into
+/// an [`Error`], as the offset and path can only be determined then.
+#[derive(Debug, PartialEq)]
+pub(crate) struct ParseError<I> {
+    pub input: I,
+    pub kind: ParseErrorKind,
+    // The path of the section that failed, innermost first
+    pub path: Vec<String>,
+}
+impl<I> ParseError<I> {
+    pub fn new(input: I, kind: ParseErrorKind) -> Self {
+        Self {
+            input,
+            kind,
+            path: vec![],
+        }
+    }
+}
+impl ParseError<&[u8]> {
+    pub fn into_error(self, file: &[u8]) -> Error {
+        let offset = file.offset(self.input);
+        let path = self.path.into_iter().rev().collect::<Vec<_>>().join("/");
+        match self.kind {
+            ParseErrorKind::Nom(ErrorKind::Eof) => Error::TruncatedData { offset, path },
+            ParseErrorKind::Nom(kind) => Error::InvalidData { kind, offset, path },
+            ParseErrorKind::UnknownSectionType(section_type) => Error::UnknownSectionType {
+                section_type,
+                offset,
+                path,
+            },
+            ParseErrorKind::UnsupportedPlatform(platform) => Error::UnsupportedPlatform {
+                platform,
+                offset,
+                path,
+            },
+            ParseErrorKind::UnsupportedVersion(version) => Error::UnsupportedVersion {
+                version,
+                offset,
+                path,
+            },
+            ParseErrorKind::TooDeeplyNested => Error::TooDeeplyNested { offset, path },
+        }
+    }
+}
+impl<I> nom::error::ParseError<I> for ParseError<I> {
+    fn from_error_kind(input: I, kind: ErrorKind) -> Self {
+        Self::new(input, ParseErrorKind::Nom(kind))
+    }
+
+    fn append(_input: I, _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+impl<I> nom::ErrorConvert<ParseError<I>> for ParseError<(I, usize)> {
+    fn convert(self) -> ParseError<I> {
+        ParseError {
+            input: self.input.0,
+            kind: self.kind,
+            path: self.path,
+        }
+    }
+}
+
+pub(crate) type IResult<I, O> = nom::IResult<I, O, ParseError<I>>;
+
+/// Like [`nom::multi::count`], but fails up front if `count` elements of at least
+/// `element_size` bytes each can't fit in what's left of the input. Counts come straight
+/// from the file, so they can't be trusted to size allocations.
+pub(crate) fn checked_count<'a, O, F>(
+    mut parser: F,
+    count: usize,
+    element_size: usize,
+) -> impl FnMut(&'a [u8]) -> IResult<&'a [u8], Vec<O>>
+where
+    F: nom::Parser<&'a [u8], O, ParseError<&'a [u8]>>,
+{
+    move |input: &'a [u8]| {
+        if count.saturating_mul(element_size) > input.len() {
+            return Err(nom::Err::Failure(ParseError::new(
+                input,
+                ParseErrorKind::Nom(ErrorKind::Eof),
+            )));
+        }
+        nom::multi::count(|input| parser.parse(input), count)(input)
+    }
+}
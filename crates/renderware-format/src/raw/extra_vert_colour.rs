@@ -0,0 +1,27 @@
+use nom::number::complete as nc;
+
+use super::{checked_count, Color, IResult};
+
+// Rockstar's plugin for a second set of prelit colours, used at night
+#[derive(Debug, PartialEq, Eq)]
+pub struct ExtraVertColour {
+    pub night_colors: Option<Vec<Color>>,
+}
+impl ExtraVertColour {
+    pub(crate) fn parse(input: &[u8], vertex_count: u32) -> IResult<&[u8], Self> {
+        let (input, has_colors) = nc::le_u32(input)?;
+        let (input, night_colors) = nom::combinator::cond(
+            has_colors != 0,
+            checked_count(Color::parse, vertex_count as usize, 4),
+        )(input)?;
+
+        Ok((input, ExtraVertColour { night_colors }))
+    }
+
+    pub(crate) fn write(&self, out: &mut Vec<u8>) {
+        out.extend((self.night_colors.is_some() as u32).to_le_bytes());
+        for color in self.night_colors.iter().flatten() {
+            color.write(out);
+        }
+    }
+}
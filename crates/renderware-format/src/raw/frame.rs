@@ -1,25 +1,38 @@
-use nom::{number::complete as nc, IResult};
+use nom::number::complete as nc;
 
-use super::{Mat3, Vec3};
+use super::{IResult, Mat3, Vec3};
 
 #[derive(Debug, PartialEq)]
 pub struct Frame {
     pub rotation: Mat3,
     pub translation: Vec3,
+    // Index of the parent frame within the frame list, if any
+    pub parent: Option<u32>,
+    pub matrix_creation_flags: u32,
 }
 impl Frame {
     pub(crate) fn parse(input: &[u8]) -> IResult<&[u8], Self> {
         let (input, rotation) = Mat3::parse(input)?;
         let (input, translation) = Vec3::parse(input)?;
-        let (input, _current_frame_index) = nc::le_u32(input)?;
-        let (input, _matrix_creation_flags) = nc::le_u32(input)?;
+        let (input, parent) = nc::le_i32(input)?;
+        let (input, matrix_creation_flags) = nc::le_u32(input)?;
 
         Ok((
             input,
             Frame {
                 rotation,
                 translation,
+                parent: u32::try_from(parent).ok(),
+                matrix_creation_flags,
             },
         ))
     }
+
+    pub(crate) fn write(&self, out: &mut Vec<u8>) {
+        self.rotation.write(out);
+        self.translation.write(out);
+        let parent = self.parent.map(|p| p as i32).unwrap_or(-1);
+        out.extend(parent.to_le_bytes());
+        out.extend(self.matrix_creation_flags.to_le_bytes());
+    }
 }
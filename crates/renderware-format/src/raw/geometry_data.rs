@@ -1,6 +1,6 @@
-use nom::{number::complete as nc, sequence::tuple, IResult};
+use nom::{number::complete as nc, sequence::tuple};
 
-use super::{Color, Triangle};
+use super::{checked_count, constants::GeometryFormat, Color, IResult, Triangle};
 
 #[derive(Debug, PartialEq)]
 pub struct GeometryData {
@@ -18,17 +18,16 @@ impl GeometryData {
     ) -> IResult<&[u8], Self> {
         let (input, prelit_color) = nom::combinator::cond(
             is_prelit,
-            nom::multi::count(Color::parse, vertices_count as usize),
+            checked_count(Color::parse, vertices_count as usize, 4),
         )(input)?;
 
         let tex_coord_parser = |input| tuple((nc::le_f32, nc::le_f32))(input);
         let texture_set_parser =
-            |input| nom::multi::count(tex_coord_parser, vertices_count as usize)(input);
+            |input| checked_count(tex_coord_parser, vertices_count as usize, 8)(input);
         let (input, texture_sets) =
             nom::multi::count(texture_set_parser, texture_set_count as usize)(input)?;
 
-        let (input, triangles) =
-            nom::multi::count(Triangle::parse, triangle_count as usize)(input)?;
+        let (input, triangles) = checked_count(Triangle::parse, triangle_count as usize, 8)(input)?;
 
         Ok((
             input,
@@ -39,4 +38,33 @@ impl GeometryData {
             },
         ))
     }
+
+    pub(crate) fn write(&self, out: &mut Vec<u8>) {
+        for color in self.prelit_color.iter().flatten() {
+            color.write(out);
+        }
+        for (u, v) in self.texture_sets.iter().flatten() {
+            out.extend(u.to_le_bytes());
+            out.extend(v.to_le_bytes());
+        }
+        for triangle in &self.triangles {
+            triangle.write(out);
+        }
+    }
+}
+
+// Geometries and worlds keep their texture set count in the third byte of their format
+pub(crate) fn split_geometry_format(format: u32) -> (GeometryFormat, u8) {
+    let texture_set_count = ((format & 0x00FF0000) >> 16) as u8;
+    let format = GeometryFormat::from_bits_retain(format & !0x00FF0000);
+    (format, texture_set_count)
+}
+
+// Without an explicit count, the texture flags say how many sets there are
+pub(crate) fn texture_sets_present(format: GeometryFormat, texture_set_count: u8) -> u16 {
+    match texture_set_count {
+        0 if format.contains(GeometryFormat::TEXTURED2) => 2,
+        0 if format.contains(GeometryFormat::TEXTURED) => 1,
+        count => count as u16,
+    }
 }
@@ -0,0 +1,114 @@
+use nom::{number::complete as nc, sequence::tuple};
+
+use super::{checked_count, constants::HAnimNodeFlags, IResult, UnparsedData};
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct HAnimNode {
+    // The bone ID, which animations use to refer to the node
+    pub id: i32,
+    pub index: u32,
+    pub flags: HAnimNodeFlags,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct HAnimHierarchy {
+    pub flags: u32,
+    pub key_frame_size: u32,
+    // In depth-first order, starting with the frame that owns the hierarchy
+    pub nodes: Vec<HAnimNode>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct HAnim {
+    pub version: u32,
+    // The bone ID of the frame this is attached to
+    pub node_id: i32,
+    // Only present on the root frame of the skeleton
+    pub hierarchy: Option<HAnimHierarchy>,
+    // Anything after the hierarchy
+    pub remainder: UnparsedData,
+}
+impl HAnim {
+    pub(crate) fn parse(input: &[u8]) -> IResult<&[u8], Self> {
+        let (input, (version, node_id, node_count)) =
+            tuple((nc::le_u32, nc::le_i32, nc::le_u32))(input)?;
+
+        let (input, hierarchy) = nom::combinator::cond(node_count > 0, |input| {
+            let (input, (flags, key_frame_size)) = tuple((nc::le_u32, nc::le_u32))(input)?;
+            let (input, nodes) = checked_count(
+                |input| {
+                    let (input, (id, index, flags)) =
+                        tuple((nc::le_i32, nc::le_u32, nc::le_u32))(input)?;
+                    Ok((
+                        input,
+                        HAnimNode {
+                            id,
+                            index,
+                            flags: HAnimNodeFlags::from_bits_truncate(flags),
+                        },
+                    ))
+                },
+                node_count as usize,
+                12,
+            )(input)?;
+            Ok((
+                input,
+                HAnimHierarchy {
+                    flags,
+                    key_frame_size,
+                    nodes,
+                },
+            ))
+        })(input)?;
+
+        let remainder = UnparsedData(input.to_vec());
+        Ok((
+            &input[input.len()..],
+            HAnim {
+                version,
+                node_id,
+                hierarchy,
+                remainder,
+            },
+        ))
+    }
+
+    pub(crate) fn write(&self, out: &mut Vec<u8>) {
+        let nodes = self
+            .hierarchy
+            .as_ref()
+            .map(|h| &h.nodes[..])
+            .unwrap_or_default();
+        out.extend(self.version.to_le_bytes());
+        out.extend(self.node_id.to_le_bytes());
+        out.extend((nodes.len() as u32).to_le_bytes());
+        if let Some(hierarchy) = &self.hierarchy {
+            out.extend(hierarchy.flags.to_le_bytes());
+            out.extend(hierarchy.key_frame_size.to_le_bytes());
+        }
+        for node in nodes {
+            out.extend(node.id.to_le_bytes());
+            out.extend(node.index.to_le_bytes());
+            out.extend(node.flags.bits().to_le_bytes());
+        }
+        out.extend(&self.remainder.0);
+    }
+}
+
+mod tests {
+    #[test]
+    fn keeps_trailing_bytes() {
+        use super::HAnim;
+
+        // A frame's bone ID without a hierarchy, followed by padding
+        let words: [u32; 4] = [0x100, 5, 0, 0];
+        let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+
+        let (_, hanim) = HAnim::parse(&bytes).unwrap();
+        assert_eq!((hanim.node_id, hanim.remainder.0.len()), (5, 4));
+
+        let mut written = vec![];
+        hanim.write(&mut written);
+        assert_eq!(written, bytes);
+    }
+}
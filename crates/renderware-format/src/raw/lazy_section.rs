@@ -0,0 +1,186 @@
+use nom::{bytes::complete as bc, number::complete as nc, sequence::tuple, Finish};
+
+use super::{
+    constants::SectionType, ClumpData, Error, IResult, ParseContext, ParseError, ParseErrorKind,
+    RwVersion, Section, World,
+};
+
+/// A section whose header has been read, but whose contents are only decoded on request.
+/// It borrows from the input, so walking a file this way doesn't copy anything.
+#[derive(Clone, Copy)]
+pub struct LazySection<'a> {
+    pub section_type: SectionType,
+    pub version: RwVersion,
+    pub build: Option<u16>,
+    // Offset of the section's header from the start of the file
+    pub offset: usize,
+    // The section's payload, including any children
+    pub data: &'a [u8],
+    // The header and payload, for decoding
+    bytes: &'a [u8],
+    context: ParseContext<'a>,
+}
+impl<'a> LazySection<'a> {
+    fn parse(input: &'a [u8], context: ParseContext<'a>) -> IResult<&'a [u8], Self> {
+        let start = input;
+        let (input, section_type) = nc::le_u32(input)?;
+        let section_type = match num_traits::FromPrimitive::from_u32(section_type) {
+            Some(section_type) => section_type,
+            None => {
+                return Err(nom::Err::Failure(ParseError::new(
+                    start,
+                    ParseErrorKind::UnknownSectionType(section_type),
+                )))
+            }
+        };
+        let (input, (section_size, library_id)) = tuple((nc::le_u32, nc::le_u32))(input)?;
+        let (version, build) = RwVersion::from_library_id(library_id);
+        let (input, data) = bc::take(section_size)(input)?;
+
+        Ok((
+            input,
+            LazySection {
+                section_type,
+                version,
+                build,
+                offset: nom::Offset::offset(context.file, start),
+                data,
+                bytes: &start[..start.len() - input.len()],
+                context,
+            },
+        ))
+    }
+
+    /// The child sections. Only sections that are made up of a struct and other sections
+    /// have children; the payload of anything else is left alone.
+    pub fn children(&self) -> LazySections<'a> {
+        let data = if ClumpData::SUPPORTED_TYPES.contains(&self.section_type) {
+            self.data
+        } else {
+            &[]
+        };
+        LazySections {
+            input: data,
+            context: self.context.child(self.section_type),
+            top_level: false,
+        }
+    }
+
+    /// Parses the section and all of its children.
+    pub fn decode(&self) -> Result<Section, Error> {
+        let (_, section) = Section::parse(self.bytes, self.context, &[])
+            .finish()
+            .map_err(|err| err.into_error(self.context.file))?;
+        Ok(section)
+    }
+}
+
+impl std::fmt::Debug for LazySection<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LazySection")
+            .field("section_type", &self.section_type)
+            .field("version", &self.version)
+            .field("offset", &self.offset)
+            .field("size", &self.data.len())
+            .finish()
+    }
+}
+
+/// Iterates over sibling sections, reading only their headers.
+pub struct LazySections<'a> {
+    input: &'a [u8],
+    context: ParseContext<'a>,
+    // At the top level, trailing zeroes are padding rather than a section
+    top_level: bool,
+}
+impl<'a> LazySections<'a> {
+    pub(crate) fn new(file: &'a [u8]) -> Self {
+        LazySections {
+            input: file,
+            context: ParseContext::new(file),
+            top_level: true,
+        }
+    }
+}
+impl<'a> Iterator for LazySections<'a> {
+    type Item = Result<LazySection<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.input.is_empty() || (self.top_level && self.input.iter().all(|b| *b == 0)) {
+            return None;
+        }
+
+        match LazySection::parse(self.input, self.context).finish() {
+            Ok((input, section)) => {
+                self.input = input;
+                // Plugins of a geometry need its vertex count, which follows the format
+                // and triangle count in its struct
+                if section.context.parent_type == Some(SectionType::Geometry)
+                    && section.section_type == SectionType::Struct
+                    && section.data.len() >= 12
+                {
+                    let count = section.data[8..12].try_into().unwrap();
+                    self.context.vertex_count = Some(u32::from_le_bytes(count));
+                }
+                // Likewise, the sectors of a world need its format
+                let format_offset = World::format_offset(section.version);
+                if section.context.parent_type == Some(SectionType::World)
+                    && section.section_type == SectionType::Struct
+                    && section.data.len() >= format_offset + 4
+                {
+                    let format = section.data[format_offset..format_offset + 4]
+                        .try_into()
+                        .unwrap();
+                    self.context.world_format = Some(u32::from_le_bytes(format));
+                }
+                self.context.is_first_child = false;
+                Some(Ok(section))
+            }
+            Err(err) => {
+                self.input = &[];
+                Some(Err(err.into_error(self.context.file)))
+            }
+        }
+    }
+}
+
+mod tests {
+    #[test]
+    fn decodes_the_same_as_eager_parsing() {
+        use crate::test_util::section;
+
+        let mut children = section(0x01, &[1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        children.extend(section(0x1A, &section(0x01, &[0, 0, 0, 0])));
+        children.extend(section(0x03, &section(0x0253F2FD, &[1, 2, 3])));
+        let mut bytes = section(0x10, &children);
+        bytes.extend([0; 8]);
+
+        let sections: Vec<_> = crate::raw::BinaryStreamFile::lazy_sections(&bytes)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(sections.len(), 1);
+
+        let children: Vec<_> = sections[0]
+            .children()
+            .collect::<Result<_, crate::raw::Error>>()
+            .unwrap();
+        let types: Vec<_> = children.iter().map(|c| c.section_type).collect();
+        use crate::raw::constants::SectionType;
+        assert_eq!(
+            types,
+            vec![
+                SectionType::Struct,
+                SectionType::GeometryList,
+                SectionType::Extension
+            ]
+        );
+        // Plugin payloads aren't sections
+        let extension: Vec<_> = children[2].children().map(Result::unwrap).collect();
+        assert_eq!(extension[0].data, &[1, 2, 3]);
+        assert_eq!(extension[0].children().count(), 0);
+
+        let eager = crate::raw::BinaryStreamFile::from_bytes(&bytes).unwrap();
+        assert_eq!(sections[0].decode().unwrap(), eager.sections[0]);
+        assert_eq!(children[1].decode().unwrap(), eager.sections[0].children[1]);
+    }
+}
@@ -1,4 +1,6 @@
-use nom::{number::complete as nc, sequence::tuple, IResult};
+use nom::{number::complete as nc, sequence::tuple};
+
+use super::IResult;
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct Lighting {
@@ -19,4 +21,10 @@ impl Lighting {
             },
         ))
     }
+
+    pub(crate) fn write(&self, out: &mut Vec<u8>) {
+        for value in [self.ambient, self.specular, self.diffuse] {
+            out.extend(value.to_le_bytes());
+        }
+    }
 }
@@ -0,0 +1,230 @@
+use nom::{number::complete as nc, sequence::tuple};
+use num_traits::FromPrimitive;
+
+use super::{constants::BlendFunction, IResult, ParseContext, ParseError, ParseErrorKind, Section};
+
+// The Material Effects plugin's data for a material. A material has two effect slots:
+// bump-environment mapping uses both (a bump map, then an environment map), as does a dual
+// texture with a UV transform, while the other effect types leave the second slot empty
+#[derive(Debug, PartialEq)]
+pub struct MaterialEffects {
+    // The combination of effects, e.g. 3 for bump-environment mapping
+    pub effect_type: u32,
+    pub effects: [MaterialEffect; 2],
+}
+impl MaterialEffects {
+    /// `context` is the context for the section's children, used to parse the textures
+    /// embedded within the effects.
+    pub(crate) fn parse<'a>(input: &'a [u8], context: ParseContext<'a>) -> IResult<&'a [u8], Self> {
+        let (input, effect_type) = nc::le_u32(input)?;
+        let (input, first) = MaterialEffect::parse(input, context)?;
+        let (input, second) = MaterialEffect::parse(input, context)?;
+        Ok((
+            input,
+            MaterialEffects {
+                effect_type,
+                effects: [first, second],
+            },
+        ))
+    }
+
+    pub(crate) fn write(&self, out: &mut Vec<u8>) {
+        out.extend(self.effect_type.to_le_bytes());
+        for effect in &self.effects {
+            effect.write(out);
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MaterialEffect {
+    Null,
+    BumpMap {
+        coefficient: f32,
+        bumped_texture: Option<Box<Section>>,
+        bump_texture: Option<Box<Section>>,
+    },
+    EnvMap {
+        // How strongly the environment is reflected
+        coefficient: f32,
+        // Whether the reflection is blended using the frame buffer's alpha
+        frame_buffer_alpha: bool,
+        texture: Option<Box<Section>>,
+    },
+    // A second texture, blended over the first in another pass
+    Dual {
+        src_blend: BlendFunction,
+        dst_blend: BlendFunction,
+        texture: Option<Box<Section>>,
+    },
+    // The UV transforms themselves are animated, rather than stored here
+    UvTransform,
+}
+impl MaterialEffect {
+    const NULL: u32 = 0;
+    const BUMP_MAP: u32 = 1;
+    const ENV_MAP: u32 = 2;
+    const DUAL: u32 = 4;
+    const UV_TRANSFORM: u32 = 5;
+
+    fn parse<'a>(input: &'a [u8], context: ParseContext<'a>) -> IResult<&'a [u8], Self> {
+        let start = input;
+        let (input, effect_type) = nc::le_u32(input)?;
+        // Each texture is preceded by whether it's present
+        let texture = |input: &'a [u8]| -> IResult<&'a [u8], Option<Box<Section>>> {
+            let (input, has_texture) = nc::le_u32(input)?;
+            if has_texture == 0 {
+                return Ok((input, None));
+            }
+            let (input, section) = Section::parse(input, context, &[])?;
+            Ok((input, Some(Box::new(section))))
+        };
+        let blend_function = |input: &'a [u8]| -> IResult<&'a [u8], BlendFunction> {
+            let (rest, value) = nc::le_u32(input)?;
+            let value = BlendFunction::from_u32(value).ok_or_else(|| {
+                nom::Err::Failure(ParseError::new(
+                    input,
+                    ParseErrorKind::Nom(nom::error::ErrorKind::MapOpt),
+                ))
+            })?;
+            Ok((rest, value))
+        };
+
+        Ok(match effect_type {
+            Self::NULL => (input, MaterialEffect::Null),
+            Self::BUMP_MAP => {
+                let (input, (coefficient, bumped_texture, bump_texture)) =
+                    tuple((nc::le_f32, texture, texture))(input)?;
+                (
+                    input,
+                    MaterialEffect::BumpMap {
+                        coefficient,
+                        bumped_texture,
+                        bump_texture,
+                    },
+                )
+            }
+            Self::ENV_MAP => {
+                let (input, (coefficient, frame_buffer_alpha, texture)) =
+                    tuple((nc::le_f32, nc::le_u32, texture))(input)?;
+                (
+                    input,
+                    MaterialEffect::EnvMap {
+                        coefficient,
+                        frame_buffer_alpha: frame_buffer_alpha != 0,
+                        texture,
+                    },
+                )
+            }
+            Self::DUAL => {
+                let (input, (src_blend, dst_blend, texture)) =
+                    tuple((blend_function, blend_function, texture))(input)?;
+                (
+                    input,
+                    MaterialEffect::Dual {
+                        src_blend,
+                        dst_blend,
+                        texture,
+                    },
+                )
+            }
+            Self::UV_TRANSFORM => (input, MaterialEffect::UvTransform),
+            _ => {
+                return Err(nom::Err::Failure(ParseError::new(
+                    start,
+                    ParseErrorKind::Nom(nom::error::ErrorKind::Switch),
+                )))
+            }
+        })
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        fn write_texture(out: &mut Vec<u8>, texture: &Option<Box<Section>>) {
+            out.extend(u32::from(texture.is_some()).to_le_bytes());
+            if let Some(texture) = texture {
+                texture.write(out);
+            }
+        }
+
+        match self {
+            MaterialEffect::Null => out.extend(Self::NULL.to_le_bytes()),
+            MaterialEffect::BumpMap {
+                coefficient,
+                bumped_texture,
+                bump_texture,
+            } => {
+                out.extend(Self::BUMP_MAP.to_le_bytes());
+                out.extend(coefficient.to_le_bytes());
+                write_texture(out, bumped_texture);
+                write_texture(out, bump_texture);
+            }
+            MaterialEffect::EnvMap {
+                coefficient,
+                frame_buffer_alpha,
+                texture,
+            } => {
+                out.extend(Self::ENV_MAP.to_le_bytes());
+                out.extend(coefficient.to_le_bytes());
+                out.extend(u32::from(*frame_buffer_alpha).to_le_bytes());
+                write_texture(out, texture);
+            }
+            MaterialEffect::Dual {
+                src_blend,
+                dst_blend,
+                texture,
+            } => {
+                out.extend(Self::DUAL.to_le_bytes());
+                out.extend((*src_blend as u32).to_le_bytes());
+                out.extend((*dst_blend as u32).to_le_bytes());
+                write_texture(out, texture);
+            }
+            MaterialEffect::UvTransform => out.extend(Self::UV_TRANSFORM.to_le_bytes()),
+        }
+    }
+}
+
+mod tests {
+    #[test]
+    fn can_round_trip_effects() {
+        use crate::raw::{
+            constants::{BlendFunction, SectionType},
+            ClumpData, ParseContext,
+        };
+        use crate::test_util::section;
+        let mut texture = section(0x01, &[0x02, 0x11, 0, 0]);
+        texture.extend(section(0x02, b"env\0"));
+        texture.extend(section(0x02, &[0; 4]));
+        texture.extend(section(0x03, &[]));
+        let texture = section(0x06, &texture);
+
+        // An environment map, then a dual texture without one
+        let mut bytes = vec![2, 0, 0, 0, 2, 0, 0, 0];
+        bytes.extend(0.5f32.to_le_bytes());
+        bytes.extend([0, 0, 0, 0, 1, 0, 0, 0]);
+        bytes.extend(&texture);
+        bytes.extend([4, 0, 0, 0, 9, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0]);
+
+        let context = ParseContext::new(&bytes).child(SectionType::MaterialEffectsPLG);
+        let (rest, effects) = super::MaterialEffects::parse(&bytes, context).unwrap();
+        assert!(rest.is_empty());
+        match &effects.effects {
+            [super::MaterialEffect::EnvMap {
+                coefficient,
+                texture: Some(texture),
+                ..
+            }, super::MaterialEffect::Dual {
+                src_blend: BlendFunction::DestColor,
+                dst_blend: BlendFunction::Zero,
+                texture: None,
+            }] => {
+                assert_eq!(*coefficient, 0.5);
+                assert_eq!(texture.children[1].data, ClumpData::String("env".into()));
+            }
+            effects => panic!("unexpected effects: {:?}", effects),
+        }
+
+        let mut written = vec![];
+        effects.write(&mut written);
+        assert_eq!(written, bytes);
+    }
+}
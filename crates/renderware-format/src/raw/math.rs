@@ -1,4 +1,6 @@
-use nom::{number::complete as nc, sequence::tuple, IResult};
+use nom::{number::complete as nc, sequence::tuple};
+
+use super::IResult;
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct Vec3 {
@@ -18,14 +20,33 @@ impl Vec3 {
         Ok((input, Vec3 { x, y, z }))
     }
 
+    pub(crate) fn write(&self, out: &mut Vec<u8>) {
+        out.extend(self.as_array().iter().flat_map(|v| v.to_le_bytes()));
+    }
+
     pub fn as_array(&self) -> [f32; 3] {
         [self.x, self.y, self.z]
     }
 }
 
+impl std::ops::Add for Vec3 {
+    type Output = Vec3;
+
+    fn add(self, rhs: Vec3) -> Vec3 {
+        Vec3 {
+            x: self.x + rhs.x,
+            y: self.y + rhs.y,
+            z: self.z + rhs.z,
+        }
+    }
+}
+
+// Stored as the right, up and at vectors, which are the columns of the rotation matrix
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct Mat3(pub [f32; 9]);
 impl Mat3 {
+    pub const IDENTITY: Mat3 = Mat3([1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0]);
+
     pub(crate) fn parse(input: &[u8]) -> IResult<&[u8], Self> {
         let (input, data) = nom::multi::count(nc::le_f32, 9)(input)?;
 
@@ -33,4 +54,56 @@ impl Mat3 {
         buf.copy_from_slice(&data);
         Ok((input, Mat3(buf)))
     }
+
+    pub(crate) fn write(&self, out: &mut Vec<u8>) {
+        out.extend(self.0.iter().flat_map(|v| v.to_le_bytes()));
+    }
+}
+impl std::ops::Mul<Vec3> for Mat3 {
+    type Output = Vec3;
+
+    fn mul(self, rhs: Vec3) -> Vec3 {
+        let m = &self.0;
+        Vec3 {
+            x: m[0] * rhs.x + m[3] * rhs.y + m[6] * rhs.z,
+            y: m[1] * rhs.x + m[4] * rhs.y + m[7] * rhs.z,
+            z: m[2] * rhs.x + m[5] * rhs.y + m[8] * rhs.z,
+        }
+    }
+}
+impl std::ops::Mul for Mat3 {
+    type Output = Mat3;
+
+    fn mul(self, rhs: Mat3) -> Mat3 {
+        let mut buf = [0.0; 9];
+        for column in 0..3 {
+            let c = &rhs.0[column * 3..column * 3 + 3];
+            let v = self
+                * Vec3 {
+                    x: c[0],
+                    y: c[1],
+                    z: c[2],
+                };
+            buf[column * 3..column * 3 + 3].copy_from_slice(&v.as_array());
+        }
+        Mat3(buf)
+    }
+}
+
+// Column-major, with the fourth row holding the flags RenderWare stores in the
+// w components (these should be treated as 0, 0, 0, 1)
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Mat4(pub [f32; 16]);
+impl Mat4 {
+    pub(crate) fn parse(input: &[u8]) -> IResult<&[u8], Self> {
+        let (input, data) = nom::multi::count(nc::le_f32, 16)(input)?;
+
+        let mut buf = [0.0; 16];
+        buf.copy_from_slice(&data);
+        Ok((input, Mat4(buf)))
+    }
+
+    pub(crate) fn write(&self, out: &mut Vec<u8>) {
+        out.extend(self.0.iter().flat_map(|v| v.to_le_bytes()));
+    }
 }
@@ -3,12 +3,18 @@ pub use error::*;
 
 pub mod constants;
 
+pub mod version;
+pub use version::*;
+
 pub mod math;
 pub use math::*;
 
 pub mod unparsed_data;
 pub use unparsed_data::*;
 
+pub mod rw_string;
+pub use rw_string::*;
+
 pub mod frame;
 pub use frame::*;
 
@@ -30,11 +36,40 @@ pub use geometry_data::*;
 pub mod morph_target;
 pub use morph_target::*;
 
+pub mod bin_mesh;
+pub use bin_mesh::*;
+
+pub mod extra_vert_colour;
+pub use extra_vert_colour::*;
+
+pub mod hanim;
+pub use hanim::*;
+
+pub mod skin;
+pub use skin::*;
+
+pub mod material_effects;
+pub use material_effects::*;
+
+pub mod world;
+pub use world::*;
+
+#[cfg(feature = "san_andreas_support")]
+pub mod effect_2d;
+#[cfg(feature = "san_andreas_support")]
+pub use effect_2d::*;
+
+pub mod ps2_raster;
+pub use ps2_raster::*;
+
 pub mod clump_data;
 pub use clump_data::*;
 
 pub mod section;
 pub use section::*;
 
+pub mod lazy_section;
+pub use lazy_section::*;
+
 pub mod binary_stream_file;
 pub use binary_stream_file::*;
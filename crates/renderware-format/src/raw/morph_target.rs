@@ -1,12 +1,15 @@
-use nom::{number::complete as nc, sequence::tuple, IResult};
+use nom::{number::complete as nc, sequence::tuple};
 
-use super::{Sphere, Vec3};
+use super::{checked_count, IResult, Sphere, Vec3};
 
 #[derive(Debug, PartialEq)]
 pub struct MorphTarget {
     pub bounding_sphere: Sphere,
     pub vertices: Vec<Vec3>,
     pub normals: Vec<Vec3>,
+    // Whether there are vertices and normals, as stored; any non-zero value means there are
+    pub has_vertices: u32,
+    pub has_normals: u32,
 }
 impl MorphTarget {
     pub(crate) fn parse(input: &[u8], vertices_count: u32) -> IResult<&[u8], Self> {
@@ -14,11 +17,11 @@ impl MorphTarget {
         let (input, (has_vertices, has_normals)) = tuple((nc::le_u32, nc::le_u32))(input)?;
         let (input, vertices) = nom::combinator::cond(
             has_vertices > 0,
-            nom::multi::count(Vec3::parse, vertices_count as usize),
+            checked_count(Vec3::parse, vertices_count as usize, 12),
         )(input)?;
         let (input, normals) = nom::combinator::cond(
             has_normals > 0,
-            nom::multi::count(Vec3::parse, vertices_count as usize),
+            checked_count(Vec3::parse, vertices_count as usize, 12),
         )(input)?;
 
         Ok((
@@ -27,7 +30,26 @@ impl MorphTarget {
                 bounding_sphere,
                 vertices: vertices.unwrap_or_default(),
                 normals: normals.unwrap_or_default(),
+                has_vertices,
+                has_normals,
             },
         ))
     }
+
+    pub(crate) fn write(&self, out: &mut Vec<u8>) {
+        self.bounding_sphere.write(out);
+        // The stored flags are only kept while they still agree with what's there
+        let flag = |stored: u32, present: bool| {
+            if (stored != 0) == present {
+                stored
+            } else {
+                present as u32
+            }
+        };
+        out.extend(flag(self.has_vertices, !self.vertices.is_empty()).to_le_bytes());
+        out.extend(flag(self.has_normals, !self.normals.is_empty()).to_le_bytes());
+        for vector in self.vertices.iter().chain(&self.normals) {
+            vector.write(out);
+        }
+    }
 }
@@ -0,0 +1,214 @@
+use nom::{number::complete as nc, sequence::tuple};
+
+use super::{
+    constants::{RasterFormat, SectionType, TextureAddressing, TextureFiltering},
+    ClumpData, Color, IResult, Raster, RasterPlatform, RwString, Section, UnparsedData,
+};
+
+// The first struct of a PlayStation 2 texture native, which only has its platform and
+// sampling. Its name and mask name follow as strings, and then a struct holding the raster's
+// header and data in structs of their own.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Ps2TextureNative {
+    // Either 6, or `PS2\0` as a FourCC
+    pub platform_id: u32,
+    pub filtering: TextureFiltering,
+    pub uv: (TextureAddressing, TextureAddressing),
+    // Any other bits of the filtering and addressing flags, left in place
+    pub flags: u32,
+}
+
+// The header of a PlayStation 2 raster, which is mostly the GS registers it's uploaded with
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Ps2RasterHeader {
+    pub width: u32,
+    pub height: u32,
+    pub depth: u32,
+    // The upper half has flags of the PlayStation 2's own; see `has_transfer_headers`
+    pub raster_format: RasterFormat,
+    pub tex0: u64,
+    pub palette_offset: u32,
+    // The lower half of TEX1, which has the number of mip levels
+    pub tex1_low: u32,
+    pub miptbp1: u64,
+    pub miptbp2: u64,
+    // The sizes of the pixels and the palette within the data that follows
+    pub pixel_size: u32,
+    pub palette_size: u32,
+    // How much GS memory the raster takes up
+    pub gpu_size: u32,
+    pub sky_mipmap: u32,
+}
+impl Ps2RasterHeader {
+    // Each level and the palette are preceded by the GIF packet that uploads them
+    const TRANSFER_HEADERS: u32 = 0x2_0000;
+    const TRANSFER_HEADER_SIZE: usize = 0x50;
+
+    pub(crate) fn parse(input: &[u8]) -> IResult<&[u8], Self> {
+        let (input, (width, height, depth, raster_format)) =
+            tuple((nc::le_u32, nc::le_u32, nc::le_u32, nc::le_u32))(input)?;
+        let (input, (tex0, palette_offset, tex1_low, miptbp1, miptbp2)) =
+            tuple((nc::le_u64, nc::le_u32, nc::le_u32, nc::le_u64, nc::le_u64))(input)?;
+        let (input, (pixel_size, palette_size, gpu_size, sky_mipmap)) =
+            tuple((nc::le_u32, nc::le_u32, nc::le_u32, nc::le_u32))(input)?;
+        Ok((
+            input,
+            Ps2RasterHeader {
+                width,
+                height,
+                depth,
+                raster_format: RasterFormat::new(raster_format),
+                tex0,
+                palette_offset,
+                tex1_low,
+                miptbp1,
+                miptbp2,
+                pixel_size,
+                palette_size,
+                gpu_size,
+                sky_mipmap,
+            },
+        ))
+    }
+
+    pub(crate) fn write(&self, out: &mut Vec<u8>) {
+        for value in [
+            self.width,
+            self.height,
+            self.depth,
+            self.raster_format.bits(),
+        ] {
+            out.extend(value.to_le_bytes());
+        }
+        out.extend(self.tex0.to_le_bytes());
+        out.extend(self.palette_offset.to_le_bytes());
+        out.extend(self.tex1_low.to_le_bytes());
+        out.extend(self.miptbp1.to_le_bytes());
+        out.extend(self.miptbp2.to_le_bytes());
+        for value in [
+            self.pixel_size,
+            self.palette_size,
+            self.gpu_size,
+            self.sky_mipmap,
+        ] {
+            out.extend(value.to_le_bytes());
+        }
+    }
+
+    /// The number of levels, from the MXL field of TEX1.
+    pub fn level_count(&self) -> usize {
+        ((self.tex1_low >> 2) & 0x7) as usize + 1
+    }
+
+    /// Whether each level and the palette are preceded by the GIF packet that uploads them,
+    /// as they are in the games' files.
+    pub fn has_transfer_headers(&self) -> bool {
+        self.raster_format.bits() & Self::TRANSFER_HEADERS != 0
+    }
+}
+
+// What a PlayStation 2 raster keeps that the others don't
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Ps2Raster {
+    pub header: Ps2RasterHeader,
+    // The width and height each level was uploaded with. Swizzled levels are uploaded as
+    // a pixel format wider than their own, and so are narrower than the level itself.
+    pub transfer_sizes: Vec<(u32, u32)>,
+}
+
+impl Raster {
+    /// Gathers the sections of a PlayStation 2 texture native into a raster, whose levels
+    /// and palette are left as they're stored, aside from the GIF packets around them.
+    /// `section` is the raster section, whose first struct was read as `native`.
+    pub fn from_ps2_section(section: &Section, native: &Ps2TextureNative) -> Option<Raster> {
+        let mut strings =
+            section
+                .find_children_by_type(SectionType::String)
+                .map(|s| match &s.data {
+                    ClumpData::String(string) => string.clone(),
+                    _ => RwString::default(),
+                });
+        let name = strings.next()?;
+        let mask_name = strings.next().unwrap_or_default();
+
+        let raster = section.find_children_by_type(SectionType::Struct).nth(1)?;
+        let header = match &raster.children.first()?.data {
+            ClumpData::Ps2RasterHeader(header) => header,
+            _ => return None,
+        };
+        let data = match &raster.children.get(1)?.data {
+            ClumpData::Struct(data) => data.0.as_slice(),
+            _ => return None,
+        };
+        let pixel_size = (header.pixel_size as usize).min(data.len());
+        let (mut pixels, palette) = data.split_at(pixel_size);
+
+        // Level sizes come from the GIF packets if there are any, and the dimensions if not
+        let mut levels = vec![];
+        let mut transfer_sizes = vec![];
+        for level in 0..header.level_count() {
+            let size = |size: u32| (size >> level).max(1);
+            let (width, height) = (size(header.width), size(header.height));
+            let (transfer_size, level_size) = if header.has_transfer_headers() {
+                let transfer_header = pixels.get(..Ps2RasterHeader::TRANSFER_HEADER_SIZE)?;
+                pixels = &pixels[Ps2RasterHeader::TRANSFER_HEADER_SIZE..];
+                let word = |offset: usize| {
+                    u32::from_le_bytes(transfer_header[offset..offset + 4].try_into().unwrap())
+                };
+                // TRXREG's width and height, and the quadword count of the image's GIFtag
+                ((word(32), word(36)), (word(64) & 0x7FFF) as usize * 16)
+            } else {
+                // The header's sizes can be anything, so this mustn't overflow
+                let size = (width as u64 * height as u64 * header.depth as u64).div_ceil(8);
+                ((width, height), size.try_into().ok()?)
+            };
+            match pixels.get(..level_size) {
+                Some(level) => levels.push(level.to_vec()),
+                None if levels.is_empty() => return None,
+                None => break,
+            }
+            transfer_sizes.push(transfer_size);
+            pixels = &pixels[level_size..];
+        }
+
+        let palette = if header.has_transfer_headers() {
+            palette
+                .get(Ps2RasterHeader::TRANSFER_HEADER_SIZE..)
+                .unwrap_or_default()
+        } else {
+            palette
+        };
+        let palette = palette
+            .chunks_exact(4)
+            .map(|c| Color::new(c[0], c[1], c[2], c[3]))
+            .collect();
+
+        let has_alpha = matches!(
+            header.raster_format.bits() & 0x0F00,
+            0x0100 | 0x0300 | 0x0500
+        );
+        Some(Raster {
+            filtering: native.filtering,
+            uv: native.uv,
+            flags: native.flags,
+            name,
+            mask_name,
+            raster_format: header.raster_format,
+            has_alpha,
+            width: header.width.try_into().ok()?,
+            height: header.height.try_into().ok()?,
+            depth: header.depth.try_into().ok()?,
+            level_count: levels.len() as u8,
+            // A texture
+            raster_type: 4,
+            compression: 0,
+            palette,
+            levels,
+            remainder: UnparsedData(pixels.to_vec()),
+            platform: RasterPlatform::Ps2(Ps2Raster {
+                header: header.clone(),
+                transfer_sizes,
+            }),
+        })
+    }
+}
@@ -0,0 +1,35 @@
+use std::{borrow::Cow, fmt::Display};
+
+/// A null-terminated string, as stored. The terminator and whatever follows it (padding,
+/// or whatever was left in the buffer when the file was written) are kept, as are bytes that
+/// aren't valid UTF-8, so that the string is written back exactly as it was read.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct RwString(pub Vec<u8>);
+impl RwString {
+    /// The text before the terminator.
+    pub fn text(&self) -> Cow<'_, str> {
+        let end = self.0.iter().position(|&b| b == 0).unwrap_or(self.0.len());
+        String::from_utf8_lossy(&self.0[..end])
+    }
+
+    // Writes exactly `length` bytes, truncating or padding with zeros as needed
+    pub(crate) fn write_fixed(&self, out: &mut Vec<u8>, length: usize) {
+        out.extend(&self.0[..self.0.len().min(length)]);
+        out.resize(out.len() + length.saturating_sub(self.0.len()), 0);
+    }
+}
+
+// New strings are terminated and padded to a multiple of four bytes, as String sections are
+impl From<&str> for RwString {
+    fn from(text: &str) -> Self {
+        let mut bytes = text.as_bytes().to_vec();
+        bytes.resize(bytes.len() + 4 - bytes.len() % 4, 0);
+        RwString(bytes)
+    }
+}
+
+impl Display for RwString {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.text())
+    }
+}
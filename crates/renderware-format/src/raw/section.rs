@@ -1,44 +1,205 @@
-use nom::{bytes::complete as bc, number::complete as nc, IResult};
+use nom::{bytes::complete as bc, number::complete as nc, Offset};
 
-use super::{constants::SectionType, ClumpData};
+use super::{
+    constants::SectionType, ClumpData, Error, IResult, ParseError, ParseErrorKind, RwVersion,
+    UnparsedData,
+};
 
 #[derive(Debug, PartialEq)]
 pub struct Section {
     pub section_type: SectionType,
-    pub version: u32,
+    pub version: RwVersion,
+    // The build number from the library ID stamp, if the stamp was in the newer format
+    // (3.1.0.1 onwards) that has one
+    pub build: Option<u16>,
+    // Offset of the section's header from the start of the file
+    pub offset: usize,
     pub children: Vec<Section>,
     pub data: ClumpData,
 }
 
+// What a section needs to know about its surroundings to parse itself
+#[derive(Clone, Copy)]
+pub(crate) struct ParseContext<'a> {
+    // The entire file, used to determine offsets
+    pub file: &'a [u8],
+    pub parent_type: Option<SectionType>,
+    // The vertex count of the enclosing geometry, if any, which some plugins need
+    // to parse their per-vertex data
+    pub vertex_count: Option<u32>,
+    // The format of the enclosing world, as stored, which its sectors need to parse
+    // their vertices
+    pub world_format: Option<u32>,
+    // How many sections enclose this one
+    pub depth: usize,
+    // Whether this is the first section within its parent. A clump's first struct is its
+    // header, while any later ones give the frame of the light or camera that follows them
+    pub is_first_child: bool,
+}
+impl<'a> ParseContext<'a> {
+    pub fn new(file: &'a [u8]) -> Self {
+        ParseContext {
+            file,
+            parent_type: None,
+            vertex_count: None,
+            world_format: None,
+            depth: 0,
+            is_first_child: true,
+        }
+    }
+
+    // The context for the children of a section of `section_type`
+    pub fn child(&self, section_type: SectionType) -> Self {
+        ParseContext {
+            parent_type: Some(section_type),
+            depth: self.depth + 1,
+            is_first_child: true,
+            ..*self
+        }
+    }
+}
+
 impl Section {
-    pub(crate) fn parse(input: &[u8], parent_type: Option<SectionType>) -> IResult<&[u8], Section> {
-        let (input, section_type) = nc::le_u32(input)?;
-        let section_type = num_traits::FromPrimitive::from_u32(section_type)
-            .unwrap_or_else(|| panic!("unexpected section type {:X}", section_type));
+    // Sections that usually have siblings of the same type, and are identified by their
+    // index amongst them in error paths
+    const INDEXED_TYPES: &'static [SectionType] = &[
+        SectionType::Atomic,
+        SectionType::Geometry,
+        SectionType::Material,
+        SectionType::Raster,
+    ];
+    // Real files nest no more than a handful of sections deep; anything beyond this is
+    // malformed, and would otherwise let a file recurse as deeply as it likes
+    const MAX_DEPTH: usize = 64;
 
-        let (input, section_size) = nc::le_u32(input)?;
-        let (input, version) = nc::le_u32(input)?;
-        let version = {
-            if version & 0xFFFF0000 != 0 {
-                ((version >> 14 & 0x3FF00) + 0x30000) | (version >> 16 & 0x3F)
-            } else {
-                version << 8
+    /// `siblings` are the sections preceding this one within its parent.
+    pub(crate) fn parse<'a>(
+        input: &'a [u8],
+        context: ParseContext<'a>,
+        siblings: &[Section],
+    ) -> IResult<&'a [u8], Section> {
+        let start = input;
+        if context.depth > Self::MAX_DEPTH {
+            return Err(nom::Err::Failure(ParseError::new(
+                start,
+                ParseErrorKind::TooDeeplyNested,
+            )));
+        }
+        let (input, section_type) = nc::le_u32(input)?;
+        let section_type = match num_traits::FromPrimitive::from_u32(section_type) {
+            Some(section_type) => section_type,
+            None => {
+                return Err(nom::Err::Failure(ParseError::new(
+                    start,
+                    ParseErrorKind::UnknownSectionType(section_type),
+                )))
             }
         };
+
+        let path_component = if Self::INDEXED_TYPES.contains(&section_type) {
+            let index = siblings
+                .iter()
+                .filter(|s| s.section_type == section_type)
+                .count();
+            format!("{:?}[{}]", section_type, index)
+        } else {
+            format!("{:?}", section_type)
+        };
+
+        Self::parse_contents(input, context, section_type, context.file.offset(start)).map_err(
+            |err| {
+                err.map(|mut err| {
+                    err.path.push(path_component);
+                    err
+                })
+            },
+        )
+    }
+
+    fn parse_contents<'a>(
+        input: &'a [u8],
+        context: ParseContext<'a>,
+        section_type: SectionType,
+        offset: usize,
+    ) -> IResult<&'a [u8], Section> {
+        let (input, section_size) = nc::le_u32(input)?;
+        let stamp = input;
+        let (input, library_id) = nc::le_u32(input)?;
+        let (version, build) = RwVersion::from_library_id(library_id);
+        if !version.is_supported() {
+            return Err(nom::Err::Failure(ParseError::new(
+                stamp,
+                ParseErrorKind::UnsupportedVersion(version),
+            )));
+        }
         let (input, data) = bc::take(section_size)(input)?;
 
+        let vertex_count = context.vertex_count;
+        let world_format = context.world_format;
         let (mut data, section_data) = match section_type {
-            SectionType::Struct => ClumpData::parse_struct(data, parent_type.unwrap(), version)?,
+            SectionType::Struct
+                if context.parent_type == Some(SectionType::Clump) && !context.is_first_child =>
+            {
+                ClumpData::parse_frame_index(data)?
+            }
+            SectionType::Struct
+                if context.parent_type == Some(SectionType::AtomicSection)
+                    && world_format.is_some() =>
+            {
+                ClumpData::parse_atomic_section(data, world_format.unwrap())?
+            }
+            // Only PlayStation 2 rasters have a second struct, which holds the raster's header
+            // and data in structs of their own
+            SectionType::Struct
+                if context.parent_type == Some(SectionType::Raster) && !context.is_first_child =>
+            {
+                (data, ClumpData::Unknown)
+            }
+            SectionType::Struct
+                if context.parent_type == Some(SectionType::Struct) && context.is_first_child =>
+            {
+                ClumpData::parse_ps2_raster_header(data)?
+            }
+            SectionType::Struct => ClumpData::parse_struct(data, context.parent_type, version)?,
             SectionType::String => ClumpData::parse_string(data)?,
             SectionType::NodeName => ClumpData::parse_node_name(data)?,
+            SectionType::HAnimPLG => ClumpData::parse_hanim(data)?,
+            SectionType::BinMeshPLG => ClumpData::parse_bin_mesh(data)?,
+            SectionType::SkinPLG if vertex_count.is_some() => {
+                ClumpData::parse_skin(data, version, vertex_count.unwrap())?
+            }
+            SectionType::ExtraVertColour if vertex_count.is_some() => {
+                ClumpData::parse_extra_vert_colour(data, vertex_count.unwrap())?
+            }
+            // Atomics have this plugin too, but only to say whether it's enabled
+            SectionType::MaterialEffectsPLG if data.len() > 4 => {
+                ClumpData::parse_material_effects(data, context.child(section_type))?
+            }
+            #[cfg(feature = "san_andreas_support")]
+            SectionType::PipelineSet if data.len() == 4 => ClumpData::parse_pipeline_set(data)?,
+            #[cfg(feature = "san_andreas_support")]
+            SectionType::_2dEffect => ClumpData::parse_effect_2d(data)?,
+            SectionType::ReflectionMaterial => ClumpData::parse_reflection_material(data)?,
+            SectionType::SpecularMaterial => ClumpData::parse_specular_material(data)?,
             _ if ClumpData::SUPPORTED_TYPES.contains(&section_type) => (data, ClumpData::Unknown),
-            _ => (&[] as &[u8], ClumpData::Unknown),
+            _ => (
+                &[] as &[u8],
+                ClumpData::Unparsed(UnparsedData(data.to_vec())),
+            ),
         };
 
+        let mut child_context = context.child(section_type);
         let mut children = vec![];
         while !data.is_empty() {
             let section: Section;
-            (data, section) = Section::parse(data, Some(section_type))?;
+            (data, section) = Section::parse(data, child_context, &children)?;
+            if let ClumpData::Geometry(geometry) = &section.data {
+                child_context.vertex_count = Some(geometry.vertex_count);
+            }
+            if let ClumpData::World(world) = &section.data {
+                child_context.world_format = Some(world.raw_format());
+            }
+            child_context.is_first_child = false;
             children.push(section);
         }
 
@@ -47,12 +208,30 @@ impl Section {
             Section {
                 section_type,
                 version,
+                build,
+                offset,
                 children,
                 data: section_data,
             },
         ))
     }
 
+    pub(crate) fn write(&self, out: &mut Vec<u8>) {
+        out.extend((self.section_type as u32).to_le_bytes());
+        // The size isn't known until the contents have been written
+        let size_offset = out.len();
+        out.extend(0u32.to_le_bytes());
+        out.extend(self.version.library_id(self.build).to_le_bytes());
+
+        let start = out.len();
+        self.data.write(out, self.version);
+        for child in &self.children {
+            child.write(out);
+        }
+        let size = (out.len() - start) as u32;
+        out[size_offset..size_offset + 4].copy_from_slice(&size.to_le_bytes());
+    }
+
     pub fn find_children_by_type(
         &self,
         section_type: SectionType,
@@ -69,4 +248,23 @@ impl Section {
     pub fn get_child_struct_data(&self) -> Option<&ClumpData> {
         Some(&self.find_child_by_type(SectionType::Struct)?.data)
     }
+
+    /// Like [`Section::find_child_by_type`], but reports a missing child as an error.
+    /// `path` is the path of this section, for the error.
+    pub fn require_child_by_type(
+        &self,
+        section_type: SectionType,
+        path: &str,
+    ) -> Result<&Section, Error> {
+        self.find_child_by_type(section_type)
+            .ok_or_else(|| self.missing_child(section_type, path))
+    }
+
+    pub fn missing_child(&self, section_type: SectionType, path: &str) -> Error {
+        Error::MissingChild {
+            child: section_type,
+            offset: self.offset,
+            path: path.to_string(),
+        }
+    }
 }
@@ -0,0 +1,154 @@
+use nom::number::complete as nc;
+
+use super::{checked_count, IResult, Mat4, RwVersion, UnparsedData};
+
+#[derive(Debug, PartialEq)]
+pub struct Skin {
+    pub bone_count: u8,
+    // As stored in the header; the old format has it, but not the list
+    pub used_bone_count: u8,
+    // Indices of the bones that are actually referenced by the vertices
+    pub used_bones: Vec<u8>,
+    pub max_weights_per_vertex: u8,
+    // Four per vertex
+    pub bone_indices: Vec<[u8; 4]>,
+    // Four per vertex; unused slots have a weight of zero
+    pub bone_weights: Vec<[f32; 4]>,
+    // One per bone, transforming from model space to bone space
+    pub inverse_bone_matrices: Vec<Mat4>,
+    // Newer versions follow the skin with split data for hardware skinning, which we
+    // don't interpret
+    pub split_data: UnparsedData,
+}
+impl Skin {
+    pub(crate) fn parse(
+        input: &[u8],
+        version: RwVersion,
+        vertex_count: u32,
+    ) -> IResult<&[u8], Self> {
+        let old_format = !version.skin_has_used_bones();
+
+        let (input, bone_count) = nc::le_u8(input)?;
+        let (input, used_bone_count) = nc::le_u8(input)?;
+        let (input, max_weights_per_vertex) = nc::le_u8(input)?;
+        let (input, _padding) = nc::le_u8(input)?;
+
+        let (input, used_bones) = nom::combinator::cond(
+            !old_format,
+            checked_count(nc::le_u8, used_bone_count as usize, 1),
+        )(input)?;
+
+        let (input, bone_indices) = checked_count(
+            |input| {
+                let (input, indices): (_, &[u8]) = nom::bytes::complete::take(4usize)(input)?;
+                Ok((input, [indices[0], indices[1], indices[2], indices[3]]))
+            },
+            vertex_count as usize,
+            4,
+        )(input)?;
+        let (input, bone_weights) = checked_count(
+            |input| {
+                let (input, w) = nom::multi::count(nc::le_f32, 4)(input)?;
+                Ok((input, [w[0], w[1], w[2], w[3]]))
+            },
+            vertex_count as usize,
+            16,
+        )(input)?;
+
+        let (input, inverse_bone_matrices) = checked_count(
+            |input| {
+                let (input, _marker) = nom::combinator::cond(old_format, nc::le_u32)(input)?;
+                Mat4::parse(input)
+            },
+            bone_count as usize,
+            64,
+        )(input)?;
+
+        let split_data = UnparsedData(input.to_vec());
+        let input = &input[input.len()..];
+
+        Ok((
+            input,
+            Skin {
+                bone_count,
+                used_bone_count,
+                used_bones: used_bones.unwrap_or_default(),
+                max_weights_per_vertex,
+                bone_indices,
+                bone_weights,
+                inverse_bone_matrices,
+                split_data,
+            },
+        ))
+    }
+
+    pub(crate) fn write(&self, out: &mut Vec<u8>, version: RwVersion) {
+        let old_format = !version.skin_has_used_bones();
+
+        out.extend([
+            self.bone_count,
+            self.used_bone_count,
+            self.max_weights_per_vertex,
+            0,
+        ]);
+        if !old_format {
+            out.extend(&self.used_bones);
+        }
+        out.extend(self.bone_indices.iter().flatten());
+        out.extend(
+            self.bone_weights
+                .iter()
+                .flatten()
+                .flat_map(|w| w.to_le_bytes()),
+        );
+        for matrix in &self.inverse_bone_matrices {
+            if old_format {
+                out.extend(0xDEADDEADu32.to_le_bytes());
+            }
+            matrix.write(out);
+        }
+        out.extend(&self.split_data.0);
+    }
+}
+
+mod tests {
+    #[test]
+    fn can_parse_old_and_new_skins() {
+        fn skin_bytes(old_format: bool) -> Vec<u8> {
+            // 2 bones, 1 used, 1 weight per vertex
+            let mut bytes = vec![2, 1, 1, 0];
+            if !old_format {
+                bytes.push(1);
+            }
+            bytes.extend([1, 0, 0, 0, 0, 1, 0, 0]);
+            for weights in [[1.0f32, 0.0, 0.0, 0.0], [0.5, 0.5, 0.0, 0.0]] {
+                bytes.extend(weights.iter().flat_map(|w| w.to_le_bytes()));
+            }
+            for bone in 0..2 {
+                if old_format {
+                    bytes.extend(0xDEADDEADu32.to_le_bytes());
+                }
+                bytes.extend((0..16).flat_map(|i| ((bone * 16 + i) as f32).to_le_bytes()));
+            }
+            bytes
+        }
+
+        for (old_format, version) in [
+            (true, super::RwVersion::VICE_CITY),
+            (false, super::RwVersion::SAN_ANDREAS),
+        ] {
+            let bytes = skin_bytes(old_format);
+            let (rest, skin) = super::Skin::parse(&bytes, version, 2).unwrap();
+            assert!(rest.is_empty());
+            assert_eq!(skin.bone_count, 2);
+            assert_eq!(skin.used_bones, if old_format { vec![] } else { vec![1] });
+            assert_eq!(skin.bone_indices, vec![[1, 0, 0, 0], [0, 1, 0, 0]]);
+            assert_eq!(skin.bone_weights[1], [0.5, 0.5, 0.0, 0.0]);
+            assert_eq!(skin.inverse_bone_matrices[1].0[0], 16.0);
+
+            let mut written = vec![];
+            skin.write(&mut written, version);
+            assert_eq!(written, bytes);
+        }
+    }
+}
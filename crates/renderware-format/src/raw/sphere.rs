@@ -1,8 +1,8 @@
-use nom::{number::complete as nc, sequence::tuple, IResult};
+use nom::{number::complete as nc, sequence::tuple};
 
-use super::Vec3;
+use super::{IResult, Vec3};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub struct Sphere {
     pub position: Vec3,
     pub radius: f32,
@@ -15,4 +15,9 @@ impl Sphere {
         let position = Vec3 { x, y, z };
         Ok((input, Sphere { position, radius }))
     }
+
+    pub(crate) fn write(&self, out: &mut Vec<u8>) {
+        self.position.write(out);
+        out.extend(self.radius.to_le_bytes());
+    }
 }
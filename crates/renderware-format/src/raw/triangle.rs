@@ -1,4 +1,6 @@
-use nom::{number::complete as nc, sequence::tuple, IResult};
+use nom::{number::complete as nc, sequence::tuple};
+
+use super::IResult;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub struct Triangle {
@@ -21,4 +23,10 @@ impl Triangle {
             },
         ))
     }
+
+    pub(crate) fn write(&self, out: &mut Vec<u8>) {
+        for value in [self.vertex2, self.vertex1, self.material_id, self.vertex3] {
+            out.extend(value.to_le_bytes());
+        }
+    }
 }
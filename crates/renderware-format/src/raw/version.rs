@@ -0,0 +1,127 @@
+/// A RenderWare version, stored as its four digits, e.g. `0x3_3002` for 3.3.0.2.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub struct RwVersion(pub u32);
+impl RwVersion {
+    // As used by most of GTA III's files
+    pub const GTA3: RwVersion = RwVersion(0x3_1001);
+    pub const VICE_CITY: RwVersion = RwVersion(0x3_3002);
+    pub const SAN_ANDREAS: RwVersion = RwVersion(0x3_6003);
+
+    /// Splits a library ID stamp into the version and, for newer stamps (3.1.0.1 onwards),
+    /// the build.
+    pub fn from_library_id(library_id: u32) -> (RwVersion, Option<u16>) {
+        if library_id & 0xFFFF0000 != 0 {
+            let version = ((library_id >> 14 & 0x3FF00) + 0x30000) | (library_id >> 16 & 0x3F);
+            (RwVersion(version), Some(library_id as u16))
+        } else {
+            (RwVersion(library_id << 8), None)
+        }
+    }
+
+    /// The inverse of [`RwVersion::from_library_id`].
+    pub fn library_id(&self, build: Option<u16>) -> u32 {
+        match build {
+            Some(build) => {
+                (self.0.wrapping_sub(0x30000) & 0x3FF00) << 14
+                    | (self.0 & 0x3F) << 16
+                    | build as u32
+            }
+            None => self.0 >> 8,
+        }
+    }
+
+    /// Whether files of this version can be parsed with the enabled features. GTA III's and
+    /// Vice City's versions are always supported; those from 3.6 onwards need
+    /// `san_andreas_support`.
+    pub fn is_supported(&self) -> bool {
+        self.0 < 0x3_6000 || cfg!(feature = "san_andreas_support")
+    }
+
+    pub(crate) fn material_has_surface_properties(&self) -> bool {
+        self.0 > 0x3_0400
+    }
+
+    pub(crate) fn geometry_has_surface_properties(&self) -> bool {
+        self.0 < 0x3_4000
+    }
+
+    pub(crate) fn clump_has_lights_and_cameras(&self) -> bool {
+        self.0 > 0x3_3000
+    }
+
+    // Before 3.3, lights stored the tangent of their cone angle instead
+    pub(crate) fn light_has_minus_cos_angle(&self) -> bool {
+        self.0 >= 0x3_3000
+    }
+
+    // Before 3.4.0.3, there was no list of used bones and each matrix was preceded by a
+    // 0xDEADDEAD marker
+    pub(crate) fn skin_has_used_bones(&self) -> bool {
+        self.0 >= 0x3_4003
+    }
+
+    pub(crate) fn texture_dictionary_has_device_id(&self) -> bool {
+        self.0 >= 0x3_6000
+    }
+}
+
+impl std::fmt::Display for RwVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}.{}.{}.{}",
+            self.0 >> 16,
+            self.0 >> 12 & 0xF,
+            self.0 >> 8 & 0xF,
+            self.0 & 0xFF
+        )
+    }
+}
+
+impl std::fmt::Debug for RwVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "RwVersion({})", self)
+    }
+}
+
+mod tests {
+    #[test]
+    fn converts_library_ids() {
+        use super::RwVersion;
+
+        for (library_id, version, build) in [
+            (0x0000_0310, RwVersion(0x3_1000), None),
+            (0x0401_FFFF, RwVersion::GTA3, Some(0xFFFF)),
+            (0x0C02_FFFF, RwVersion::VICE_CITY, Some(0xFFFF)),
+            (0x1803_FFFF, RwVersion::SAN_ANDREAS, Some(0xFFFF)),
+        ] {
+            assert_eq!(RwVersion::from_library_id(library_id), (version, build));
+            assert_eq!(version.library_id(build), library_id);
+        }
+        assert_eq!(RwVersion::SAN_ANDREAS.to_string(), "3.6.0.3");
+    }
+
+    #[test]
+    fn rejects_versions_of_disabled_games() {
+        use crate::raw::{BinaryStreamFile, Error};
+
+        for (library_id, enabled) in [
+            (0x0000_0310, true),
+            (0x0401_FFFF, true),
+            (0x0C02_FFFF, true),
+            (0x1803_FFFF, cfg!(feature = "san_andreas_support")),
+        ] {
+            let mut bytes = 0x10u32.to_le_bytes().to_vec();
+            bytes.extend(0u32.to_le_bytes());
+            bytes.extend(u32::to_le_bytes(library_id));
+            match BinaryStreamFile::from_bytes(&bytes) {
+                Ok(_) => assert!(enabled),
+                Err(Error::UnsupportedVersion { offset, .. }) => {
+                    assert!(!enabled);
+                    assert_eq!(offset, 8);
+                }
+                Err(err) => panic!("unexpected error: {}", err),
+            }
+        }
+    }
+}
@@ -0,0 +1,362 @@
+use nom::{combinator::cond, number::complete as nc, sequence::tuple};
+use num_traits::FromPrimitive;
+
+use super::{
+    checked_count,
+    constants::{GeometryFormat, PlaneAxis},
+    split_geometry_format, texture_sets_present, Color, IResult, Lighting, ParseError,
+    ParseErrorKind, RwVersion, Vec3,
+};
+
+// The header of a world (a BSP), whose geometry is split into sectors by a tree of planes
+#[derive(Debug, PartialEq)]
+pub struct World {
+    pub root_is_world_sector: bool,
+    pub inverse_origin: Vec3,
+    pub lighting: Option<Lighting>,
+    // The counts across all of the world's sectors
+    pub polygon_count: u32,
+    pub vertex_count: u32,
+    pub plane_sector_count: u32,
+    pub world_sector_count: u32,
+    pub col_sector_size: u32,
+    // Shared by every sector, which is laid out according to it
+    pub format: GeometryFormat,
+    pub texture_set_count: u8,
+    // The maximum and minimum corners, in that order; missing from some older files
+    pub bounding_box: Option<(Vec3, Vec3)>,
+}
+impl World {
+    pub(crate) fn parse(input: &[u8], version: RwVersion) -> IResult<&[u8], Self> {
+        let (input, (root_is_world_sector, inverse_origin)) =
+            tuple((nc::le_u32, Vec3::parse))(input)?;
+        let (input, lighting) =
+            cond(version.geometry_has_surface_properties(), Lighting::parse)(input)?;
+        let (
+            input,
+            (polygon_count, vertex_count, plane_sector_count, world_sector_count, col_sector_size),
+        ) = tuple((nc::le_u32, nc::le_u32, nc::le_u32, nc::le_u32, nc::le_u32))(input)?;
+        let (input, format) = nc::le_u32(input)?;
+        let (format, texture_set_count) = split_geometry_format(format);
+        let (input, bounding_box) =
+            cond(input.len() >= 24, tuple((Vec3::parse, Vec3::parse)))(input)?;
+
+        Ok((
+            input,
+            World {
+                root_is_world_sector: root_is_world_sector != 0,
+                inverse_origin,
+                lighting,
+                polygon_count,
+                vertex_count,
+                plane_sector_count,
+                world_sector_count,
+                col_sector_size,
+                format,
+                texture_set_count,
+                bounding_box,
+            },
+        ))
+    }
+
+    // Where the format is within the struct, for finding it without parsing the rest
+    pub(crate) fn format_offset(version: RwVersion) -> usize {
+        if version.geometry_has_surface_properties() {
+            48
+        } else {
+            36
+        }
+    }
+
+    // The format as stored, which the world's sectors need to parse themselves
+    pub(crate) fn raw_format(&self) -> u32 {
+        self.format.bits() | (self.texture_set_count as u32) << 16
+    }
+
+    pub(crate) fn write(&self, out: &mut Vec<u8>) {
+        out.extend((self.root_is_world_sector as u32).to_le_bytes());
+        self.inverse_origin.write(out);
+        if let Some(lighting) = &self.lighting {
+            lighting.write(out);
+        }
+        for value in [
+            self.polygon_count,
+            self.vertex_count,
+            self.plane_sector_count,
+            self.world_sector_count,
+            self.col_sector_size,
+            self.raw_format(),
+        ] {
+            out.extend(value.to_le_bytes());
+        }
+        if let Some((sup, inf)) = &self.bounding_box {
+            sup.write(out);
+            inf.write(out);
+        }
+    }
+}
+
+// A plane splitting the space of a world in two; its children are the sectors on the left
+// (below the plane) and right (above the plane), which may be planes themselves
+#[derive(Debug, PartialEq)]
+pub struct PlaneSection {
+    pub axis: PlaneAxis,
+    pub value: f32,
+    pub left_is_world_sector: bool,
+    pub right_is_world_sector: bool,
+    // The extents of the children along the axis, which may overlap the plane
+    pub left_value: f32,
+    pub right_value: f32,
+}
+impl PlaneSection {
+    pub(crate) fn parse(input: &[u8]) -> IResult<&[u8], Self> {
+        let start = input;
+        let (input, (axis, value, left_is_world_sector, right_is_world_sector)) =
+            tuple((nc::le_u32, nc::le_f32, nc::le_u32, nc::le_u32))(input)?;
+        let (input, (left_value, right_value)) = tuple((nc::le_f32, nc::le_f32))(input)?;
+        let axis = PlaneAxis::from_u32(axis).ok_or_else(|| {
+            nom::Err::Failure(ParseError::new(
+                start,
+                ParseErrorKind::Nom(nom::error::ErrorKind::MapOpt),
+            ))
+        })?;
+
+        Ok((
+            input,
+            PlaneSection {
+                axis,
+                value,
+                left_is_world_sector: left_is_world_sector != 0,
+                right_is_world_sector: right_is_world_sector != 0,
+                left_value,
+                right_value,
+            },
+        ))
+    }
+
+    pub(crate) fn write(&self, out: &mut Vec<u8>) {
+        out.extend((self.axis as u32).to_le_bytes());
+        out.extend(self.value.to_le_bytes());
+        out.extend((self.left_is_world_sector as u32).to_le_bytes());
+        out.extend((self.right_is_world_sector as u32).to_le_bytes());
+        out.extend(self.left_value.to_le_bytes());
+        out.extend(self.right_value.to_le_bytes());
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Polygon {
+    // Relative to the sector's material list window base
+    pub material_id: u16,
+    pub vertices: [u16; 3],
+}
+impl Polygon {
+    fn parse(input: &[u8]) -> IResult<&[u8], Self> {
+        let (input, (material_id, vertex1, vertex2, vertex3)) =
+            tuple((nc::le_u16, nc::le_u16, nc::le_u16, nc::le_u16))(input)?;
+        Ok((
+            input,
+            Polygon {
+                material_id,
+                vertices: [vertex1, vertex2, vertex3],
+            },
+        ))
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        out.extend(self.material_id.to_le_bytes());
+        for vertex in self.vertices {
+            out.extend(vertex.to_le_bytes());
+        }
+    }
+}
+
+// A leaf of a world's plane tree, holding the geometry within its bounds. Native worlds
+// keep their geometry in a plugin instead, leaving the vertices and polygons empty.
+#[derive(Debug, PartialEq)]
+pub struct AtomicSection {
+    // Added to the polygons' material IDs to index into the world's material list
+    pub material_list_window_base: u32,
+    pub polygon_count: u32,
+    pub vertex_count: u32,
+    // The minimum and maximum corners, in that order
+    pub bounding_box: (Vec3, Vec3),
+    pub collision_sector_present: u32,
+    pub unused: u32,
+
+    pub vertices: Vec<Vec3>,
+    // Each component is scaled to fit in a byte, followed by padding
+    pub normals: Option<Vec<[i8; 4]>>,
+    pub prelit_color: Option<Vec<Color>>,
+    pub texture_sets: Vec<Vec<(f32, f32)>>,
+    pub polygons: Vec<Polygon>,
+}
+impl AtomicSection {
+    /// `world_format` is the format of the enclosing world, as stored.
+    pub(crate) fn parse(input: &[u8], world_format: u32) -> IResult<&[u8], Self> {
+        let (format, texture_set_count) = split_geometry_format(world_format);
+        let has_data = !format.contains(GeometryFormat::NATIVE);
+
+        let (input, (material_list_window_base, polygon_count, vertex_count)) =
+            tuple((nc::le_u32, nc::le_u32, nc::le_u32))(input)?;
+        let (input, bounding_box) = tuple((Vec3::parse, Vec3::parse))(input)?;
+        let (input, (collision_sector_present, unused)) = tuple((nc::le_u32, nc::le_u32))(input)?;
+
+        let vertex_count_present = if has_data { vertex_count as usize } else { 0 };
+        let (input, vertices) = checked_count(Vec3::parse, vertex_count_present, 12)(input)?;
+        let (input, normals) = cond(
+            has_data && format.contains(GeometryFormat::NORMALS),
+            checked_count(
+                |input| {
+                    let (input, (x, y, z, w)) =
+                        tuple((nc::le_i8, nc::le_i8, nc::le_i8, nc::le_i8))(input)?;
+                    Ok((input, [x, y, z, w]))
+                },
+                vertex_count_present,
+                4,
+            ),
+        )(input)?;
+        let (input, prelit_color) = cond(
+            has_data && format.contains(GeometryFormat::PRELIT),
+            checked_count(Color::parse, vertex_count_present, 4),
+        )(input)?;
+
+        let texture_set_count = if has_data {
+            texture_sets_present(format, texture_set_count) as usize
+        } else {
+            0
+        };
+        let tex_coord_parser = |input| tuple((nc::le_f32, nc::le_f32))(input);
+        let (input, texture_sets) = nom::multi::count(
+            checked_count(tex_coord_parser, vertex_count_present, 8),
+            texture_set_count,
+        )(input)?;
+
+        let polygon_count_present = if has_data { polygon_count as usize } else { 0 };
+        let (input, polygons) = checked_count(Polygon::parse, polygon_count_present, 8)(input)?;
+
+        Ok((
+            input,
+            AtomicSection {
+                material_list_window_base,
+                polygon_count,
+                vertex_count,
+                bounding_box,
+                collision_sector_present,
+                unused,
+
+                vertices,
+                normals,
+                prelit_color,
+                texture_sets,
+                polygons,
+            },
+        ))
+    }
+
+    pub(crate) fn write(&self, out: &mut Vec<u8>) {
+        for value in [
+            self.material_list_window_base,
+            self.polygon_count,
+            self.vertex_count,
+        ] {
+            out.extend(value.to_le_bytes());
+        }
+        self.bounding_box.0.write(out);
+        self.bounding_box.1.write(out);
+        out.extend(self.collision_sector_present.to_le_bytes());
+        out.extend(self.unused.to_le_bytes());
+
+        for vertex in &self.vertices {
+            vertex.write(out);
+        }
+        for normal in self.normals.iter().flatten() {
+            out.extend(normal.map(|c| c as u8));
+        }
+        for color in self.prelit_color.iter().flatten() {
+            color.write(out);
+        }
+        for (u, v) in self.texture_sets.iter().flatten() {
+            out.extend(u.to_le_bytes());
+            out.extend(v.to_le_bytes());
+        }
+        for polygon in &self.polygons {
+            polygon.write(out);
+        }
+    }
+}
+
+mod tests {
+    #[test]
+    fn can_round_trip_world_sectors() {
+        use crate::raw::{constants::PlaneAxis, BinaryStreamFile, ClumpData};
+        use crate::test_util::{floats, section};
+        // A triangle with normals and one texture set, split across the X axis from nothing
+        fn atomic_section(x: f32) -> Vec<u8> {
+            let mut data = vec![2, 0, 0, 0, 1, 0, 0, 0, 3, 0, 0, 0];
+            data.extend(floats(&[x, 0.0, 0.0, x + 1.0, 1.0, 0.0]));
+            data.extend([0; 8]);
+            data.extend(floats(&[x, 0.0, 0.0, x + 1.0, 0.0, 0.0, x, 1.0, 0.0]));
+            data.extend([0, 0, 127, 0, 0, 0, 127, 0, 0, 0, 127, 0]);
+            data.extend(floats(&[0.0, 0.0, 1.0, 0.0, 0.0, 1.0]));
+            data.extend([1, 0, 0, 0, 1, 0, 2, 0]);
+            let mut bytes = section(0x01, &data);
+            bytes.extend(section(0x03, &[]));
+            section(0x09, &bytes)
+        }
+
+        // Positions, textured, normals and lit
+        let mut world = vec![0, 0, 0, 0];
+        world.extend(floats(&[0.0, 0.0, 0.0, 1.0, 1.0, 1.0]));
+        world.extend([2, 0, 0, 0, 6, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0]);
+        world.extend(0x36u32.to_le_bytes());
+        world.extend(floats(&[2.0, 1.0, 0.0, 0.0, 0.0, 0.0]));
+        let material_list = section(0x01, &[0; 4]);
+        let mut plane = vec![0, 0, 0, 0];
+        plane.extend(1.0f32.to_le_bytes());
+        plane.extend([1, 0, 0, 0, 1, 0, 0, 0]);
+        plane.extend(floats(&[1.0, 1.0]));
+        let mut plane = section(0x01, &plane);
+        plane.extend(atomic_section(0.0));
+        plane.extend(atomic_section(1.0));
+
+        let mut children = section(0x01, &world);
+        children.extend(section(0x08, &material_list));
+        children.extend(section(0x0A, &plane));
+        children.extend(section(0x03, &[]));
+        let bytes = section(0x0B, &children);
+
+        let file = BinaryStreamFile::from_bytes(&bytes).unwrap();
+        let world = &file.sections[0];
+        match &world.children[0].data {
+            ClumpData::World(world) => {
+                assert!(!world.root_is_world_sector);
+                assert_eq!(world.world_sector_count, 2);
+                assert!(world.bounding_box.is_some());
+            }
+            data => panic!("unexpected data: {:?}", data),
+        }
+        let plane = &world.children[2];
+        match &plane.children[0].data {
+            ClumpData::PlaneSection(plane) => {
+                assert_eq!(plane.axis, PlaneAxis::X);
+                assert_eq!(plane.value, 1.0);
+                assert!(plane.left_is_world_sector && plane.right_is_world_sector);
+            }
+            data => panic!("unexpected data: {:?}", data),
+        }
+        match &plane.children[2].children[0].data {
+            ClumpData::AtomicSection(sector) => {
+                assert_eq!(sector.material_list_window_base, 2);
+                assert_eq!(sector.vertices[1].x, 2.0);
+                assert_eq!(sector.normals.as_ref().unwrap()[0], [0, 0, 127, 0]);
+                assert_eq!(sector.texture_sets.len(), 1);
+                assert_eq!(sector.polygons[0].material_id, 1);
+                assert_eq!(sector.polygons[0].vertices, [0, 1, 2]);
+            }
+            data => panic!("unexpected data: {:?}", data),
+        }
+
+        assert_eq!(file.to_bytes(), bytes);
+    }
+}
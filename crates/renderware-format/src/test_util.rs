@@ -0,0 +1,38 @@
+// Builders for the files and sections the tests parse
+
+use crate::raw::{constants::SectionType, ClumpData, RwVersion, Section};
+
+/// A section's bytes: its header, stamped with `library_id`, followed by `data`.
+pub(crate) fn section_with_id(section_type: u32, library_id: u32, data: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![];
+    bytes.extend(section_type.to_le_bytes());
+    bytes.extend((data.len() as u32).to_le_bytes());
+    bytes.extend(library_id.to_le_bytes());
+    bytes.extend(data);
+    bytes
+}
+
+/// A section's bytes, stamped with 3.3.0.2, as used by Vice City.
+pub(crate) fn section(section_type: u32, data: &[u8]) -> Vec<u8> {
+    section_with_id(section_type, 0x0C02FFFF, data)
+}
+
+pub(crate) fn floats(values: &[f32]) -> Vec<u8> {
+    values.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+/// An already parsed Vice City section.
+pub(crate) fn parsed_section(
+    section_type: SectionType,
+    data: ClumpData,
+    children: Vec<Section>,
+) -> Section {
+    Section {
+        section_type,
+        version: RwVersion::VICE_CITY,
+        build: Some(0xFFFF),
+        offset: 0,
+        children,
+        data,
+    }
+}
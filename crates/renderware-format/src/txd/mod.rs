@@ -0,0 +1,989 @@
+use std::borrow::Cow;
+
+use crate::raw::{
+    constants::SectionType, BinaryStreamFile, ClumpData, Error, Raster, RasterPlatform, RwVersion,
+    Section, TextureDictionary, UnparsedData,
+};
+
+mod platform;
+
+pub use crate::raw::{
+    constants::{RasterFormat, TextureAddressing, TextureFiltering},
+    Color,
+};
+
+// How a texture's levels are laid out
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PixelFormat {
+    Rgba8,
+    // DXT1, with 8 bytes to each 4x4 block
+    Bc1,
+    // DXT3, with 16 bytes to each 4x4 block
+    Bc2,
+    // DXT5, with 16 bytes to each 4x4 block
+    Bc3,
+}
+
+impl PixelFormat {
+    pub fn is_compressed(self) -> bool {
+        self != PixelFormat::Rgba8
+    }
+
+    /// The width and height of a block of pixels, and the bytes it takes up.
+    pub fn block_size(self) -> (u32, usize) {
+        match self.squish() {
+            Some(format) => (4, format.block_size()),
+            None => (1, 4),
+        }
+    }
+
+    /// The bytes taken up by an image of this size, rounded up to whole blocks.
+    pub fn data_size(self, width: u32, height: u32) -> usize {
+        let (block, block_bytes) = self.block_size();
+        let blocks = |size: u32| size.div_ceil(block) as usize;
+        blocks(width) * blocks(height) * block_bytes
+    }
+
+    /// Decodes `data` into RGBA8.
+    pub fn decompress(self, data: &[u8], width: u32, height: u32) -> Vec<u8> {
+        match self.squish() {
+            Some(format) => {
+                let mut uncompressed = vec![0u8; (width * height * 4) as usize];
+                format.decompress(data, width as usize, height as usize, &mut uncompressed);
+                uncompressed
+            }
+            None => data.to_vec(),
+        }
+    }
+
+    /// Encodes RGBA8 `data` into this format.
+    pub fn compress(self, data: &[u8], width: u32, height: u32) -> Vec<u8> {
+        match self.squish() {
+            Some(format) => {
+                let mut compressed = vec![0u8; self.data_size(width, height)];
+                format.compress(
+                    data,
+                    width as usize,
+                    height as usize,
+                    Default::default(),
+                    &mut compressed,
+                );
+                compressed
+            }
+            None => data.to_vec(),
+        }
+    }
+
+    fn squish(self) -> Option<squish::Format> {
+        match self {
+            PixelFormat::Rgba8 => None,
+            PixelFormat::Bc1 => Some(squish::Format::Bc1),
+            PixelFormat::Bc2 => Some(squish::Format::Bc2),
+            PixelFormat::Bc3 => Some(squish::Format::Bc3),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone)]
+pub struct Texture {
+    pub filtering: TextureFiltering,
+    pub uv: (TextureAddressing, TextureAddressing),
+
+    pub name: String,
+    pub mask_name: String,
+
+    pub width: u16,
+    pub height: u16,
+
+    // The format of the raster this came from
+    pub raster_format: RasterFormat,
+    pub format: PixelFormat,
+    // Data in `format` for the full-size level, followed by each mip level; there's always
+    // at least one level
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl std::fmt::Debug for Texture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Texture")
+            .field("filtering", &self.filtering)
+            .field("uv", &self.uv)
+            .field("name", &self.name)
+            .field("mask_name", &self.mask_name)
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("raster_format", &self.raster_format)
+            .field("format", &self.format)
+            .field("level_count", &self.levels.len())
+            .finish()
+    }
+}
+
+impl Texture {
+    /// Reads every texture in the first dictionary, decoding each into RGBA8.
+    pub fn from_raw(raw: &BinaryStreamFile) -> Result<Vec<Texture>, Error> {
+        Self::from_raw_with(raw, false)
+    }
+
+    /// Reads every texture in the first dictionary, keeping DXT-compressed ones as they are so
+    /// that they can be uploaded without decoding; everything else is decoded into RGBA8.
+    pub fn from_raw_compressed(raw: &BinaryStreamFile) -> Result<Vec<Texture>, Error> {
+        Self::from_raw_with(raw, true)
+    }
+
+    fn from_raw_with(raw: &BinaryStreamFile, keep_compressed: bool) -> Result<Vec<Texture>, Error> {
+        let main = raw
+            .texture_dictionaries()
+            .next()
+            .ok_or_else(|| raw.missing_section(SectionType::TextureDictionary))?;
+
+        main.find_children_by_type(SectionType::Raster)
+            .enumerate()
+            .filter_map(|(index, section)| {
+                let raster = match section.get_child_struct_data()? {
+                    ClumpData::Raster(r) => Some(Cow::Borrowed(r)),
+                    ClumpData::Ps2TextureNative(native) => {
+                        Raster::from_ps2_section(section, native).map(Cow::Owned)
+                    }
+                    _ => return None,
+                };
+                let texture = raster
+                    .ok_or(DecodeError::Truncated)
+                    .and_then(|r| Self::from_raster(&r, keep_compressed));
+                Some(texture.map_err(|err| {
+                    let offset = section.offset;
+                    let path = format!("TextureDictionary/Raster[{}]", index);
+                    match err {
+                        DecodeError::Unsupported(feature) => Error::Unsupported {
+                            feature,
+                            offset,
+                            path,
+                        },
+                        DecodeError::Truncated => Error::TruncatedData { offset, path },
+                    }
+                }))
+            })
+            .collect()
+    }
+
+    // Decodes each level of the raster with the decoder for its platform, unless they're
+    // compressed and that's to be kept
+    fn from_raster(raster: &Raster, keep_compressed: bool) -> Result<Texture, DecodeError> {
+        let decoder = platform::decoder(raster);
+        let format = decoder
+            .compressed_format(raster)
+            .filter(|_| keep_compressed)
+            .unwrap_or(PixelFormat::Rgba8);
+        let levels = (0..raster.levels.len())
+            .map(|level| match format {
+                PixelFormat::Rgba8 => decoder.decode_level(raster, level),
+                _ => copy_level(raster, level, format),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Texture {
+            filtering: raster.filtering,
+            uv: raster.uv,
+            name: raster.name.to_string(),
+            mask_name: raster.mask_name.to_string(),
+            width: raster.width,
+            height: raster.height,
+            raster_format: raster.raster_format,
+            format,
+            levels,
+        })
+    }
+
+    /// The width and height of a level, each halving with every level down to 1.
+    pub fn level_size(&self, level: usize) -> (u16, u16) {
+        level_size(self.width, self.height, level)
+    }
+
+    /// This texture with its levels decoded into RGBA8, if they aren't already.
+    pub fn decompressed(&self) -> Cow<'_, Texture> {
+        if !self.format.is_compressed() {
+            return Cow::Borrowed(self);
+        }
+        let levels = self
+            .levels
+            .iter()
+            .enumerate()
+            .map(|(level, data)| {
+                let (width, height) = self.level_size(level);
+                self.format.decompress(data, width as u32, height as u32)
+            })
+            .collect();
+        Cow::Owned(Texture {
+            format: PixelFormat::Rgba8,
+            levels,
+            ..self.clone()
+        })
+    }
+
+    /// The colours of the full-size level.
+    pub fn as_colors(&self) -> impl Iterator<Item = Color> {
+        let (width, height) = (self.width as u32, self.height as u32);
+        self.format
+            .decompress(&self.levels[0], width, height)
+            .chunks_exact(4)
+            .map(|c| Color::new(c[0], c[1], c[2], c[3]))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    /// Builds a texture out of an RGBA8 image, with mip levels down to a single pixel, each
+    /// encoded in `format`. Drop all but the first level for a texture without mipmaps.
+    ///
+    /// Fails if the image is empty, or `data` isn't `width * height` pixels.
+    pub fn from_rgba8(
+        name: &str,
+        filtering: TextureFiltering,
+        uv: (TextureAddressing, TextureAddressing),
+        width: u16,
+        height: u16,
+        data: &[u8],
+        format: PixelFormat,
+    ) -> Result<Texture, Error> {
+        let (width32, height32) = (width as u32, height as u32);
+        if width == 0 || height == 0 || data.len() != (width32 * height32 * 4) as usize {
+            return Err(Error::InvalidImageSize {
+                width,
+                height,
+                len: data.len(),
+            });
+        }
+
+        let levels: Vec<_> = complete_mip_chain(vec![data.to_vec()], width32, height32)
+            .into_iter()
+            .enumerate()
+            .map(|(level, data)| {
+                let (width, height) = level_size(width, height, level);
+                format.compress(&data, width as u32, height as u32)
+            })
+            .collect();
+        // DXT1 only keeps alpha below half, so this goes by what survived encoding
+        let has_alpha = format
+            .decompress(&levels[0], width32, height32)
+            .chunks_exact(4)
+            .any(|p| p[3] != 255);
+        Ok(Texture {
+            filtering,
+            uv,
+            name: name.to_string(),
+            mask_name: String::new(),
+            width,
+            height,
+            raster_format: raster_format(format, has_alpha, levels.len() > 1),
+            format,
+            levels,
+        })
+    }
+
+    /// Writes a PC texture dictionary, as Vice City reads them, holding `textures`.
+    pub fn to_raw(textures: &[Texture]) -> BinaryStreamFile {
+        let section = |section_type, data, children| Section {
+            section_type,
+            version: RwVersion::VICE_CITY,
+            build: Some(0xFFFF),
+            offset: 0,
+            children,
+            data,
+        };
+        let extension = || {
+            section(
+                SectionType::Extension,
+                ClumpData::Unparsed(UnparsedData(vec![])),
+                vec![],
+            )
+        };
+
+        let mut children = vec![section(
+            SectionType::Struct,
+            ClumpData::TextureDictionary(TextureDictionary {
+                texture_count: textures.len() as u32,
+                device_id: None,
+            }),
+            vec![],
+        )];
+        children.extend(textures.iter().map(|texture| {
+            let raster = section(
+                SectionType::Struct,
+                ClumpData::Raster(texture.to_raster()),
+                vec![],
+            );
+            section(
+                SectionType::Raster,
+                ClumpData::Unknown,
+                vec![raster, extension()],
+            )
+        }));
+        children.push(extension());
+
+        BinaryStreamFile {
+            sections: vec![section(
+                SectionType::TextureDictionary,
+                ClumpData::Unknown,
+                children,
+            )],
+            padding: UnparsedData(vec![]),
+        }
+    }
+
+    /// The Direct3D 8 raster for this texture: DXT-compressed textures stay as they are, and
+    /// everything else is written as 8888.
+    pub fn to_raster(&self) -> Raster {
+        let has_alpha = self.as_colors().any(|c| c.a != 255);
+        let levels = match self.format {
+            // Stored as BGRA
+            PixelFormat::Rgba8 => self
+                .levels
+                .iter()
+                .map(|level| {
+                    level
+                        .chunks_exact(4)
+                        .flat_map(|p| [p[2], p[1], p[0], p[3]])
+                        .collect()
+                })
+                .collect(),
+            _ => self.levels.clone(),
+        };
+        Raster {
+            filtering: self.filtering,
+            uv: self.uv,
+            flags: 0,
+            name: self.name.as_str().into(),
+            mask_name: self.mask_name.as_str().into(),
+            raster_format: raster_format(self.format, has_alpha, levels.len() > 1),
+            has_alpha,
+            width: self.width,
+            height: self.height,
+            depth: match self.format {
+                PixelFormat::Rgba8 => 32,
+                _ => 16,
+            },
+            level_count: levels.len() as u8,
+            // A texture, rather than a camera or Z-buffer
+            raster_type: 4,
+            compression: match self.format {
+                PixelFormat::Rgba8 => 0,
+                PixelFormat::Bc1 => 1,
+                PixelFormat::Bc2 => 3,
+                PixelFormat::Bc3 => 5,
+            },
+            palette: vec![],
+            levels,
+            remainder: UnparsedData(vec![]),
+            platform: RasterPlatform::D3d8,
+        }
+    }
+}
+
+// The raster format that Direct3D 8 rasters in `format` are written with
+fn raster_format(format: PixelFormat, has_alpha: bool, has_mipmaps: bool) -> RasterFormat {
+    let scheme = match format {
+        PixelFormat::Rgba8 => 0x0500,
+        PixelFormat::Bc1 if has_alpha => 0x0100,
+        PixelFormat::Bc1 => 0x0200,
+        PixelFormat::Bc2 | PixelFormat::Bc3 => 0x0300,
+    };
+    let mipmaps = if has_mipmaps { 0x8000 } else { 0 };
+    RasterFormat::new(scheme | mipmaps)
+}
+
+enum DecodeError {
+    Unsupported(String),
+    // The data is too short for the raster's dimensions
+    Truncated,
+}
+
+fn level_size(width: u16, height: u16, level: usize) -> (u16, u16) {
+    let halve = |size: u16| size.checked_shr(level as u32).unwrap_or(0).max(1);
+    (halve(width), halve(height))
+}
+
+// Fills out the levels a texture doesn't have by averaging each 2x2 block of the level before
+pub(crate) fn complete_mip_chain(
+    mut levels: Vec<Vec<u8>>,
+    width: u32,
+    height: u32,
+) -> Vec<Vec<u8>> {
+    let size = |level: usize| ((width >> level).max(1), (height >> level).max(1));
+    while size(levels.len() - 1) != (1, 1) {
+        let (previous_width, previous_height) = size(levels.len() - 1);
+        let (level_width, level_height) = size(levels.len());
+        let previous = levels.last().unwrap();
+        let mut level = Vec::with_capacity((level_width * level_height * 4) as usize);
+        for y in 0..level_height {
+            for x in 0..level_width {
+                let sample = |dx: u32, dy: u32| {
+                    let x = (x * 2 + dx).min(previous_width - 1);
+                    let y = (y * 2 + dy).min(previous_height - 1);
+                    let offset = ((y * previous_width + x) * 4) as usize;
+                    &previous[offset..offset + 4]
+                };
+                let samples = [sample(0, 0), sample(1, 0), sample(0, 1), sample(1, 1)];
+                level.extend(
+                    (0..4).map(|c| (samples.iter().map(|s| s[c] as u32).sum::<u32>() / 4) as u8),
+                );
+            }
+        }
+        levels.push(level);
+    }
+    levels
+}
+
+// Copies a level of a compressed raster as-is, dropping any padding after its blocks
+fn copy_level(raster: &Raster, level: usize, format: PixelFormat) -> Result<Vec<u8>, DecodeError> {
+    let (width, height) = level_size(raster.width, raster.height, level);
+    let size = format.data_size(width as u32, height as u32);
+    match raster.levels[level].get(..size) {
+        Some(data) => Ok(data.to_vec()),
+        None => Err(DecodeError::Truncated),
+    }
+}
+
+mod tests {
+    #[test]
+    fn fills_out_missing_mip_levels() {
+        // A 4x2 texture with only its first two levels; the second is left alone
+        let first = [[255, 0, 0, 255], [0, 0, 255, 255]].repeat(4).concat();
+        let second = vec![10; 8];
+        let levels = super::complete_mip_chain(vec![first, second.clone()], 4, 2);
+        assert_eq!(levels.len(), 3);
+        assert_eq!(levels[1], second);
+        assert_eq!(levels[2], [10, 10, 10, 10]);
+
+        // Down to a single pixel, averaging as it goes
+        let levels = super::complete_mip_chain(vec![vec![0, 0, 0, 255, 255, 255, 255, 255]], 2, 1);
+        assert_eq!(
+            levels,
+            [
+                vec![0, 0, 0, 255, 255, 255, 255, 255],
+                vec![127, 127, 127, 255]
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_each_games_rasters() {
+        use crate::raw::{BinaryStreamFile, RwVersion};
+        use crate::test_util::section_with_id;
+
+        // A 4x4 white DXT1 texture, in Direct3D 8's layout before 3.6 and Direct3D 9's after
+        let dictionary = |library_id: u32, version: RwVersion| {
+            let section =
+                |section_type, data: &[u8]| section_with_id(section_type, library_id, data);
+            let is_d3d9 = version >= RwVersion(0x3_6000);
+
+            let mut raster = vec![if is_d3d9 { 9 } else { 8 }, 0, 0, 0, 0x06, 0x11, 0, 0];
+            raster.extend(b"white");
+            raster.extend([0; 59]);
+            raster.extend([0x00, 0x02, 0, 0]);
+            raster.extend(if is_d3d9 { *b"DXT1" } else { [0; 4] });
+            raster.extend([4, 0, 4, 0, 16, 1, 4, if is_d3d9 { 0x08 } else { 1 }]);
+            raster.extend([8, 0, 0, 0, 0xFF, 0xFF, 0, 0, 0, 0, 0, 0]);
+            let mut raster_children = section(0x01, &raster);
+            raster_children.extend(section(0x03, &[]));
+
+            let mut children = section(0x01, &[1, 0, 0, 0]);
+            children.extend(section(0x15, &raster_children));
+            children.extend(section(0x03, &[]));
+            section(0x16, &children)
+        };
+
+        let mut versions = vec![
+            (0x0401FFFF, RwVersion::GTA3),
+            (0x0C02FFFF, RwVersion::VICE_CITY),
+        ];
+        if cfg!(feature = "san_andreas_support") {
+            versions.push((0x1803FFFF, RwVersion::SAN_ANDREAS));
+        }
+        for (library_id, version) in versions {
+            let bytes = dictionary(library_id, version);
+            let raw = BinaryStreamFile::from_bytes(&bytes).unwrap();
+            assert_eq!(raw.to_bytes(), bytes);
+
+            let textures = super::Texture::from_raw(&raw).unwrap();
+            assert_eq!(textures[0].name, "white");
+            assert_eq!((textures[0].width, textures[0].height), (4, 4));
+            assert!(textures[0].levels[0].iter().all(|b| *b == 255));
+        }
+    }
+
+    #[test]
+    fn decodes_every_raster_format() {
+        use super::platform::decoder;
+        use crate::raw::{
+            constants::{RasterFormat, TextureAddressing, TextureFiltering},
+            Color, Raster, UnparsedData,
+        };
+
+        // A 2x1 raster in `format`
+        fn raster(format: u32, depth: u8, palette: Vec<Color>, data: Vec<u8>) -> Raster {
+            Raster {
+                filtering: TextureFiltering::Nearest,
+                uv: (TextureAddressing::Wrap, TextureAddressing::Wrap),
+                flags: 0,
+                name: Default::default(),
+                mask_name: Default::default(),
+                raster_format: RasterFormat::new(format),
+                has_alpha: false,
+                width: 2,
+                height: 1,
+                depth,
+                level_count: 1,
+                raster_type: 4,
+                compression: 0,
+                palette,
+                levels: vec![data],
+                remainder: UnparsedData(vec![]),
+                platform: crate::raw::RasterPlatform::D3d8,
+            }
+        }
+        let try_decode = |raster: Raster| decoder(&raster).decode_level(&raster, 0);
+        let decode = |raster: Raster| try_decode(raster).ok().unwrap();
+
+        // Each is red, then semi-transparent blue or as close as the format gets
+        let palette = vec![
+            Color::new(255, 0, 0, 255),
+            Color::new(0, 0, 255, 128),
+            Color::new(0, 255, 0, 255),
+        ];
+        let cases = [
+            (0x0500, 32, vec![], vec![0, 0, 255, 255, 255, 0, 0, 128]),
+            (0x0600, 32, vec![], vec![0, 0, 255, 0, 255, 0, 0, 0]),
+            (0x0600, 24, vec![], vec![0, 0, 255, 255, 0, 0]),
+            (0x0100, 16, vec![], vec![0x00, 0xFC, 0x1F, 0x00]),
+            (0x0A00, 16, vec![], vec![0x00, 0x7C, 0x1F, 0x00]),
+            (0x0200, 16, vec![], vec![0x00, 0xF8, 0x1F, 0x00]),
+            (0x0300, 16, vec![], vec![0x00, 0xFF, 0x0F, 0x80]),
+            (0x2500, 8, palette.clone(), vec![0, 1]),
+            (0x4500, 4, palette.clone(), vec![0, 1]),
+            // Packed two indices to a byte
+            (0x4500, 4, palette.clone(), vec![0x10]),
+            (0x2600, 8, palette, vec![0, 1]),
+        ];
+        for (format, depth, palette, data) in cases {
+            let opaque = matches!(format & 0x0F00, 0x0200 | 0x0600 | 0x0A00);
+            let decoded = decode(raster(format, depth, palette, data));
+            let (red, blue) = decoded.split_at(4);
+            assert_eq!(red, [255, 0, 0, 255], "format {:#X}", format);
+            assert_eq!(&blue[..3], [0, 0, 255], "format {:#X}", format);
+            match (format, opaque) {
+                (_, true) => assert_eq!(blue[3], 255, "format {:#X}", format),
+                // One bit of alpha
+                (0x0100, _) => assert_eq!(blue[3], 0),
+                // Four bits of alpha
+                (0x0300, _) => assert_eq!(blue[3], 136),
+                _ => assert_eq!(blue[3], 128, "format {:#X}", format),
+            }
+        }
+
+        assert_eq!(
+            decode(raster(0x0400, 8, vec![], vec![0, 200]))[4..],
+            [200, 200, 200, 255]
+        );
+
+        // A 4x4 DXT3 block: red throughout, with alpha ramping up across each row in steps of
+        // 4 bits, which squish widens by shifting
+        let mut block = vec![0x40, 0xC8, 0x40, 0xC8, 0x40, 0xC8, 0x40, 0xC8];
+        block.extend([0x00, 0xF8, 0x00, 0xF8, 0, 0, 0, 0]);
+        let mut dxt3 = raster(0x0300, 16, vec![], block);
+        (dxt3.width, dxt3.height, dxt3.compression) = (4, 4, 3);
+        let dxt3 = decode(dxt3);
+        assert_eq!(dxt3[..4], [255, 0, 0, 0]);
+        assert_eq!(dxt3[12..16], [255, 0, 0, 192]);
+
+        // Too little data, and formats with no colours
+        assert!(try_decode(raster(0x0500, 32, vec![], vec![0; 4])).is_err());
+        assert!(try_decode(raster(0x0000, 32, vec![], vec![0; 8])).is_err());
+    }
+
+    #[test]
+    fn parses_palettes_before_the_data() {
+        use crate::raw::{BinaryStreamFile, ClumpData};
+        use crate::test_util::section;
+
+        // A 2x2 PAL4 raster, whose palette has 32 entries on PC
+        let mut raster = vec![8, 0, 0, 0, 0x01, 0x11, 0, 0];
+        raster.extend(b"font");
+        raster.extend([0; 60]);
+        raster.extend([0x00, 0x45, 0, 0, 1, 0, 0, 0]);
+        raster.extend([2, 0, 2, 0, 4, 1, 4, 0]);
+        for index in 0..32u8 {
+            raster.extend([index * 8, 0, 0, 255]);
+        }
+        raster.extend([4, 0, 0, 0, 0, 1, 2, 3]);
+        let mut raster_children = section(0x01, &raster);
+        raster_children.extend(section(0x03, &[]));
+        let mut children = section(0x01, &[1, 0, 0, 0]);
+        children.extend(section(0x15, &raster_children));
+        children.extend(section(0x03, &[]));
+        let bytes = section(0x16, &children);
+
+        let raw = BinaryStreamFile::from_bytes(&bytes).unwrap();
+        match raw.sections[0].children[1].get_child_struct_data() {
+            Some(ClumpData::Raster(raster)) => {
+                assert_eq!(raster.palette.len(), 32);
+                assert_eq!(raster.levels, [[0, 1, 2, 3]]);
+            }
+            data => panic!("unexpected data: {:?}", data),
+        }
+        assert_eq!(raw.to_bytes(), bytes);
+
+        let textures = super::Texture::from_raw(&raw).unwrap();
+        let reds: Vec<_> = textures[0].as_colors().map(|c| c.r).collect();
+        assert_eq!(reds, [0, 8, 16, 24]);
+    }
+
+    #[test]
+    fn keeps_every_mip_level() {
+        use crate::raw::BinaryStreamFile;
+        use crate::test_util::section;
+
+        // A 4x2 8888 raster with three levels, each a solid grey that darkens with each level
+        let mut raster = vec![8, 0, 0, 0, 0x06, 0x11, 0, 0];
+        raster.extend(b"road");
+        raster.extend([0; 60]);
+        raster.extend([0x00, 0x85, 0, 0, 1, 0, 0, 0]);
+        raster.extend([4, 0, 2, 0, 32, 3, 4, 0]);
+        for (level, pixel_count) in [8u8, 2, 1].into_iter().enumerate() {
+            let grey = 255 - level as u8 * 100;
+            raster.extend((pixel_count as u32 * 4).to_le_bytes());
+            for _ in 0..pixel_count {
+                raster.extend([grey, grey, grey, 255]);
+            }
+        }
+        let mut raster_children = section(0x01, &raster);
+        raster_children.extend(section(0x03, &[]));
+        let mut children = section(0x01, &[1, 0, 0, 0]);
+        children.extend(section(0x15, &raster_children));
+        children.extend(section(0x03, &[]));
+        let bytes = section(0x16, &children);
+
+        let raw = BinaryStreamFile::from_bytes(&bytes).unwrap();
+        assert_eq!(raw.to_bytes(), bytes);
+
+        let texture = &super::Texture::from_raw(&raw).unwrap()[0];
+        assert_eq!(texture.levels.len(), 3);
+        for (level, (size, grey)) in [((4, 2), 255), ((2, 1), 155), ((1, 1), 55)]
+            .into_iter()
+            .enumerate()
+        {
+            assert_eq!(texture.level_size(level), size);
+            let (width, height) = size;
+            assert_eq!(
+                texture.levels[level].len(),
+                width as usize * height as usize * 4
+            );
+            assert_eq!(texture.levels[level][..4], [grey, grey, grey, 255]);
+        }
+    }
+
+    #[test]
+    fn keeps_dxt_blocks_when_asked() {
+        use super::PixelFormat;
+        use crate::raw::BinaryStreamFile;
+        use crate::test_util::section;
+
+        // A 4x4 white DXT1 texture, with a few bytes of padding after its block
+        let block = [0xFF, 0xFF, 0, 0, 0, 0, 0, 0];
+        let mut raster = vec![8, 0, 0, 0, 0x06, 0x11, 0, 0];
+        raster.extend(b"white");
+        raster.extend([0; 59]);
+        raster.extend([0x00, 0x02, 0, 0, 0, 0, 0, 0]);
+        raster.extend([4, 0, 4, 0, 16, 1, 4, 1]);
+        raster.extend([12, 0, 0, 0]);
+        raster.extend(block);
+        raster.extend([0; 4]);
+        let mut raster_children = section(0x01, &raster);
+        raster_children.extend(section(0x03, &[]));
+        let mut children = section(0x01, &[1, 0, 0, 0]);
+        children.extend(section(0x15, &raster_children));
+        children.extend(section(0x03, &[]));
+        let raw = BinaryStreamFile::from_bytes(&section(0x16, &children)).unwrap();
+
+        let compressed = &super::Texture::from_raw_compressed(&raw).unwrap()[0];
+        assert_eq!(compressed.format, PixelFormat::Bc1);
+        assert_eq!(compressed.raster_format.bits(), 0x0200);
+        assert_eq!(compressed.levels, [block]);
+        assert!(compressed.as_colors().all(|c| c.as_array() == [255; 4]));
+
+        let decoded = super::Texture::from_raw(&raw).unwrap();
+        assert_eq!(decoded[0].format, PixelFormat::Rgba8);
+        assert_eq!(compressed.decompressed().as_ref(), &decoded[0]);
+    }
+
+    #[test]
+    fn writes_texture_dictionaries() {
+        use super::{PixelFormat, Texture, TextureAddressing, TextureFiltering};
+        use crate::raw::{constants::RasterFormatScheme, BinaryStreamFile, Error};
+
+        // An 8x4 image: opaque red on the left, and half-transparent blue on the right
+        let data = [[255, 0, 0, 255].repeat(4), [0, 0, 255, 128].repeat(4)]
+            .concat()
+            .repeat(4);
+        let uv = (TextureAddressing::Wrap, TextureAddressing::Clamp);
+        for format in [
+            PixelFormat::Rgba8,
+            PixelFormat::Bc1,
+            PixelFormat::Bc2,
+            PixelFormat::Bc3,
+        ] {
+            let texture = Texture::from_rgba8(
+                "paint",
+                TextureFiltering::LinearMipLinear,
+                uv,
+                8,
+                4,
+                &data,
+                format,
+            )
+            .unwrap();
+            assert_eq!(texture.levels.len(), 4);
+            assert_eq!(
+                texture.raster_format.scheme(),
+                Some(match format {
+                    PixelFormat::Rgba8 => RasterFormatScheme::_8888,
+                    // Half-transparent is rounded up to opaque
+                    PixelFormat::Bc1 => RasterFormatScheme::_565,
+                    _ => RasterFormatScheme::_4444,
+                })
+            );
+
+            let bytes = Texture::to_raw(std::slice::from_ref(&texture)).to_bytes();
+            let raw = BinaryStreamFile::from_bytes(&bytes).unwrap();
+            assert_eq!(raw.to_bytes(), bytes);
+            assert_eq!(Texture::from_raw_compressed(&raw).unwrap(), [texture]);
+
+            let decoded = &Texture::from_raw(&raw).unwrap()[0];
+            assert_eq!((decoded.name.as_str(), decoded.uv), ("paint", uv));
+            let colors: Vec<_> = decoded.as_colors().map(|c| c.as_array()).collect();
+            assert_eq!(colors[0], [255, 0, 0, 255], "{:?}", format);
+            assert_eq!(colors[7][..3], [0, 0, 255], "{:?}", format);
+        }
+
+        // Pixels that don't fill the image, or an image without any, are errors
+        let filtering = TextureFiltering::Linear;
+        for (width, height, data) in [(8, 8, &data[..]), (8, 4, &data[4..]), (0, 4, &[][..])] {
+            let texture = Texture::from_rgba8(
+                "paint",
+                filtering,
+                uv,
+                width,
+                height,
+                data,
+                PixelFormat::Bc1,
+            );
+            assert!(matches!(texture, Err(Error::InvalidImageSize { .. })));
+        }
+    }
+
+    #[test]
+    fn decodes_xbox_rasters() {
+        use super::{PixelFormat, Texture, TextureAddressing, TextureFiltering};
+        use crate::raw::{BinaryStreamFile, ClumpData, RasterPlatform, UnparsedData, XboxRaster};
+
+        // A 4x4 image with a different colour for each pixel
+        let data: Vec<u8> = (0..16u8).flat_map(|i| [i * 16, 255 - i, 0, 255]).collect();
+        let uv = (TextureAddressing::Wrap, TextureAddressing::Wrap);
+        let filtering = TextureFiltering::Linear;
+
+        // Writes `texture` as an Xbox raster, after `edit` has had a chance to change it
+        let to_xbox = |texture: &Texture, edit: &dyn Fn(&mut crate::raw::Raster)| {
+            let mut raw = Texture::to_raw(std::slice::from_ref(texture));
+            let raster = &mut raw.sections[0].children[1].children[0].data;
+            if let ClumpData::Raster(raster) = raster {
+                raster.platform = RasterPlatform::Xbox(XboxRaster {
+                    cube_texture: false,
+                    padding: UnparsedData(vec![]),
+                });
+                edit(raster);
+            }
+            let bytes = raw.to_bytes();
+            let raw = BinaryStreamFile::from_bytes(&bytes).unwrap();
+            assert_eq!(raw.to_bytes(), bytes);
+            raw
+        };
+
+        // Uncompressed levels are swizzled, with the bits of x and y interleaved
+        let texture =
+            Texture::from_rgba8("xbox", filtering, uv, 4, 4, &data, PixelFormat::Rgba8).unwrap();
+        let raw = to_xbox(&texture, &|raster| {
+            let level = raster.levels[0].clone();
+            for y in 0..4 {
+                for x in 0..4 {
+                    let swizzled = (x & 1) | (y & 1) << 1 | (x & 2) << 1 | (y & 2) << 2;
+                    let (from, to) = ((y * 4 + x) * 4, swizzled * 4);
+                    raster.levels[0][to..to + 4].copy_from_slice(&level[from..from + 4]);
+                }
+            }
+        });
+        let decoded = Texture::from_raw(&raw).unwrap();
+        assert_eq!(decoded[0].levels[0], data);
+
+        // A 3x3 level swizzles past its own end, which is an error rather than a panic
+        let texture =
+            Texture::from_rgba8("xbox", filtering, uv, 3, 3, &data[..36], PixelFormat::Rgba8)
+                .unwrap();
+        assert!(Texture::from_raw(&to_xbox(&texture, &|_| {})).is_err());
+
+        // Compressed levels aren't, and are numbered after the DXT formats
+        let texture =
+            Texture::from_rgba8("xbox", filtering, uv, 4, 4, &data, PixelFormat::Bc3).unwrap();
+        let raw = to_xbox(&texture, &|raster| raster.compression = 0x0F);
+        assert_eq!(Texture::from_raw_compressed(&raw).unwrap(), [texture]);
+    }
+
+    #[test]
+    fn decodes_ps2_rasters() {
+        use super::{Texture, TextureAddressing, TextureFiltering};
+        use crate::raw::{
+            constants::{RasterFormat, SectionType},
+            BinaryStreamFile, ClumpData, Ps2RasterHeader, Ps2TextureNative, UnparsedData,
+        };
+        use crate::test_util::parsed_section as section;
+
+        // The GIF packet before an upload of `width` by `height`, taking `size` bytes
+        fn transfer_header(width: u32, height: u32, size: u32) -> Vec<u8> {
+            let mut header = vec![0; 0x50];
+            header[32..36].copy_from_slice(&width.to_le_bytes());
+            header[36..40].copy_from_slice(&height.to_le_bytes());
+            header[64..68].copy_from_slice(&(size / 16).to_le_bytes());
+            header
+        }
+
+        // A texture dictionary holding a single raster of `header`, followed by its pixels and
+        // palette
+        let dictionary = |header: Ps2RasterHeader, pixels: Vec<u8>, palette: Vec<u8>| {
+            let header = Ps2RasterHeader {
+                pixel_size: pixels.len() as u32,
+                palette_size: palette.len() as u32,
+                ..header
+            };
+            let raster = section(
+                SectionType::Struct,
+                ClumpData::Unknown,
+                vec![
+                    section(
+                        SectionType::Struct,
+                        ClumpData::Ps2RasterHeader(header),
+                        vec![],
+                    ),
+                    section(
+                        SectionType::Struct,
+                        ClumpData::Struct(UnparsedData([pixels, palette].concat())),
+                        vec![],
+                    ),
+                ],
+            );
+            let native = ClumpData::Ps2TextureNative(Ps2TextureNative {
+                platform_id: 0x0032_5350,
+                filtering: TextureFiltering::Linear,
+                uv: (TextureAddressing::Wrap, TextureAddressing::Clamp),
+                flags: 0,
+            });
+            let string =
+                |s: &str| section(SectionType::String, ClumpData::String(s.into()), vec![]);
+            let extension = || section(SectionType::Extension, ClumpData::Unknown, vec![]);
+            let mut raw = Texture::to_raw(&[]);
+            raw.sections[0].children.insert(
+                1,
+                section(
+                    SectionType::Raster,
+                    ClumpData::Unknown,
+                    vec![
+                        section(SectionType::Struct, native, vec![]),
+                        string("ps2"),
+                        string(""),
+                        raster,
+                        extension(),
+                    ],
+                ),
+            );
+            if let ClumpData::TextureDictionary(dictionary) = &mut raw.sections[0].children[0].data
+            {
+                dictionary.texture_count = 1;
+            }
+
+            let bytes = raw.to_bytes();
+            let raw = BinaryStreamFile::from_bytes(&bytes).unwrap();
+            assert_eq!(raw.to_bytes(), bytes);
+            raw
+        };
+
+        // A 16x16 PAL8 raster, whose indices were swizzled into an 8x8 upload of 32-bit
+        // pixels, and whose palette has a red equal to each entry's place in it
+        let header = Ps2RasterHeader {
+            width: 16,
+            height: 16,
+            depth: 8,
+            raster_format: RasterFormat::new(0x2_2500),
+            tex0: 0,
+            palette_offset: 0,
+            tex1_low: 0,
+            miptbp1: 0,
+            miptbp2: 0,
+            pixel_size: 0,
+            palette_size: 0,
+            gpu_size: 0,
+            sky_mipmap: 0,
+        };
+        let raw = dictionary(
+            header.clone(),
+            [transfer_header(8, 8, 256), (0..=255).collect()].concat(),
+            [
+                transfer_header(16, 16, 1024),
+                (0..=255).flat_map(|i| [i, 0, 0, 0x80]).collect(),
+            ]
+            .concat(),
+        );
+        let texture = &Texture::from_raw(&raw).unwrap()[0];
+        assert_eq!((texture.name.as_str(), texture.width), ("ps2", 16));
+        let reds: Vec<_> = texture.as_colors().map(|c| c.as_array()[0]).collect();
+        // Each index is unswizzled, then has bits 3 and 4 swapped to find its palette entry
+        assert_eq!(
+            [reds[0], reds[1], reds[16], reds[32], reds[8]],
+            [0, 4, 32, 9, 2]
+        );
+        let mut sorted = reds.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..=255).collect::<Vec<u8>>());
+        assert!(texture.as_colors().all(|c| c.as_array()[3] == 255));
+
+        // Without GIF packets, level sizes come from a header that can claim any size
+        let mut raw = raw;
+        let raster = &mut raw.sections[0].children[1].children[3].children[0];
+        if let ClumpData::Ps2RasterHeader(header) = &mut raster.data {
+            (header.width, header.height, header.depth) = (0x10000, 0x10000, 32);
+            header.raster_format = RasterFormat::new(0x2500);
+        }
+        assert!(Texture::from_raw(&raw).is_err());
+
+        // A 32x16 PAL4 raster, whose indices were swizzled into an 8x8 upload of 32-bit
+        // pixels; each byte holds its offset, low nibble first
+        let raw = dictionary(
+            Ps2RasterHeader {
+                width: 32,
+                depth: 4,
+                raster_format: RasterFormat::new(0x2_4500),
+                ..header
+            },
+            [transfer_header(8, 8, 256), (0..=255).collect()].concat(),
+            [
+                transfer_header(8, 2, 64),
+                (0..16).flat_map(|i| [i, 0, 0, 0x80]).collect(),
+            ]
+            .concat(),
+        );
+        let texture = &Texture::from_raw(&raw).unwrap()[0];
+        assert_eq!((texture.width, texture.height), (32, 16));
+        let red = |x: usize, y: usize| texture.as_colors().nth(y * 32 + x).unwrap().as_array()[0];
+        // The first row's indices come from the low nibbles of every fourth byte, the third
+        // row's from the high nibbles
+        assert_eq!(
+            [red(1, 0), red(8, 0), red(16, 0), red(0, 2), red(0, 3)],
+            [4, 1, 2, 1, 3]
+        );
+        assert_eq!([red(0, 10), red(31, 15)], [9, 15]);
+    }
+}
@@ -0,0 +1,378 @@
+use crate::raw::{constants::RasterFormatScheme, Color, Raster, RasterPlatform};
+
+use super::{copy_level, level_size, DecodeError, PixelFormat};
+
+/// Reads the levels of one platform's rasters; each lays its pixels and palettes out
+/// differently.
+pub(crate) trait RasterDecoder {
+    /// The compressed format the raster's levels are stored in, if any, which they can be
+    /// used in without decoding.
+    fn compressed_format(&self, raster: &Raster) -> Option<PixelFormat>;
+
+    /// Decodes a level of the raster into RGBA8.
+    fn decode_level(&self, raster: &Raster, level: usize) -> Result<Vec<u8>, DecodeError>;
+}
+
+/// The decoder for the platform the raster was built for.
+pub(crate) fn decoder(raster: &Raster) -> &'static dyn RasterDecoder {
+    match raster.platform {
+        RasterPlatform::D3d8 | RasterPlatform::D3d9(_) => &Direct3d,
+        RasterPlatform::Xbox(_) => &Xbox,
+        RasterPlatform::Ps2(_) => &Ps2,
+    }
+}
+
+// PC rasters, from both Direct3D 8 and 9
+struct Direct3d;
+impl RasterDecoder for Direct3d {
+    fn compressed_format(&self, raster: &Raster) -> Option<PixelFormat> {
+        match raster.compression {
+            1 => Some(PixelFormat::Bc1),
+            3 => Some(PixelFormat::Bc2),
+            5 => Some(PixelFormat::Bc3),
+            _ => None,
+        }
+    }
+
+    fn decode_level(&self, raster: &Raster, level: usize) -> Result<Vec<u8>, DecodeError> {
+        let (width, height) = level_size(raster.width, raster.height, level);
+        if raster.compression != 0 {
+            return decompress_level(self, raster, level);
+        }
+        decode_pixels(
+            raster,
+            &raster.levels[level],
+            width as usize,
+            height as usize,
+            &raster.palette,
+        )
+    }
+}
+
+// Xbox rasters are Direct3D 8's formats, but swizzled unless they're compressed
+struct Xbox;
+impl RasterDecoder for Xbox {
+    // DXT2 and DXT4 have their colours premultiplied by alpha, which is left as it is
+    fn compressed_format(&self, raster: &Raster) -> Option<PixelFormat> {
+        match raster.compression {
+            0x0C => Some(PixelFormat::Bc1),
+            0x0D | 0x0E => Some(PixelFormat::Bc2),
+            0x0F | 0x10 => Some(PixelFormat::Bc3),
+            _ => None,
+        }
+    }
+
+    fn decode_level(&self, raster: &Raster, level: usize) -> Result<Vec<u8>, DecodeError> {
+        let (width, height) = level_size(raster.width, raster.height, level);
+        let (width, height) = (width as usize, height as usize);
+        if raster.compression != 0 {
+            return decompress_level(self, raster, level);
+        }
+
+        let bytes_per_pixel = match raster.depth {
+            32 => 4,
+            16 => 2,
+            8 => 1,
+            depth => {
+                return Err(DecodeError::Unsupported(format!(
+                    "{}-bit Xbox raster",
+                    depth
+                )))
+            }
+        };
+        let data = &raster.levels[level];
+        if data.len() < width * height * bytes_per_pixel {
+            return Err(DecodeError::Truncated);
+        }
+        let mut unswizzled = Vec::with_capacity(width * height * bytes_per_pixel);
+        for y in 0..height {
+            for x in 0..width {
+                // Sizes that aren't powers of two can swizzle past the end of the level
+                let offset = xbox_swizzled_index(x, y, width, height) * bytes_per_pixel;
+                let pixel = data
+                    .get(offset..offset + bytes_per_pixel)
+                    .ok_or(DecodeError::Truncated)?;
+                unswizzled.extend(pixel);
+            }
+        }
+
+        // Palettes are D3DCOLORs, which have blue first
+        let palette: Vec<_> = raster
+            .palette
+            .iter()
+            .map(|c| Color::new(c.b, c.g, c.r, c.a))
+            .collect();
+        decode_pixels(raster, &unswizzled, width, height, &palette)
+    }
+}
+
+// The Xbox swizzles by interleaving the bits of the coordinates, starting with x, until one
+// of them runs out of bits; the rest of the other's follow
+fn xbox_swizzled_index(x: usize, y: usize, width: usize, height: usize) -> usize {
+    let (mut index, mut bit) = (0, 0);
+    let (mut mask_x, mut mask_y) = (1, 1);
+    while mask_x < width || mask_y < height {
+        if mask_x < width {
+            index |= usize::from(x & mask_x != 0) << bit;
+            bit += 1;
+            mask_x <<= 1;
+        }
+        if mask_y < height {
+            index |= usize::from(y & mask_y != 0) << bit;
+            bit += 1;
+            mask_y <<= 1;
+        }
+    }
+    index
+}
+
+// PlayStation 2 rasters are RGBA throughout, with alpha that goes up to 0x80. Indices are
+// usually swizzled, and 256-colour palettes have their entries shuffled.
+struct Ps2;
+impl RasterDecoder for Ps2 {
+    fn compressed_format(&self, _raster: &Raster) -> Option<PixelFormat> {
+        None
+    }
+
+    fn decode_level(&self, raster: &Raster, level: usize) -> Result<Vec<u8>, DecodeError> {
+        let (width, height) = level_size(raster.width, raster.height, level);
+        let (width, height) = (width as usize, height as usize);
+        let pixel_count = width * height;
+        let data = &raster.levels[level];
+
+        // Swizzled levels are uploaded as a wider pixel format, and so are narrower than the
+        // level itself
+        let transfer_width = match &raster.platform {
+            RasterPlatform::Ps2(ps2) => ps2.transfer_sizes.get(level).map(|(w, _)| *w as usize),
+            _ => None,
+        };
+        let is_swizzled = transfer_width.is_some_and(|w| w != width);
+        let alpha = |a: u8| (a as u32 * 255 / 0x80).min(255) as u8;
+
+        if !raster.palette.is_empty() {
+            let indices: Vec<u8> = match raster.depth {
+                8 if data.len() < pixel_count => return Err(DecodeError::Truncated),
+                8 if is_swizzled => ps2_unswizzle8(data, width, height)?,
+                8 => data[..pixel_count].to_vec(),
+                4 if is_swizzled => ps2_unswizzle4(data, width, height)?,
+                4 if data.len() < pixel_count.div_ceil(2) => return Err(DecodeError::Truncated),
+                4 => data
+                    .iter()
+                    .flat_map(|b| [b & 0x0F, b >> 4])
+                    .take(pixel_count)
+                    .collect(),
+                depth => {
+                    return Err(DecodeError::Unsupported(format!(
+                        "{}-bit paletted PlayStation 2 raster",
+                        depth
+                    )))
+                }
+            };
+            // 256-colour palettes swap the second and third eight entries of every 32
+            let is_shuffled = raster.palette.len() >= 256;
+            return Ok(indices
+                .iter()
+                .flat_map(|index| {
+                    let index = match is_shuffled {
+                        true => (index & !0x18) | (index & 0x08) << 1 | (index & 0x10) >> 1,
+                        false => *index,
+                    };
+                    let color = raster.palette.get(index as usize).copied();
+                    let [r, g, b, a] = color.unwrap_or(Color::new(0, 0, 0, 0)).as_array();
+                    [r, g, b, alpha(a)]
+                })
+                .collect());
+        }
+
+        let bytes_per_pixel = match raster.depth {
+            32 => 4,
+            24 => 3,
+            16 => 2,
+            depth => {
+                return Err(DecodeError::Unsupported(format!(
+                    "{}-bit PlayStation 2 raster",
+                    depth
+                )))
+            }
+        };
+        if data.len() < pixel_count * bytes_per_pixel {
+            return Err(DecodeError::Truncated);
+        }
+        Ok(data
+            .chunks_exact(bytes_per_pixel)
+            .take(pixel_count)
+            .flat_map(|p| match bytes_per_pixel {
+                4 => [p[0], p[1], p[2], alpha(p[3])],
+                3 => [p[0], p[1], p[2], 255],
+                // Red in the lowest bits, and a bit of alpha in the highest
+                _ => {
+                    let v = u16::from_le_bytes([p[0], p[1]]);
+                    [
+                        expand(v, 5),
+                        expand(v >> 5, 5),
+                        expand(v >> 10, 5),
+                        expand(v >> 15, 1),
+                    ]
+                }
+            })
+            .collect())
+    }
+}
+
+// 8-bit indices are swizzled by uploading them as 32-bit pixels, which the GS lays out in
+// columns of its own
+fn ps2_unswizzle8(data: &[u8], width: usize, height: usize) -> Result<Vec<u8>, DecodeError> {
+    let mut indices = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let block = (y & !0x0F) * width + (x & !0x0F) * 2;
+            let swap = (((y + 2) >> 2) & 0x01) * 4;
+            let row = (((y & !3) >> 1) + (y & 1)) & 0x07;
+            let column = row * width * 2 + ((x + swap) & 0x07) * 4;
+            let byte = ((y >> 1) & 1) + ((x >> 2) & 2);
+            let index = data
+                .get(block + column + byte)
+                .ok_or(DecodeError::Truncated)?;
+            indices.push(*index);
+        }
+    }
+    Ok(indices)
+}
+
+// 4-bit indices are swizzled the same way, into pages of 128x128 indices that each fill a
+// 32-bit page of 64x32; the upload is as tall as the level is wide, and half as wide
+fn ps2_unswizzle4(data: &[u8], width: usize, height: usize) -> Result<Vec<u8>, DecodeError> {
+    let pages_wide = width.div_ceil(128);
+    let pages_high = height.div_ceil(128);
+    let mut indices = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let page_number = (y / 128) * pages_wide + x / 128;
+            let page =
+                (page_number / pages_high) * 32 * height * 2 + (page_number % pages_high) * 64 * 4;
+            let block = ((x & 0x7F & !0x1F) >> 1) * height + (y & 0x7F & !0x0F) * 2;
+            let swap = (((y + 2) >> 2) & 0x01) * 4;
+            let row = (((y & !3) >> 1) + (y & 1)) & 0x07;
+            let column = row * height * 2 + ((x + swap) & 0x07) * 4;
+            let byte = (x >> 3) & 3;
+            let byte = data
+                .get(page + block + column + byte)
+                .ok_or(DecodeError::Truncated)?;
+            // The lower and upper halves of each byte alternate every two rows
+            indices.push((byte >> (((y >> 1) & 1) * 4)) & 0x0F);
+        }
+    }
+    Ok(indices)
+}
+
+// Decompresses a level stored in the decoder's compressed format
+fn decompress_level(
+    decoder: &dyn RasterDecoder,
+    raster: &Raster,
+    level: usize,
+) -> Result<Vec<u8>, DecodeError> {
+    let format = decoder.compressed_format(raster).ok_or_else(|| {
+        DecodeError::Unsupported(format!("raster compression {}", raster.compression))
+    })?;
+    let (width, height) = level_size(raster.width, raster.height, level);
+    let data = copy_level(raster, level, format)?;
+    Ok(format.decompress(&data, width as u32, height as u32))
+}
+
+// Scales a value of `bits` bits to a byte, so that the maximum maps to 255
+fn expand(value: u16, bits: u32) -> u8 {
+    let max = (1 << bits) - 1;
+    ((value as u32 & max) * 255 / max) as u8
+}
+
+// Decodes Direct3D's uncompressed formats, which are little-endian with blue in the lowest
+// bits, and palettes of `palette`
+fn decode_pixels(
+    raster: &Raster,
+    data: &[u8],
+    width: usize,
+    height: usize,
+    palette: &[Color],
+) -> Result<Vec<u8>, DecodeError> {
+    let pixel_count = width * height;
+    let scheme = raster.raster_format.scheme().ok_or_else(|| {
+        DecodeError::Unsupported(format!(
+            "raster format {:#06X}",
+            raster.raster_format.bits()
+        ))
+    })?;
+
+    if !palette.is_empty() {
+        // Direct3D has no 4-bit palettes, so PC files store one index per byte regardless;
+        // anything shorter than that has two indices to a byte, low nibble first
+        let indices: Vec<u8> = if data.len() >= pixel_count {
+            data[..pixel_count].to_vec()
+        } else if raster.depth == 4 && data.len() >= pixel_count.div_ceil(2) {
+            data.iter()
+                .flat_map(|b| [b & 0x0F, b >> 4])
+                .take(pixel_count)
+                .collect()
+        } else {
+            return Err(DecodeError::Truncated);
+        };
+        // Palettes without alpha leave it unset
+        let is_opaque = scheme == RasterFormatScheme::_888;
+        return Ok(indices
+            .iter()
+            .flat_map(|index| {
+                let color = palette.get(*index as usize).copied();
+                let [r, g, b, a] = color.unwrap_or(Color::new(0, 0, 0, 0)).as_array();
+                [r, g, b, if is_opaque { 255 } else { a }]
+            })
+            .collect());
+    }
+
+    let bytes_per_pixel = match scheme {
+        RasterFormatScheme::_8888 => 4,
+        // Usually padded to four bytes, as Direct3D has no 24-bit format
+        RasterFormatScheme::_888 if raster.depth == 24 => 3,
+        RasterFormatScheme::_888 => 4,
+        RasterFormatScheme::_1555
+        | RasterFormatScheme::_565
+        | RasterFormatScheme::_4444
+        | RasterFormatScheme::_555 => 2,
+        RasterFormatScheme::LUM8 => 1,
+    };
+    if data.len() < pixel_count * bytes_per_pixel {
+        return Err(DecodeError::Truncated);
+    }
+
+    Ok(data
+        .chunks_exact(bytes_per_pixel)
+        .take(pixel_count)
+        .flat_map(|p| {
+            let packed = || u16::from_le_bytes([p[0], p[1]]);
+            match scheme {
+                RasterFormatScheme::_8888 => [p[2], p[1], p[0], p[3]],
+                RasterFormatScheme::_888 => [p[2], p[1], p[0], 255],
+                RasterFormatScheme::_1555 | RasterFormatScheme::_555 => {
+                    let v = packed();
+                    let a = match scheme {
+                        RasterFormatScheme::_1555 => expand(v >> 15, 1),
+                        _ => 255,
+                    };
+                    [expand(v >> 10, 5), expand(v >> 5, 5), expand(v, 5), a]
+                }
+                RasterFormatScheme::_565 => {
+                    let v = packed();
+                    [expand(v >> 11, 5), expand(v >> 5, 6), expand(v, 5), 255]
+                }
+                RasterFormatScheme::_4444 => {
+                    let v = packed();
+                    [
+                        expand(v >> 8, 4),
+                        expand(v >> 4, 4),
+                        expand(v, 4),
+                        expand(v >> 12, 4),
+                    ]
+                }
+                RasterFormatScheme::LUM8 => [p[0], p[0], p[0], 255],
+            }
+        })
+        .collect())
+}
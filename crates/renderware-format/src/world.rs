@@ -0,0 +1,386 @@
+use crate::{
+    dff::{self, Material, Model, Topology, Vertex},
+    raw::{self, constants::SectionType, BinaryStreamFile, ClumpData, Error, Section},
+};
+
+pub use crate::raw::{constants::PlaneAxis, Color, Triangle, Vec3};
+
+// A node of a world's plane tree
+#[derive(Debug, Clone)]
+pub enum Node {
+    // Splits space along the axis; `left` is below `value`, and `right` above it
+    Plane {
+        axis: PlaneAxis,
+        value: f32,
+        left: Box<Node>,
+        right: Box<Node>,
+    },
+    // An index into the world's sectors
+    Sector(usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct Sector {
+    pub min: Vec3,
+    pub max: Vec3,
+    pub vertices: Vec<Vertex>,
+    // Each triangle's material ID indexes into the world's material indices
+    pub triangles: Vec<Triangle>,
+}
+
+#[derive(Debug, Clone)]
+pub struct World {
+    pub root: Node,
+    // In the order they appear in the tree, from left to right
+    pub sectors: Vec<Sector>,
+    // The number of UV sets each vertex has
+    pub uv_set_count: usize,
+    pub materials: Vec<Material>,
+    pub material_indices: Vec<usize>,
+}
+
+impl World {
+    /// Reads the first world in the file.
+    pub fn from_raw(raw: &BinaryStreamFile) -> Result<World, Error> {
+        let world = raw
+            .worlds()
+            .next()
+            .ok_or_else(|| raw.missing_section(SectionType::World))?;
+        let unsupported = |feature: &str| Error::Unsupported {
+            feature: feature.to_string(),
+            offset: world.offset,
+            path: "World".to_string(),
+        };
+
+        let header = match world.get_child_struct_data() {
+            Some(ClumpData::World(header)) => header,
+            _ => return Err(world.missing_child(SectionType::Struct, "World")),
+        };
+        if header
+            .format
+            .contains(raw::constants::GeometryFormat::NATIVE)
+        {
+            return Err(unsupported("native world"));
+        }
+
+        let material_list = world.require_child_by_type(SectionType::MaterialList, "World")?;
+        let (materials, material_indices) =
+            dff::materials_from_list(material_list, "World/MaterialList")?;
+
+        // The root is whichever section follows the material list
+        let root_type = if header.root_is_world_sector {
+            SectionType::AtomicSection
+        } else {
+            SectionType::PlaneSection
+        };
+        let root = world.require_child_by_type(root_type, "World")?;
+        let mut sectors = vec![];
+        let root = Self::node_from_section(root, &format!("World/{:?}", root_type), &mut sectors)?;
+
+        Ok(World {
+            root,
+            sectors,
+            uv_set_count: raw::texture_sets_present(header.format, header.texture_set_count)
+                as usize,
+            materials,
+            material_indices,
+        })
+    }
+
+    // `path` is the path of `section`, for errors
+    fn node_from_section(
+        section: &Section,
+        path: &str,
+        sectors: &mut Vec<Sector>,
+    ) -> Result<Node, Error> {
+        match section.get_child_struct_data() {
+            Some(ClumpData::PlaneSection(plane)) => {
+                // The struct is followed by the left child, then the right
+                let mut children = section.children.iter().filter(|s| {
+                    s.section_type == SectionType::PlaneSection
+                        || s.section_type == SectionType::AtomicSection
+                });
+                let mut child = |is_world_sector: bool| {
+                    let child_type = if is_world_sector {
+                        SectionType::AtomicSection
+                    } else {
+                        SectionType::PlaneSection
+                    };
+                    match children.next() {
+                        Some(child) if child.section_type == child_type => Self::node_from_section(
+                            child,
+                            &format!("{}/{:?}", path, child_type),
+                            sectors,
+                        ),
+                        _ => Err(section.missing_child(child_type, path)),
+                    }
+                };
+                let left = child(plane.left_is_world_sector)?;
+                let right = child(plane.right_is_world_sector)?;
+                Ok(Node::Plane {
+                    axis: plane.axis,
+                    value: plane.value,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                })
+            }
+            Some(ClumpData::AtomicSection(sector)) => {
+                sectors.push(Sector::from_raw(sector, section.offset, path)?);
+                Ok(Node::Sector(sectors.len() - 1))
+            }
+            _ => Err(section.missing_child(SectionType::Struct, path)),
+        }
+    }
+
+    /// Finds the sector containing `point`.
+    pub fn sector_at(&self, point: Vec3) -> &Sector {
+        let mut node = &self.root;
+        loop {
+            match node {
+                Node::Plane {
+                    axis,
+                    value,
+                    left,
+                    right,
+                } => {
+                    let component = match axis {
+                        PlaneAxis::X => point.x,
+                        PlaneAxis::Y => point.y,
+                        PlaneAxis::Z => point.z,
+                    };
+                    node = if component < *value { left } else { right };
+                }
+                Node::Sector(index) => return &self.sectors[*index],
+            }
+        }
+    }
+
+    /// Builds a model for each sector with any triangles, splitting their vertices by
+    /// material as [`dff::Model`]s are.
+    pub fn models(&self) -> impl Iterator<Item = Model> + '_ {
+        self.sectors
+            .iter()
+            .filter(|s| !s.triangles.is_empty())
+            .map(|sector| {
+                let (vertices, indices, _) =
+                    dff::split_by_material(&sector.vertices, sector.triangles.clone());
+                Model {
+                    vertices,
+                    indices,
+                    topology: Topology::TriangleList,
+                    uv_set_count: self.uv_set_count,
+                    materials: self.materials.clone(),
+                    material_indices: self.material_indices.clone(),
+                    skin: None,
+                    morph_targets: vec![],
+                }
+            })
+    }
+}
+
+impl Sector {
+    // `offset` and `path` locate the sector's section, for errors
+    fn from_raw(sector: &raw::AtomicSection, offset: usize, path: &str) -> Result<Self, Error> {
+        let mut vertices: Vec<_> = sector
+            .vertices
+            .iter()
+            .map(|position| Vertex::new(*position, Vec3::ZERO, [0.0, 0.0], 0))
+            .collect();
+        for (vertex, normal) in vertices.iter_mut().zip(sector.normals.iter().flatten()) {
+            let [x, y, z, _] = normal.map(|c| c as f32 / 128.0);
+            vertex.normal = Vec3 { x, y, z };
+        }
+        for (vertex, color) in vertices
+            .iter_mut()
+            .zip(sector.prelit_color.iter().flatten())
+        {
+            vertex.day_color = Some(*color);
+            vertex.night_color = Some(*color);
+        }
+        for (set, texture_set) in sector
+            .texture_sets
+            .iter()
+            .enumerate()
+            .take(Vertex::MAX_UV_SETS)
+        {
+            for (vertex, (u, v)) in vertices.iter_mut().zip(texture_set) {
+                vertex.uvs[set] = [*u, *v];
+            }
+        }
+
+        // Splitting the sector by material indexes its vertices by these
+        let vertex_count = sector.vertices.len();
+        for polygon in &sector.polygons {
+            if let Some(vertex) = polygon
+                .vertices
+                .iter()
+                .find(|v| **v as usize >= vertex_count)
+            {
+                return Err(Error::InvalidIndex {
+                    what: "vertex".to_string(),
+                    index: *vertex as usize,
+                    offset,
+                    path: path.to_string(),
+                });
+            }
+        }
+
+        let base = sector.material_list_window_base as u16;
+        let triangles = sector
+            .polygons
+            .iter()
+            .map(|p| Triangle {
+                vertex1: p.vertices[0],
+                vertex2: p.vertices[1],
+                vertex3: p.vertices[2],
+                material_id: base.wrapping_add(p.material_id),
+            })
+            .collect();
+
+        let (min, max) = sector.bounding_box;
+        Ok(Sector {
+            min,
+            max,
+            vertices,
+            triangles,
+        })
+    }
+}
+
+mod tests {
+    #[test]
+    fn builds_the_plane_tree() {
+        use super::{Node, PlaneAxis, Vec3};
+        use crate::raw::{
+            constants::{GeometryFormat, SectionType},
+            AtomicSection, BinaryStreamFile, ClumpData, Color, PlaneSection, Polygon, Section,
+            UnparsedData, World,
+        };
+        use crate::test_util::parsed_section as section;
+
+        fn structure(data: ClumpData) -> Section {
+            section(SectionType::Struct, data, vec![])
+        }
+        fn sector_struct(x: f32, base: u32) -> Section {
+            let vertex = |x, y| Vec3 { x, y, z: 0.0 };
+            structure(ClumpData::AtomicSection(AtomicSection {
+                material_list_window_base: base,
+                polygon_count: 1,
+                vertex_count: 3,
+                bounding_box: (vertex(x, 0.0), vertex(x + 1.0, 1.0)),
+                collision_sector_present: 0,
+                unused: 0,
+                vertices: vec![vertex(x, 0.0), vertex(x + 1.0, 0.0), vertex(x, 1.0)],
+                normals: Some(vec![[0, 0, 64, 0]; 3]),
+                prelit_color: Some(vec![Color::new(255, 0, 0, 255); 3]),
+                texture_sets: vec![vec![(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)]],
+                polygons: vec![Polygon {
+                    material_id: 0,
+                    vertices: [0, 1, 2],
+                }],
+            }))
+        }
+        let sector = |x, base| {
+            section(
+                SectionType::AtomicSection,
+                ClumpData::Unknown,
+                vec![sector_struct(x, base)],
+            )
+        };
+
+        let header = World {
+            root_is_world_sector: false,
+            inverse_origin: Vec3::ZERO,
+            lighting: None,
+            polygon_count: 2,
+            vertex_count: 6,
+            plane_sector_count: 1,
+            world_sector_count: 2,
+            col_sector_size: 0,
+            format: GeometryFormat::POSITIONS
+                | GeometryFormat::TEXTURED
+                | GeometryFormat::PRELIT
+                | GeometryFormat::NORMALS,
+            texture_set_count: 0,
+            bounding_box: None,
+        };
+        let plane = PlaneSection {
+            axis: PlaneAxis::X,
+            value: 1.0,
+            left_is_world_sector: true,
+            right_is_world_sector: true,
+            left_value: 1.0,
+            right_value: 1.0,
+        };
+        let world = section(
+            SectionType::World,
+            ClumpData::Unknown,
+            vec![
+                structure(ClumpData::World(header)),
+                section(
+                    SectionType::MaterialList,
+                    ClumpData::Unknown,
+                    vec![structure(ClumpData::MaterialList {
+                        material_indices: vec![],
+                    })],
+                ),
+                section(
+                    SectionType::PlaneSection,
+                    ClumpData::Unknown,
+                    vec![
+                        structure(ClumpData::PlaneSection(plane)),
+                        sector(0.0, 0),
+                        sector(1.0, 2),
+                    ],
+                ),
+                section(
+                    SectionType::Extension,
+                    ClumpData::Unparsed(UnparsedData(vec![])),
+                    vec![],
+                ),
+            ],
+        );
+        let raw = BinaryStreamFile {
+            sections: vec![world],
+            padding: UnparsedData(vec![]),
+        };
+
+        let world = super::World::from_raw(&raw).unwrap();
+        assert!(matches!(
+            world.root,
+            Node::Plane {
+                axis: PlaneAxis::X,
+                ..
+            }
+        ));
+        assert_eq!(world.sectors.len(), 2);
+        assert_eq!(world.uv_set_count, 1);
+
+        let right = world.sector_at(Vec3 {
+            x: 1.5,
+            y: 0.5,
+            z: 0.0,
+        });
+        assert_eq!(right.min.x, 1.0);
+        assert_eq!(right.triangles[0].material_id, 2);
+        assert_eq!(right.vertices[1].normal.z, 0.5);
+        assert_eq!(right.vertices[2].uv(), [0.0, 1.0]);
+        assert!(right.vertices[0].day_color.is_some());
+
+        let models: Vec<_> = world.models().collect();
+        assert_eq!(models.len(), 2);
+        assert_eq!(models[0].indices.len(), 3);
+        assert!(models[1].vertices.iter().all(|v| v.material_id == 2));
+
+        // A polygon using a vertex the sector doesn't have is an error, rather than a panic
+        let mut raw = raw;
+        let plane = &mut raw.sections[0].children[2];
+        if let ClumpData::AtomicSection(sector) = &mut plane.children[1].children[0].data {
+            sector.polygons[0].vertices[2] = 3;
+        }
+        assert!(matches!(
+            super::World::from_raw(&raw),
+            Err(crate::raw::Error::InvalidIndex { index: 3, .. })
+        ));
+    }
+}
@@ -0,0 +1,489 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+// https://gtamods.com/wiki/IMG_archive
+pub const SECTOR_SIZE: u64 = 2048;
+const ENTRY_SIZE: usize = 32;
+const NAME_SIZE: usize = 24;
+const VER2_MAGIC: &[u8; 4] = b"VER2";
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Version {
+    // GTA III and Vice City: the directory lives in a separate .dir file
+    V1,
+    // San Andreas: the directory is stored at the start of the .img
+    V2,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Entry {
+    pub name: String,
+    // Offset from the start of the archive, in sectors
+    pub offset: u32,
+    // Size of the entry, in sectors
+    pub size: u32,
+}
+
+impl Entry {
+    pub fn byte_offset(&self) -> u64 {
+        self.offset as u64 * SECTOR_SIZE
+    }
+
+    pub fn byte_size(&self) -> u64 {
+        self.size as u64 * SECTOR_SIZE
+    }
+
+    /// Reads the contents of the entry from `archive`, an open handle to the archive's data,
+    /// including any trailing sector padding. Use this to read many entries without
+    /// reopening the archive for each.
+    pub fn read<R: Read + Seek>(&self, archive: &mut R) -> io::Result<Vec<u8>> {
+        archive.seek(SeekFrom::Start(self.byte_offset()))?;
+        let mut data = vec![0u8; self.byte_size() as usize];
+        archive.read_exact(&mut data)?;
+        Ok(data)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Img {
+    pub version: Version,
+    pub path: PathBuf,
+    pub entries: Vec<Entry>,
+}
+
+impl Img {
+    /// Creates a new, empty archive at `path`, overwriting anything that was there.
+    pub fn create<P: AsRef<Path>>(path: P, version: Version) -> io::Result<Img> {
+        File::create(path.as_ref())?;
+        let img = Img {
+            version,
+            path: path.as_ref().to_owned(),
+            entries: vec![],
+        };
+        img.write_directory()?;
+        Ok(img)
+    }
+
+    /// Opens the archive at `path`, reading its directory from the sibling `.dir`
+    /// for V1 archives, or from the archive itself for V2 archives.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Img> {
+        let path = path.as_ref().to_owned();
+
+        let mut file = File::open(&path)?;
+        let mut header = [0u8; 8];
+        let is_ver2 = file.read_exact(&mut header).is_ok() && &header[0..4] == VER2_MAGIC;
+
+        let (version, entries) = if is_ver2 {
+            let entry_count = u32::from_le_bytes(header[4..8].try_into().unwrap()) as u64;
+            let mut directory = header.to_vec();
+            file.take(entry_count * ENTRY_SIZE as u64)
+                .read_to_end(&mut directory)?;
+            (Version::V2, parse_ver2_directory(&directory)?)
+        } else {
+            let dir = fs::read(path.with_extension("dir"))?;
+            (Version::V1, parse_dir(&dir))
+        };
+
+        Ok(Img {
+            version,
+            path,
+            entries,
+        })
+    }
+
+    /// Finds an entry by name. Names are compared case-insensitively, as the game does.
+    pub fn entry(&self, name: &str) -> Option<&Entry> {
+        self.entries
+            .iter()
+            .find(|e| e.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Reads the contents of `entry` from the archive, including any trailing sector padding.
+    pub fn read(&self, entry: &Entry) -> io::Result<Vec<u8>> {
+        entry.read(&mut File::open(&self.path)?)
+    }
+
+    /// Reads the contents of the entry called `name`.
+    pub fn extract(&self, name: &str) -> io::Result<Vec<u8>> {
+        self.read(self.entry(name).ok_or_else(|| not_found(name))?)
+    }
+
+    /// Appends a new entry to the end of the archive.
+    pub fn add(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        if name.len() >= NAME_SIZE || !name.is_ascii() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("`{name}` is not a valid entry name"),
+            ));
+        }
+        if self.entry(name).is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("`{name}` is already in the archive"),
+            ));
+        }
+
+        // A V2 directory grows into the data that follows it, so move that data out of the way.
+        let directory_end = self.data_start(self.entries.len() + 1);
+        self.relocate_entries_below(directory_end)?;
+
+        let offset = self.end_of_data();
+        let size = self.write_data(offset, data)?;
+        self.entries.push(Entry {
+            name: name.to_string(),
+            offset,
+            size,
+        });
+        self.write_directory()
+    }
+
+    /// Replaces the contents of an existing entry. The data is written in place if it fits,
+    /// and is otherwise appended to the archive, leaving a gap that [`Img::repack`] reclaims.
+    pub fn replace(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        let index = self.entry_index(name)?;
+        let offset = if sector_count(data.len() as u64) <= self.entries[index].size as u64 {
+            self.entries[index].offset
+        } else {
+            self.end_of_data()
+        };
+
+        let size = self.write_data(offset, data)?;
+        let entry = &mut self.entries[index];
+        entry.offset = offset;
+        entry.size = size;
+        self.write_directory()
+    }
+
+    /// Removes an entry from the directory. Its data stays in the archive until it is repacked.
+    pub fn delete(&mut self, name: &str) -> io::Result<()> {
+        let index = self.entry_index(name)?;
+        self.entries.remove(index);
+        self.write_directory()
+    }
+
+    /// Rewrites the archive so that its entries are stored contiguously and in directory
+    /// order, dropping any space left behind by deleted or replaced entries.
+    pub fn repack(&mut self) -> io::Result<()> {
+        let mut temp_path = self.path.clone().into_os_string();
+        temp_path.push(".tmp");
+        let temp_path = PathBuf::from(temp_path);
+
+        let mut offset = self.data_start(self.entries.len());
+        let mut entries = Vec::with_capacity(self.entries.len());
+        {
+            let mut source = File::open(&self.path)?;
+            let mut target = File::create(&temp_path)?;
+            for entry in &self.entries {
+                target.seek(SeekFrom::Start(offset as u64 * SECTOR_SIZE))?;
+                source.seek(SeekFrom::Start(entry.byte_offset()))?;
+                io::copy(&mut (&mut source).take(entry.byte_size()), &mut target)?;
+
+                entries.push(Entry {
+                    offset,
+                    ..entry.clone()
+                });
+                offset += entry.size;
+            }
+            target.set_len(offset as u64 * SECTOR_SIZE)?;
+        }
+
+        fs::rename(&temp_path, &self.path)?;
+        self.entries = entries;
+        self.write_directory()
+    }
+
+    fn entry_index(&self, name: &str) -> io::Result<usize> {
+        self.entries
+            .iter()
+            .position(|e| e.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| not_found(name))
+    }
+
+    // The first sector that entry data can occupy for a directory of `entry_count` entries
+    fn data_start(&self, entry_count: usize) -> u32 {
+        match self.version {
+            Version::V1 => 0,
+            Version::V2 => sector_count((8 + entry_count * ENTRY_SIZE) as u64) as u32,
+        }
+    }
+
+    fn end_of_data(&self) -> u32 {
+        self.entries
+            .iter()
+            .map(|e| e.offset + e.size)
+            .max()
+            .unwrap_or(0)
+            .max(self.data_start(self.entries.len()))
+    }
+
+    fn relocate_entries_below(&mut self, sector: u32) -> io::Result<()> {
+        for index in 0..self.entries.len() {
+            if self.entries[index].offset >= sector {
+                continue;
+            }
+
+            let data = self.read(&self.entries[index])?;
+            let offset = self.end_of_data().max(sector);
+            self.write_data(offset, &data)?;
+            self.entries[index].offset = offset;
+        }
+        Ok(())
+    }
+
+    // Writes `data` at `offset`, padded to a whole number of sectors. Returns the size in sectors.
+    fn write_data(&self, offset: u32, data: &[u8]) -> io::Result<u32> {
+        let size = sector_count(data.len() as u64);
+        if self.version == Version::V2 && size > u16::MAX as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "entry is too large for a VER2 archive",
+            ));
+        }
+
+        let mut file = OpenOptions::new().write(true).open(&self.path)?;
+        file.seek(SeekFrom::Start(offset as u64 * SECTOR_SIZE))?;
+        file.write_all(data)?;
+        file.write_all(&vec![0u8; (size * SECTOR_SIZE) as usize - data.len()])?;
+        Ok(size as u32)
+    }
+
+    fn write_directory(&self) -> io::Result<()> {
+        match self.version {
+            Version::V1 => fs::write(self.path.with_extension("dir"), write_dir(&self.entries)),
+            Version::V2 => {
+                let mut file = OpenOptions::new().write(true).open(&self.path)?;
+                file.write_all(&write_ver2_directory(&self.entries)?)
+            }
+        }
+    }
+}
+
+/// Parses the contents of a V1 `.dir` file.
+pub fn parse_dir(data: &[u8]) -> Vec<Entry> {
+    data.chunks_exact(ENTRY_SIZE)
+        .map(|entry| Entry {
+            name: parse_name(&entry[8..8 + NAME_SIZE]),
+            offset: u32::from_le_bytes(entry[0..4].try_into().unwrap()),
+            size: u32::from_le_bytes(entry[4..8].try_into().unwrap()),
+        })
+        .collect()
+}
+
+/// Writes the contents of a V1 `.dir` file.
+pub fn write_dir(entries: &[Entry]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(entries.len() * ENTRY_SIZE);
+    for entry in entries {
+        data.extend_from_slice(&entry.offset.to_le_bytes());
+        data.extend_from_slice(&entry.size.to_le_bytes());
+        data.extend_from_slice(&write_name(&entry.name));
+    }
+    data
+}
+
+/// Parses the directory at the start of a V2 `.img` file.
+pub fn parse_ver2_directory(data: &[u8]) -> io::Result<Vec<Entry>> {
+    let invalid = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+    if data.len() < 8 || &data[0..4] != VER2_MAGIC {
+        return Err(invalid("missing VER2 header"));
+    }
+
+    let entry_count = u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize;
+    let directory = data
+        .get(8..8 + entry_count * ENTRY_SIZE)
+        .ok_or_else(|| invalid("truncated VER2 directory"))?;
+
+    Ok(directory
+        .chunks_exact(ENTRY_SIZE)
+        .map(|entry| {
+            let streaming_size = u16::from_le_bytes(entry[4..6].try_into().unwrap());
+            let archive_size = u16::from_le_bytes(entry[6..8].try_into().unwrap());
+            Entry {
+                name: parse_name(&entry[8..8 + NAME_SIZE]),
+                offset: u32::from_le_bytes(entry[0..4].try_into().unwrap()),
+                // The archive size is almost always zero, in which case the streaming size is used
+                size: if archive_size != 0 {
+                    archive_size
+                } else {
+                    streaming_size
+                } as u32,
+            }
+        })
+        .collect())
+}
+
+/// Writes the directory at the start of a V2 `.img` file. Fails if an entry is too large
+/// for the directory's 16-bit sizes.
+pub fn write_ver2_directory(entries: &[Entry]) -> io::Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(8 + entries.len() * ENTRY_SIZE);
+    data.extend_from_slice(VER2_MAGIC);
+    data.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+    for entry in entries {
+        let size = u16::try_from(entry.size).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("`{}` is too large for a VER2 archive", entry.name),
+            )
+        })?;
+        data.extend_from_slice(&entry.offset.to_le_bytes());
+        data.extend_from_slice(&size.to_le_bytes());
+        data.extend_from_slice(&0u16.to_le_bytes());
+        data.extend_from_slice(&write_name(&entry.name));
+    }
+    Ok(data)
+}
+
+/// Splits a path of the form `archive.img:entry.dff` into the archive path and entry name.
+pub fn split_archive_path(path: &Path) -> Option<(&Path, &str)> {
+    let path = path.to_str()?;
+    let separator = path.to_ascii_lowercase().rfind(".img:")? + ".img".len();
+    Some((Path::new(&path[..separator]), &path[separator + 1..]))
+}
+
+/// Reads either a loose file, or an archive entry if `path` is of the form `archive.img:entry.dff`.
+pub fn read_path(path: &Path) -> io::Result<Vec<u8>> {
+    match split_archive_path(path) {
+        Some((archive, name)) => Img::open(archive)?.extract(name),
+        None => fs::read(path),
+    }
+}
+
+fn sector_count(byte_count: u64) -> u64 {
+    byte_count.div_ceil(SECTOR_SIZE)
+}
+
+fn not_found(name: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("`{name}` is not in the archive"),
+    )
+}
+
+fn parse_name(data: &[u8]) -> String {
+    let end = data.iter().position(|b| *b == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).to_string()
+}
+
+fn write_name(name: &str) -> [u8; NAME_SIZE] {
+    let mut buf = [0u8; NAME_SIZE];
+    let length = name.len().min(NAME_SIZE - 1);
+    buf[..length].copy_from_slice(&name.as_bytes()[..length]);
+    buf
+}
+
+mod tests {
+    #[test]
+    fn can_parse_dir_entries() {
+        let mut data = vec![];
+        for (offset, size, name) in [(0u32, 3u32, "bar.dff"), (3, 1, "bar.txd")] {
+            data.extend_from_slice(&offset.to_le_bytes());
+            data.extend_from_slice(&size.to_le_bytes());
+            let mut name_buf = [0u8; super::NAME_SIZE];
+            name_buf[..name.len()].copy_from_slice(name.as_bytes());
+            // Rockstar's tools leave junk after the terminator
+            name_buf[name.len() + 1] = b'X';
+            data.extend_from_slice(&name_buf);
+        }
+
+        assert_eq!(
+            super::parse_dir(&data),
+            vec![
+                super::Entry {
+                    name: "bar.dff".to_string(),
+                    offset: 0,
+                    size: 3
+                },
+                super::Entry {
+                    name: "bar.txd".to_string(),
+                    offset: 3,
+                    size: 1
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn can_parse_ver2_directory() {
+        let mut data = b"VER2".to_vec();
+        data.extend_from_slice(&1u32.to_le_bytes());
+        data.extend_from_slice(&1u32.to_le_bytes());
+        data.extend_from_slice(&4u16.to_le_bytes());
+        data.extend_from_slice(&0u16.to_le_bytes());
+        let mut name_buf = [0u8; super::NAME_SIZE];
+        name_buf[..7].copy_from_slice(b"foo.dff");
+        data.extend_from_slice(&name_buf);
+
+        assert_eq!(
+            super::parse_ver2_directory(&data).unwrap(),
+            vec![super::Entry {
+                name: "foo.dff".to_string(),
+                offset: 1,
+                size: 4
+            }]
+        );
+    }
+
+    #[test]
+    fn can_split_archive_path() {
+        use std::path::Path;
+        assert_eq!(
+            super::split_archive_path(Path::new("models/GTA3.IMG:bar.dff")),
+            Some((Path::new("models/GTA3.IMG"), "bar.dff"))
+        );
+        assert_eq!(super::split_archive_path(Path::new("models/bar.dff")), None);
+    }
+
+    #[test]
+    fn can_edit_and_repack_archives() {
+        use super::{Img, Version, SECTOR_SIZE};
+
+        // A directory of this run's own, so that concurrent runs don't share archives
+        let directory = std::env::temp_dir().join(format!("vcf-img-test-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        for version in [Version::V1, Version::V2] {
+            let path = directory.join(format!("{version:?}.img"));
+            let mut img = Img::create(&path, version).unwrap();
+            img.add("a.dff", &[1; 10]).unwrap();
+            img.add("b.txd", &[2; 3000]).unwrap();
+            img.replace("a.dff", &[3; 5000]).unwrap();
+            img.delete("b.txd").unwrap();
+            for index in 0..100 {
+                img.add(&format!("{index}.dff"), &[index as u8]).unwrap();
+            }
+            img.repack().unwrap();
+
+            let img = Img::open(&path).unwrap();
+            assert_eq!(img.version, version);
+            assert_eq!(img.entries.len(), 101);
+            assert_eq!(img.entry("A.DFF").unwrap().size, 3);
+            assert_eq!(&img.extract("a.dff").unwrap()[..5000], &[3; 5000]);
+            assert_eq!(img.extract("99.dff").unwrap()[0], 99);
+            assert!(img.entry("b.txd").is_none());
+
+            // Entries are packed back-to-back after the directory
+            let data_start = match version {
+                Version::V1 => 0,
+                Version::V2 => 2,
+            };
+            assert_eq!(img.entries[0].offset, data_start);
+            assert_eq!(
+                std::fs::metadata(&path).unwrap().len(),
+                (data_start as u64 + 3 + 100) * SECTOR_SIZE
+            );
+        }
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn rejects_oversized_ver2_entries() {
+        let entry = |size| super::Entry {
+            name: "big.dff".to_string(),
+            offset: 1,
+            size,
+        };
+        assert!(super::write_ver2_directory(&[entry(u16::MAX as u32)]).is_ok());
+        assert!(super::write_ver2_directory(&[entry(u16::MAX as u32 + 1)]).is_err());
+    }
+}
@@ -2,7 +2,9 @@ mod common;
 
 pub mod dat;
 pub mod ide;
+pub mod img;
 pub mod ipl;
 
 pub use ide::Ide;
+pub use img::Img;
 pub use ipl::Ipl;
@@ -0,0 +1,68 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+
+use renderware_format as rwf;
+
+use super::dff::{rwf_model_to_bevy_model, Model};
+
+#[derive(Default)]
+pub struct BspLoader;
+
+impl AssetLoader for BspLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut bevy::asset::LoadContext,
+    ) -> BoxedFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move { load_bsp(bytes, load_context).await })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        static EXTENSIONS: &[&str] = &["bsp"];
+        EXTENSIONS
+    }
+}
+
+#[derive(TypeUuid)]
+#[uuid = "d3b0a3f2-5c1e-4f27-9a8b-6e0c4d7f1a92"]
+pub struct Bsp {
+    pub name: String,
+    // One per world sector with any geometry, already in world space
+    pub models: Vec<Model>,
+}
+
+async fn load_bsp<'a, 'b>(
+    bytes: &'a [u8],
+    load_context: &'a mut LoadContext<'b>,
+) -> anyhow::Result<()> {
+    let name = load_context
+        .path()
+        .file_stem()
+        .expect("failed to extract filestem")
+        .to_string_lossy()
+        .to_string();
+
+    let raw = rwf::raw::BinaryStreamFile::from_bytes(bytes)?;
+    let world = rwf::world::World::from_raw(&raw)?;
+    // Sectors aren't attached to frames, so their frame index is meaningless
+    let models = world
+        .models()
+        .map(|model| rwf_model_to_bevy_model(0, model, None))
+        .collect();
+
+    load_context.set_default_asset(LoadedAsset::new(Bsp { name, models }));
+
+    Ok(())
+}
+
+#[derive(Default)]
+pub struct BspPlugin;
+impl Plugin for BspPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<Bsp>().init_asset_loader::<BspLoader>();
+    }
+}
@@ -3,7 +3,7 @@ use bevy::{
     prelude::*,
     reflect::TypeUuid,
     render::{
-        mesh::{Indices, Mesh},
+        mesh::{skinning::SkinnedMeshInverseBindposes, Indices, Mesh},
         render_resource::PrimitiveTopology,
     },
     utils::BoxedFuture,
@@ -29,18 +29,65 @@ impl AssetLoader for DffLoader {
     }
 }
 
-pub struct Model {
-    pub mesh: Mesh,
+pub struct Frame {
+    pub name: Option<String>,
+    pub parent: Option<usize>,
+    // Relative to the parent frame, or to the instance if there is no parent
     pub transform: Transform,
+    pub bone_id: Option<i32>,
+}
+
+pub struct Model {
+    pub frame_index: usize,
     pub materials: Vec<rwf::dff::Material>,
     pub material_indices: Vec<usize>,
+    pub skin: Option<Skin>,
+    // The model's triangles, split so that every submaterial within a part uses the same
+    // effect textures, as a material can only bind one of each
+    pub parts: Vec<ModelPart>,
+}
+
+pub struct ModelPart {
+    pub mesh: Mesh,
+    // The names of the environment map and dual pass textures this part's submaterials use
+    pub env_map_texture: Option<String>,
+    pub dual_texture: Option<String>,
+    // Empty unless the model has more than one target to animate between
+    pub morph_targets: Vec<MorphTarget>,
+}
+
+// The effect textures a part is split by, as triangles using different ones must be drawn
+// with different bindings
+#[derive(PartialEq)]
+struct PartTextures {
+    env_map_texture: Option<String>,
+    dual_texture: Option<String>,
+}
+
+/// A morph target, laid out like the mesh's (duplicated) vertices.
+#[derive(Clone)]
+pub struct MorphTarget {
+    pub positions: Vec<[f32; 3]>,
+    // Flat, like the mesh's own normals, so they're computed once here rather than
+    // each time the mesh is morphed
+    pub normals: Vec<[f32; 3]>,
+}
+
+pub struct Skin {
+    pub inverse_bindposes: Handle<SkinnedMeshInverseBindposes>,
+    // The frame that each bone is attached to
+    pub joints: Vec<usize>,
 }
 
 #[derive(TypeUuid)]
 #[uuid = "7f24d251-ce34-4078-85b8-a8f99fc790db"]
 pub struct Dff {
     pub name: String,
+    pub frames: Vec<Frame>,
     pub models: Vec<Model>,
+    pub lights: Vec<rwf::dff::Light>,
+    pub cameras: Vec<rwf::dff::Camera>,
+    pub skeleton: Option<rwf::dff::Skeleton>,
 }
 
 async fn load_dff<'a, 'b>(
@@ -55,18 +102,87 @@ async fn load_dff<'a, 'b>(
         .to_string();
 
     let raw = rwf::raw::BinaryStreamFile::from_bytes(bytes)?;
-    let models = rwf::dff::Model::from_raw(&raw)
+    let clump = rwf::dff::Clump::from_raw(&raw)?;
+    let frames = clump
+        .frames
         .into_iter()
-        .map(rwf_model_to_bevy_model)
+        .map(|frame| Frame {
+            name: frame.name,
+            parent: frame.parent,
+            transform: rwf_transform_to_bevy_transform(&frame.transform),
+            bone_id: frame.bone_id,
+        })
         .collect();
+    let models =
+        clump
+            .atomics
+            .into_iter()
+            .filter(|atomic| atomic.render)
+            .enumerate()
+            .map(|(index, atomic)| {
+                // Skins refer to their bones by their index within the skeleton; without one,
+                // the model is rendered in its bind pose.
+                let skin = atomic.model.skin.as_ref().zip(clump.skeleton.as_ref()).map(
+                    |(skin, skeleton)| Skin {
+                        inverse_bindposes: load_context.set_labeled_asset(
+                            &format!("Skin{}", index),
+                            LoadedAsset::new(rwf_skin_to_bevy_inverse_bindposes(skin)),
+                        ),
+                        joints: skeleton.bones.iter().map(|b| b.frame_index).collect(),
+                    },
+                );
+                rwf_model_to_bevy_model(atomic.frame_index, atomic.model, skin)
+            })
+            .collect();
 
-    load_context.set_default_asset(LoadedAsset::new(Dff { name, models }));
+    load_context.set_default_asset(LoadedAsset::new(Dff {
+        name,
+        frames,
+        models,
+        lights: clump.lights,
+        cameras: clump.cameras,
+        skeleton: clump.skeleton,
+    }));
 
     Ok(())
 }
 
-fn rwf_model_to_bevy_model((transform, model): (rwf::dff::Transform, rwf::dff::Model)) -> Model {
-    let mesh = {
+// RenderWare is Z-up, while Bevy is Y-up: (x, y, z) in RenderWare is (x, z, -y) in Bevy.
+fn rwf_to_bevy_basis() -> Mat3 {
+    Mat3::from_cols(Vec3::X, -Vec3::Z, Vec3::Y)
+}
+
+fn rwf_vec3_to_bevy_vec3(v: rwf::dff::Vec3) -> Vec3 {
+    rwf_to_bevy_basis() * Vec3::from(v.as_array())
+}
+
+fn rwf_transform_to_bevy_transform(transform: &rwf::dff::Transform) -> Transform {
+    let basis = rwf_to_bevy_basis();
+    let rotation = Mat3::from_cols_array(&transform.rotation.0);
+    Transform {
+        translation: rwf_vec3_to_bevy_vec3(transform.translation),
+        rotation: Quat::from_mat3(&(basis * rotation * basis.transpose())),
+        scale: Vec3::ONE,
+    }
+}
+
+fn rwf_skin_to_bevy_inverse_bindposes(skin: &rwf::dff::Skin) -> SkinnedMeshInverseBindposes {
+    // Vertices are converted into Bevy's basis before skinning, so the matrices need to
+    // convert them back to RenderWare's basis before applying, and the results out of it after.
+    let basis = Mat4::from_mat3(rwf_to_bevy_basis());
+    skin.inverse_bone_matrices
+        .iter()
+        .map(|m| basis * Mat4::from_cols_array(&m.0) * basis.transpose())
+        .collect::<Vec<_>>()
+        .into()
+}
+
+pub(super) fn rwf_model_to_bevy_model(
+    frame_index: usize,
+    model: rwf::dff::Model,
+    skin: Option<Skin>,
+) -> Model {
+    let base_mesh = {
         let mut mesh = Mesh::new(match model.topology {
             rwf::dff::Topology::TriangleList => PrimitiveTopology::TriangleList,
             rwf::dff::Topology::TriangleStrip => PrimitiveTopology::TriangleStrip,
@@ -75,35 +191,114 @@ fn rwf_model_to_bevy_model((transform, model): (rwf::dff::Transform, rwf::dff::M
         let mut positions = vec![];
         let mut normals = vec![];
         let mut uvs = vec![];
+        let mut second_uvs = vec![];
         let mut material_ids = vec![];
+        let mut joint_indices = vec![];
+        let mut joint_weights = vec![];
+        let mut day_colors = vec![];
+        let mut night_colors = vec![];
         for vertex in &model.vertices {
-            let rwf::dff::Vec3 { x, y, z } = vertex.position;
-            positions.push([x, z, -y]);
-            normals.push(vertex.normal.as_array());
-            uvs.push(vertex.uv);
+            positions.push(rwf_vec3_to_bevy_vec3(vertex.position).to_array());
+            normals.push(rwf_vec3_to_bevy_vec3(vertex.normal).to_array());
+            uvs.push(vertex.uvs[0]);
+            second_uvs.push(vertex.uvs[1]);
             material_ids.push(vertex.material_id as u32);
+            joint_indices.push(vertex.bone_indices.map(u16::from));
+            joint_weights.push(vertex.bone_weights);
+            // Models without night colours are lit the same way at night
+            if let Some(day) = vertex.day_color {
+                day_colors.push(day.as_array());
+                night_colors.push(vertex.night_color.unwrap_or(day).as_array());
+            }
         }
         mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
         mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
         mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        if model.uv_set_count > 1 {
+            mesh.insert_attribute(crate::render::ATTRIBUTE_UV_1, second_uvs);
+        }
         mesh.insert_attribute(crate::render::ATTRIBUTE_MATERIAL_ID, material_ids);
-        mesh.set_indices(Some(Indices::U16(model.indices)));
-        mesh.duplicate_vertices();
-        mesh.compute_flat_normals();
+        // Either every vertex is prelit, or none are
+        if !day_colors.is_empty() {
+            mesh.insert_attribute(crate::render::ATTRIBUTE_DAY_COLOR, day_colors);
+            mesh.insert_attribute(crate::render::ATTRIBUTE_NIGHT_COLOR, night_colors);
+        }
+        // Skinned meshes must have a `SkinnedMesh`, so only add the joints if we can provide one
+        if skin.is_some() {
+            mesh.insert_attribute(Mesh::ATTRIBUTE_JOINT_INDEX, joint_indices);
+            mesh.insert_attribute(Mesh::ATTRIBUTE_JOINT_WEIGHT, joint_weights);
+        }
         mesh
     };
-    let transform = Transform {
-        translation: transform.translation.as_array().into(),
-        rotation: Quat::from_mat3(&Mat3::from_cols_array(&transform.rotation.0)),
-        scale: Vec3::new(1.0, 1.0, 1.0),
+
+    let effect_textures = |material_id: u16| {
+        let material = model
+            .material_indices
+            .get(material_id as usize)
+            .map(|i| &model.materials[*i]);
+        let name = |texture: Option<&rwf::dff::Texture>| Some(texture?.name.to_lowercase());
+        PartTextures {
+            env_map_texture: name(material.and_then(|m| m.env_map()?.1)),
+            dual_texture: name(material.and_then(|m| m.dual_texture())),
+        }
     };
-    let materials = model.materials;
-    let material_indices = model.material_indices;
+    let mut part_indices: Vec<(PartTextures, Vec<u16>)> = vec![];
+    for triangle in model.indices.chunks_exact(3) {
+        let textures = effect_textures(model.vertices[triangle[0] as usize].material_id);
+        match part_indices.iter_mut().find(|(t, _)| *t == textures) {
+            Some((_, indices)) => indices.extend(triangle),
+            None => part_indices.push((textures, triangle.to_vec())),
+        }
+    }
+
+    let parts = part_indices
+        .into_iter()
+        .map(|(textures, indices)| {
+            let mut mesh = base_mesh.clone();
+            mesh.set_indices(Some(Indices::U16(indices.clone())));
+            mesh.duplicate_vertices();
+            mesh.compute_flat_normals();
+            ModelPart {
+                mesh,
+                env_map_texture: textures.env_map_texture,
+                dual_texture: textures.dual_texture,
+                morph_targets: part_morph_targets(&model.morph_targets, &indices),
+            }
+        })
+        .collect();
     Model {
-        mesh,
-        transform,
-        materials,
-        material_indices,
+        frame_index,
+        materials: model.materials,
+        material_indices: model.material_indices,
+        skin,
+        parts,
+    }
+}
+
+fn part_morph_targets(targets: &[rwf::dff::MorphTarget], indices: &[u16]) -> Vec<MorphTarget> {
+    // Duplicating the vertices expanded them through the indices, so the targets must be too
+    let morph_targets = targets
+        .iter()
+        .filter(|target| !target.positions.is_empty())
+        .map(|target| {
+            let positions: Vec<[f32; 3]> = indices
+                .iter()
+                .map(|i| rwf_vec3_to_bevy_vec3(target.positions[*i as usize]).to_array())
+                .collect();
+            let normals = positions
+                .chunks_exact(3)
+                .flat_map(|triangle| {
+                    let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(Vec3::from);
+                    [(b - a).cross(c - a).normalize_or_zero().to_array(); 3]
+                })
+                .collect();
+            MorphTarget { positions, normals }
+        })
+        .collect::<Vec<_>>();
+    if morph_targets.len() > 1 {
+        morph_targets
+    } else {
+        vec![]
     }
 }
 
@@ -114,3 +309,56 @@ impl Plugin for DffPlugin {
         app.add_asset::<Dff>().init_asset_loader::<DffLoader>();
     }
 }
+
+mod tests {
+    #[test]
+    fn lights_models_without_night_colors_by_day() {
+        use renderware_format::dff::{Color, Model, Topology, Vec3, Vertex};
+
+        let vertex = |x: f32, day: u8, night: Option<u8>| {
+            let mut vertex = Vertex::new(
+                Vec3 { x, y: 0.0, z: 0.0 },
+                Vec3 {
+                    x: 0.0,
+                    y: 0.0,
+                    z: 1.0,
+                },
+                [0.0; 2],
+                0,
+            );
+            vertex.day_color = Some(Color::new(day, day, day, 255));
+            vertex.night_color = night.map(|n| Color::new(n, n, n, 255));
+            vertex
+        };
+        let model = |night: Option<u8>| Model {
+            vertices: vec![
+                vertex(0.0, 10, night),
+                vertex(1.0, 20, night),
+                vertex(2.0, 30, night),
+            ],
+            indices: vec![0, 1, 2],
+            topology: Topology::TriangleList,
+            uv_set_count: 1,
+            materials: vec![],
+            material_indices: vec![],
+            skin: None,
+            morph_targets: vec![],
+        };
+        let colors = |model: Model| {
+            let model = super::rwf_model_to_bevy_model(0, model, None);
+            let mesh = &model.parts[0].mesh;
+            let bytes = |attribute| mesh.attribute(attribute).unwrap().get_bytes().to_vec();
+            (
+                bytes(crate::render::ATTRIBUTE_DAY_COLOR),
+                bytes(crate::render::ATTRIBUTE_NIGHT_COLOR),
+            )
+        };
+
+        let (day, night) = colors(model(None));
+        assert_eq!(night, day);
+        assert_eq!(&day[..4], [10, 10, 10, 255]);
+        let (day, night) = colors(model(Some(5)));
+        assert_ne!(night, day);
+        assert_eq!(&night[..4], [5, 5, 5, 255]);
+    }
+}
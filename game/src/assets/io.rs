@@ -1,35 +1,157 @@
 use bevy::{
-    asset::{AssetIo, AssetIoError},
+    asset::{AssetIo, AssetIoError, AssetServerSettings, FileAssetIo},
     prelude::*,
-    utils::BoxedFuture,
+    tasks::{TaskPool, TaskPoolBuilder},
+    utils::{BoxedFuture, HashMap},
 };
-use std::path::{Path, PathBuf};
+use std::{
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use vice_city_formats::Img;
+
+// Archives to mount, relative to the asset folder. Each archive's contents
+// appear in a directory named after the archive, e.g. `models/gta3/bar.dff`.
+const ARCHIVES: &[&str] = &["models/gta3.img"];
+
+struct MountedImg {
+    mount_point: PathBuf,
+    img: Img,
+    // Lowercased entry name to index in `img.entries`
+    entry_indices: HashMap<String, usize>,
+    // Kept open for as long as the archive is mounted, rather than reopened for each read
+    file: Arc<Mutex<File>>,
+}
+
+impl MountedImg {
+    fn new(mount_point: PathBuf, img: Img) -> io::Result<Self> {
+        let entry_indices = img
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(idx, e)| (e.name.to_ascii_lowercase(), idx))
+            .collect();
+        let file = Arc::new(Mutex::new(File::open(&img.path)?));
+
+        Ok(Self {
+            mount_point,
+            img,
+            entry_indices,
+            file,
+        })
+    }
+
+    fn entry_for_path(&self, path: &Path) -> Option<&vice_city_formats::img::Entry> {
+        if path.parent() != Some(self.mount_point.as_path()) {
+            return None;
+        }
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        Some(&self.img.entries[*self.entry_indices.get(&name)?])
+    }
+}
 
 struct ImgAssetIo {
     base: Box<dyn AssetIo>,
+    archives: Vec<MountedImg>,
+    // Entries are read with blocking file IO, which is kept off the threads that poll the
+    // asset server's loads
+    reader: TaskPool,
 }
 
 impl ImgAssetIo {
-    fn new(base: Box<dyn AssetIo>) -> Self {
-        Self { base }
+    fn new(base: Box<dyn AssetIo>, root_path: &Path) -> Self {
+        let archives = ARCHIVES
+            .iter()
+            .map(Path::new)
+            .filter_map(|archive_path| {
+                let mounted = Img::open(root_path.join(archive_path))
+                    .and_then(|img| MountedImg::new(archive_path.with_extension(""), img));
+                match mounted {
+                    Ok(archive) => {
+                        info!(
+                            "mounted {:?} ({:?}, {} entries)",
+                            archive_path,
+                            archive.img.version,
+                            archive.img.entries.len()
+                        );
+                        Some(archive)
+                    }
+                    Err(err) => {
+                        info!("not mounting {:?}: {}", archive_path, err);
+                        None
+                    }
+                }
+            })
+            .collect();
+        let reader = TaskPoolBuilder::new()
+            .num_threads(1)
+            .thread_name("IMG reader".to_string())
+            .build();
+
+        Self {
+            base,
+            archives,
+            reader,
+        }
+    }
+
+    fn archive_at(&self, path: &Path) -> Option<&MountedImg> {
+        self.archives.iter().find(|a| a.mount_point == path)
     }
 }
 
 impl AssetIo for ImgAssetIo {
     fn load_path<'a>(&'a self, path: &'a Path) -> BoxedFuture<'a, Result<Vec<u8>, AssetIoError>> {
-        if path.parent() == Some(&Path::new("models/gta3")) {}
-        self.base.load_path(path)
+        let archived = self
+            .archives
+            .iter()
+            .find_map(|a| Some((a, a.entry_for_path(path)?)));
+
+        match archived {
+            Some((archive, entry)) => {
+                let file = archive.file.clone();
+                let entry = entry.clone();
+                let read = self
+                    .reader
+                    .spawn(async move { entry.read(&mut *file.lock().unwrap()) });
+                Box::pin(async move { Ok(read.await?) })
+            }
+            None => self.base.load_path(path),
+        }
     }
 
     fn read_directory(
         &self,
         path: &Path,
     ) -> Result<Box<dyn Iterator<Item = PathBuf>>, AssetIoError> {
-        self.base.read_directory(path)
+        let archive = match self.archive_at(path) {
+            Some(archive) => archive,
+            None => return self.base.read_directory(path),
+        };
+
+        // Loose files that aren't in the archive are still listed, so that the directory
+        // looks the same whether or not its contents have been extracted.
+        let loose_files: Vec<_> = match self.base.read_directory(path) {
+            Ok(paths) => paths
+                .filter(|p| archive.entry_for_path(p).is_none())
+                .collect(),
+            Err(_) => vec![],
+        };
+        let archived_files: Vec<_> = archive
+            .img
+            .entries
+            .iter()
+            .map(|e| archive.mount_point.join(&e.name))
+            .collect();
+
+        Ok(Box::new(archived_files.into_iter().chain(loose_files)))
     }
 
     fn is_directory(&self, path: &Path) -> bool {
-        self.base.is_directory(path)
+        self.archive_at(path).is_some() || self.base.is_directory(path)
     }
 
     fn watch_path_for_changes(&self, path: &Path) -> Result<(), AssetIoError> {
@@ -41,13 +163,63 @@ impl AssetIo for ImgAssetIo {
     }
 }
 
+/// Replaces the default asset IO with one that can also read from the game's IMG archives.
+/// Must be added before [`bevy::asset::AssetPlugin`].
 pub struct ImgIoPlugin;
 
 impl Plugin for ImgIoPlugin {
     fn build(&self, app: &mut App) {
         let task_pool = app.world.resource::<bevy::tasks::IoTaskPool>().0.clone();
-        let asset_io = ImgAssetIo::new(bevy::asset::create_platform_default_asset_io(app));
+        let root_path = {
+            let settings = app
+                .world
+                .get_resource_or_insert_with(AssetServerSettings::default);
+            FileAssetIo::get_root_path().join(&settings.asset_folder)
+        };
+        let asset_io = ImgAssetIo::new(
+            bevy::asset::create_platform_default_asset_io(app),
+            &root_path,
+        );
 
         app.insert_resource(AssetServer::new(asset_io, task_pool));
     }
 }
+
+mod tests {
+    #[test]
+    fn loads_archived_and_loose_files() {
+        use super::ImgAssetIo;
+        use bevy::asset::{AssetIo, FileAssetIo};
+        use futures_lite::future::block_on;
+        use std::path::Path;
+        use vice_city_formats::img::{Img, Version};
+
+        // An asset folder with the archive mounted at `models/gta3`, and a file beside it
+        let root = std::env::temp_dir().join(format!("bevy-city-img-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("models")).unwrap();
+        let mut img = Img::create(root.join("models/gta3.img"), Version::V2).unwrap();
+        img.add("bar.dff", b"archived").unwrap();
+        std::fs::write(root.join("models/loose.ide"), b"loose").unwrap();
+
+        let asset_io = ImgAssetIo::new(Box::new(FileAssetIo::new(&root, false)), &root);
+        let load = |path: &str| block_on(asset_io.load_path(Path::new(path)));
+
+        // Entries come padded to a whole sector, and are found whatever their case
+        for path in ["models/gta3/bar.dff", "models/gta3/BAR.DFF"] {
+            let data = load(path).unwrap();
+            assert!(data.starts_with(b"archived"));
+            assert_eq!(data.len(), 2048);
+        }
+        // Anything else is left to the filesystem
+        assert_eq!(load("models/loose.ide").unwrap(), b"loose");
+        assert!(load("models/gta3/missing.dff").is_err());
+        assert!(asset_io.is_directory(Path::new("models/gta3")));
+        let listed: Vec<_> = asset_io
+            .read_directory(Path::new("models/gta3"))
+            .unwrap()
+            .collect();
+        assert_eq!(listed, [Path::new("models/gta3/bar.dff")]);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
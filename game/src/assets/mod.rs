@@ -1,15 +1,19 @@
 use bevy::{app::PluginGroupBuilder, prelude::PluginGroup};
 
+mod bsp;
 mod dat;
 mod dff;
 mod ide;
+mod io;
 mod ipl;
 pub mod txd;
 
 pub use self::{
+    bsp::Bsp,
     dat::Dat,
-    dff::{Dff, Model},
+    dff::{Dff, Frame, Model, MorphTarget},
     ide::Ide,
+    io::ImgIoPlugin,
     ipl::Ipl,
     txd::{Texture, Txd},
 };
@@ -18,6 +22,7 @@ pub struct ViceCityPluginGroup;
 impl PluginGroup for ViceCityPluginGroup {
     fn build(&mut self, group: &mut PluginGroupBuilder) {
         group
+            .add(bsp::BspPlugin)
             .add(dat::DatPlugin)
             .add(dff::DffPlugin)
             .add(ide::IdePlugin)
@@ -37,7 +37,8 @@ async fn load_txd<'a, 'b>(
     load_context: &'a mut LoadContext<'b>,
 ) -> anyhow::Result<()> {
     let raw = rwf::raw::BinaryStreamFile::from_bytes(bytes)?;
-    let textures = rwf::txd::Texture::from_raw(&raw);
+    // DXT textures stay compressed, and are decoded when they're turned into images if need be
+    let textures = rwf::txd::Texture::from_raw_compressed(&raw)?;
     load_context.set_default_asset(LoadedAsset::new(Txd { textures }));
 
     Ok(())
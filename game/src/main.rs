@@ -3,8 +3,12 @@
 use std::{collections::HashMap, path::PathBuf};
 
 use bevy::{
+    asset::LoadState,
     prelude::*,
-    render::{render_resource::WgpuFeatures, settings::WgpuSettings},
+    render::{
+        mesh::skinning::SkinnedMesh, render_resource::WgpuFeatures, renderer::RenderDevice,
+        settings::WgpuSettings,
+    },
 };
 
 use bevy_atmosphere::*;
@@ -17,7 +21,7 @@ use bevy_flycam::{FlyCam, MovementSettings, NoCameraPlayerPlugin};
 use clap::Parser;
 
 pub mod assets;
-use assets::{Dat, Dff, Ide, Ipl, Txd};
+use assets::{Bsp, Dat, Dff, Ide, Ipl, MorphTarget, Txd};
 
 pub mod render;
 use render::*;
@@ -25,20 +29,35 @@ use render::*;
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
 struct Cli {
-    /// If provided, render this asset by itself
+    /// If provided, render this asset (a DFF or BSP) by itself
     #[clap(short, long)]
     path: Option<PathBuf>,
 
     /// If provided, only IPLs with this in their name will be loaded
     #[clap(short, long)]
     ipl_filter: Option<String>,
+
+    /// How many morph targets a model moves through each second, as `model=rate`.
+    /// Models that aren't listed move through one a second
+    #[clap(long, parse(try_from_str = parse_morph_rate), multiple_occurrences(true))]
+    morph_rate: Vec<(String, f32)>,
+}
+
+fn parse_morph_rate(s: &str) -> anyhow::Result<(String, f32)> {
+    let (model, rate) = s
+        .split_once('=')
+        .ok_or_else(|| anyhow::anyhow!("expected `model=rate`, got `{s}`"))?;
+    Ok((model.to_lowercase(), rate.parse()?))
 }
 
 struct DesiredAssetRenderPath(PathBuf);
 struct IplFilter(Option<String>);
 
 struct DesiredAssetMeshes(Vec<(Handle<Dff>, Transform, bool)>);
+struct DesiredBsps(Vec<(Handle<Bsp>, bool)>);
 struct GlobalDat(Handle<Dat>);
+// The textures shared between models, such as vehicles' environment maps
+struct GenericTxd(Handle<Txd>);
 #[derive(PartialEq, Eq)]
 enum LoadedIdes {
     Unloaded,
@@ -51,11 +70,23 @@ enum PendingIpls {
     Loaded(Vec<Handle<Ipl>>),
 }
 struct ModelTextureMap(HashMap<String, String>);
-type DffAssetHandles = (Handle<Mesh>, Handle<GtaMaterial>);
+// Morph targets per second for each model, by lowercase name
+struct MorphRates(HashMap<String, f32>);
+// A mesh and material for each of a model's parts
+type DffAssetHandles = Vec<(Handle<Mesh>, Handle<GtaMaterial>)>;
 struct DffCache(HashMap<String, Vec<DffAssetHandles>>);
 struct GameTime(f32);
 #[derive(Component)]
 struct Sun;
+/// A camera that came with a DFF. These aren't rendered from unless made active.
+#[derive(Component, Default)]
+struct DffCamera;
+/// Loops a model's mesh through its morph targets, interpolating between each pair.
+#[derive(Component)]
+struct MorphAnimation {
+    targets: Vec<MorphTarget>,
+    targets_per_second: f32,
+}
 
 const EXTERIOR_MAP_SIZE: f32 = 10_000.0;
 
@@ -70,14 +101,18 @@ fn main() -> anyhow::Result<()> {
             features: WgpuFeatures::POLYGON_MODE_LINE,
             ..default()
         })
-        .add_plugins(DefaultPlugins)
+        .add_plugins_with(DefaultPlugins, |group| {
+            group.add_before::<bevy::asset::AssetPlugin, _>(assets::ImgIoPlugin)
+        })
         .add_plugins(assets::ViceCityPluginGroup)
         .add_plugin(RenderPlugin)
         .add_plugin(EditorPlugin)
         .insert_resource(IplFilter(args.ipl_filter))
         .insert_resource(DesiredAssetMeshes(vec![]))
+        .insert_resource(DesiredBsps(vec![]))
         .insert_resource(LoadedIdes::Unloaded)
         .insert_resource(ModelTextureMap(HashMap::new()))
+        .insert_resource(MorphRates(args.morph_rate.into_iter().collect()))
         .insert_resource(DffCache(HashMap::new()));
 
     // Loading systems
@@ -85,7 +120,9 @@ fn main() -> anyhow::Result<()> {
         .add_system(handle_dat_events)
         .add_system(handle_ipl_events)
         .add_system(process_pending_desired_meshes)
-        .add_system(process_pending_ides);
+        .add_system(process_pending_bsps)
+        .add_system(process_pending_ides)
+        .add_system(animate_morph_targets);
 
     // Primary behaviour
     if let Some(path) = args.path {
@@ -109,6 +146,7 @@ fn main() -> anyhow::Result<()> {
             })
             .insert_resource(GameTime(12.0))
             .add_system(update_game_time)
+            .add_system(update_day_night_balance)
             .add_editor_window::<TimeEditorWindow>()
             // Bevy atmosphere
             .insert_resource(AtmosphereMat::default())
@@ -126,19 +164,29 @@ fn main() -> anyhow::Result<()> {
 
 fn load_vice_city_dat(mut commands: Commands, asset_server: Res<AssetServer>) {
     commands.insert_resource(GlobalDat(asset_server.load("data/gta_vc.dat")));
+    commands.insert_resource(GenericTxd(asset_server.load("models/generic.txd")));
 }
 
 fn asset_viewer(
     mut commands: Commands,
     mut desired_asset_meshes: ResMut<DesiredAssetMeshes>,
+    mut desired_bsps: ResMut<DesiredBsps>,
     asset_server: Res<AssetServer>,
     desired_asset_render_path: Res<DesiredAssetRenderPath>,
 ) {
-    desired_asset_meshes.0.push((
-        asset_server.load(desired_asset_render_path.0.as_path()),
-        Transform::from_xyz(0.0, 0.5, 0.0),
-        false,
-    ));
+    let path = desired_asset_render_path.0.as_path();
+    if path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("bsp"))
+    {
+        desired_bsps.0.push((asset_server.load(path), false));
+    } else {
+        desired_asset_meshes.0.push((
+            asset_server.load(path),
+            Transform::from_xyz(0.0, 0.5, 0.0),
+            false,
+        ));
+    }
 
     commands.spawn_bundle(PointLightBundle {
         point_light: PointLight {
@@ -270,17 +318,24 @@ fn process_pending_desired_meshes(
     mut dff_cache: ResMut<DffCache>,
     loaded_ides: Res<LoadedIdes>,
     model_texture_map: Res<ModelTextureMap>,
+    generic_txd: Res<GenericTxd>,
+    morph_rates: Res<MorphRates>,
     asset_server: Res<AssetServer>,
     asset_meshes: Res<Assets<Dff>>,
     asset_txds: Res<Assets<Txd>>,
+    render_device: Res<RenderDevice>,
 ) {
     if *loaded_ides != LoadedIdes::Processed {
         return;
     }
 
+    let supports_bc = render_device
+        .features()
+        .contains(WgpuFeatures::TEXTURE_COMPRESSION_BC);
+
     for (handle, transform, spawned) in desired_asset_meshes.0.iter_mut().filter(|(_, _, s)| !*s) {
         if let Some(dff) = asset_meshes.get(handle.clone()) {
-            if let Some(bundles) = attempt_to_spawn_dff(
+            if let Some(model_handles) = attempt_to_load_dff(
                 &mut materials,
                 &mut meshes,
                 &mut images,
@@ -288,43 +343,163 @@ fn process_pending_desired_meshes(
                 &asset_server,
                 &asset_txds,
                 &model_texture_map,
+                &generic_txd,
                 dff,
-                *transform,
+                supports_bc,
             ) {
-                for bundle in bundles {
-                    commands.spawn_bundle(bundle);
-                }
+                spawn_dff(&mut commands, dff, model_handles, *transform, &morph_rates);
                 *spawned = true;
             }
         }
     }
 }
 
-pub fn packed_texture_to_image(texture: &renderware_format::packer::PackedTexture) -> Image {
-    use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
-    Image::new(
-        Extent3d {
-            width: texture.width as _,
-            height: texture.height as _,
-            depth_or_array_layers: 1,
-        },
-        TextureDimension::D2,
-        texture.data.clone(),
-        TextureFormat::Rgba8Unorm,
+fn process_pending_bsps(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<GtaMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut images: ResMut<Assets<Image>>,
+    mut desired_bsps: ResMut<DesiredBsps>,
+    generic_txd: Res<GenericTxd>,
+    asset_server: Res<AssetServer>,
+    asset_bsps: Res<Assets<Bsp>>,
+    asset_txds: Res<Assets<Txd>>,
+    render_device: Res<RenderDevice>,
+) {
+    let supports_bc = render_device
+        .features()
+        .contains(WgpuFeatures::TEXTURE_COMPRESSION_BC);
+    // Either TXD may not exist, so only wait for them while they're still loading
+    let loaded_txd = |handle: &Handle<Txd>| match asset_server.get_load_state(handle) {
+        LoadState::Loaded => Some(asset_txds.get(handle)),
+        LoadState::Failed => Some(None),
+        _ => None,
+    };
+    let generic_txd = match loaded_txd(&generic_txd.0) {
+        Some(txd) => txd,
+        None => return,
+    };
+
+    for (handle, spawned) in desired_bsps.0.iter_mut().filter(|(_, s)| !*s) {
+        let bsp = match asset_bsps.get(&*handle) {
+            Some(bsp) => bsp,
+            None => continue,
+        };
+        // A world's textures are kept in a TXD of the same name beside it
+        let txd_handle: Handle<Txd> = match asset_server.get_handle_path(&*handle) {
+            Some(path) => asset_server.load(path.path().with_extension("txd")),
+            None => continue,
+        };
+        let txd = match loaded_txd(&txd_handle) {
+            Some(txd) => txd,
+            None => continue,
+        };
+
+        let model_handles: Vec<_> = bsp
+            .models
+            .iter()
+            .map(|model| {
+                model_handles(
+                    &mut materials,
+                    &mut meshes,
+                    &mut images,
+                    &bsp.name,
+                    model,
+                    txd,
+                    generic_txd,
+                    supports_bc,
+                )
+            })
+            .collect();
+        spawn_bsp(&mut commands, bsp, &model_handles);
+        *spawned = true;
+    }
+}
+
+pub fn packed_texture_to_image(
+    texture: &renderware_format::packer::PackedTexture,
+    supports_bc: bool,
+) -> Image {
+    levels_to_image(
+        texture.width,
+        texture.height,
+        texture.format,
+        &texture.levels,
+        supports_bc,
+    )
+}
+
+pub fn txd_texture_to_image(texture: &renderware_format::txd::Texture, supports_bc: bool) -> Image {
+    levels_to_image(
+        texture.width,
+        texture.height,
+        texture.format,
+        &texture.levels,
+        supports_bc,
     )
 }
 
-fn attempt_to_spawn_dff(
+// Builds an image with the full mip chain, blending between levels so that distant surfaces
+// don't shimmer. Compressed levels are uploaded as they are if the device can sample them,
+// and decoded into RGBA8 otherwise.
+fn levels_to_image(
+    width: u16,
+    height: u16,
+    format: renderware_format::txd::PixelFormat,
+    levels: &[Vec<u8>],
+    supports_bc: bool,
+) -> Image {
+    use bevy::render::render_resource::{Extent3d, FilterMode, TextureFormat};
+    use renderware_format::txd::PixelFormat;
+
+    // Block-compressed textures also have to be a whole number of blocks across
+    let is_uploadable = supports_bc && width.is_multiple_of(4) && height.is_multiple_of(4);
+    let (texture_format, levels) = match format {
+        PixelFormat::Bc1 if is_uploadable => (TextureFormat::Bc1RgbaUnorm, levels.to_vec()),
+        PixelFormat::Bc2 if is_uploadable => (TextureFormat::Bc2RgbaUnorm, levels.to_vec()),
+        PixelFormat::Bc3 if is_uploadable => (TextureFormat::Bc3RgbaUnorm, levels.to_vec()),
+        _ => {
+            let levels = levels
+                .iter()
+                .enumerate()
+                .map(|(level, data)| {
+                    let size = |size: u16| (u32::from(size) >> level).max(1);
+                    format.decompress(data, size(width), size(height))
+                })
+                .collect();
+            (TextureFormat::Rgba8Unorm, levels)
+        }
+    };
+    // wgpu rejects more levels than it takes to reach a single pixel
+    let max_level_count = 32 - u32::from(width.max(height)).leading_zeros();
+    let levels = &levels[..levels.len().min(max_level_count as usize)];
+
+    // `Image::new` expects a single level of uncompressed data, so this is filled in directly
+    let mut image = Image::default();
+    image.texture_descriptor.size = Extent3d {
+        width: width as _,
+        height: height as _,
+        depth_or_array_layers: 1,
+    };
+    image.texture_descriptor.format = texture_format;
+    image.texture_descriptor.mip_level_count = levels.len() as u32;
+    image.data = levels.concat();
+    image.sampler_descriptor.mipmap_filter = FilterMode::Linear;
+    image
+}
+
+fn attempt_to_load_dff<'a>(
     gta_materials: &mut Assets<GtaMaterial>,
     meshes: &mut Assets<Mesh>,
     images: &mut Assets<Image>,
-    dff_cache: &mut DffCache,
+    dff_cache: &'a mut DffCache,
     asset_server: &AssetServer,
     asset_txds: &Assets<Txd>,
     model_texture_map: &ModelTextureMap,
+    generic_txd: &GenericTxd,
     dff: &Dff,
-    transform: Transform,
-) -> Option<Vec<GtaBundle>> {
+    supports_bc: bool,
+) -> Option<&'a [DffAssetHandles]> {
     // If this model has an associated texture, load the texture.
     // If the texture is not loaded yet, do not attempt to spawn this model, and try again later.
     let texture_path = model_texture_map
@@ -341,72 +516,212 @@ fn attempt_to_spawn_dff(
         },
         None => None,
     };
+    // Effect textures that aren't in the model's own TXD come from here. Not every install
+    // has one, so only wait for it while it's still loading
+    let generic_txd = match asset_server.get_load_state(&generic_txd.0) {
+        LoadState::Loaded => asset_txds.get(&generic_txd.0),
+        LoadState::Failed => None,
+        _ => return None,
+    };
 
     let cache_entry = dff_cache.0.entry(dff.name.clone()).or_insert_with(|| {
         dff.models
             .iter()
-            .map(|model: &assets::Model| {
-                let packed_texture = txd.map(|txd| {
-                    renderware_format::packer::repack_model_textures(
-                        &model.materials,
-                        &model.material_indices,
-                        &txd.textures,
-                    )
-                });
-
-                if packed_texture
-                    .as_ref()
-                    .map(|pt| pt.frames.len())
-                    .unwrap_or_default()
-                    > render::gta_material::SUBMATERIAL_MAX_COUNT
-                {
-                    panic!(
-                        "the dff {}.dff ({}) exceeds the submaterial count with {}",
-                        dff.name,
-                        texture_path.as_ref().unwrap_or(&String::new()),
-                        packed_texture.unwrap().frames.len()
-                    );
-                }
-
-                let mesh = meshes.add(model.mesh.clone());
-                let material = gta_materials.add(GtaMaterial {
-                    base_color_texture: packed_texture.as_ref().map(|pt| {
-                        use bevy::render::render_resource::{
-                            Extent3d, TextureDimension, TextureFormat,
-                        };
-                        let asset = Image::new(
-                            Extent3d {
-                                width: pt.width as _,
-                                height: pt.height as _,
-                                depth_or_array_layers: 1,
-                            },
-                            TextureDimension::D2,
-                            pt.data.clone(),
-                            TextureFormat::Rgba8Unorm,
-                        );
-                        images.add(asset)
-                    }),
-                    materials: model.materials.clone(),
-                    frames: packed_texture.as_ref().map(|pt| pt.frames.clone()),
-                    ..default()
-                });
-
-                (mesh, material)
+            .map(|model| {
+                model_handles(
+                    gta_materials,
+                    meshes,
+                    images,
+                    &dff.name,
+                    model,
+                    txd,
+                    generic_txd,
+                    supports_bc,
+                )
             })
             .collect()
     });
 
-    Some(
-        cache_entry
-            .iter()
-            .map(|(mesh, material)| GtaBundle {
+    Some(cache_entry)
+}
+
+/// Creates a mesh and material for each part of `model`, taking its textures from `txd`,
+/// and any effect textures missing from it from `generic_txd`.
+fn model_handles(
+    gta_materials: &mut Assets<GtaMaterial>,
+    meshes: &mut Assets<Mesh>,
+    images: &mut Assets<Image>,
+    name: &str,
+    model: &assets::Model,
+    txd: Option<&Txd>,
+    generic_txd: Option<&Txd>,
+    supports_bc: bool,
+) -> DffAssetHandles {
+    let packed_texture = txd.map(|txd| {
+        renderware_format::packer::repack_model_textures(
+            &model.materials,
+            &model.material_indices,
+            &txd.textures,
+        )
+    });
+
+    if packed_texture
+        .as_ref()
+        .map(|pt| pt.frames.len())
+        .unwrap_or_default()
+        > render::gta_material::SUBMATERIAL_MAX_COUNT
+    {
+        panic!(
+            "the model {} exceeds the submaterial count with {}",
+            name,
+            packed_texture.unwrap().frames.len()
+        );
+    }
+
+    let base_color_texture = packed_texture
+        .as_ref()
+        .map(|pt| images.add(packed_texture_to_image(pt, supports_bc)));
+    // The mesh's material IDs index the material list, which may refer to the same material
+    // more than once
+    let submaterials: Vec<_> = model
+        .material_indices
+        .iter()
+        .map(|i| model.materials[*i].clone())
+        .collect();
+
+    // Material effects name their textures, which are looked up in the model's TXD, and then
+    // in the generic one
+    let mut effect_texture = |texture_name: &Option<String>| {
+        let texture_name = texture_name.as_ref()?;
+        let texture = txd
+            .into_iter()
+            .chain(generic_txd)
+            .flat_map(|txd| &txd.textures)
+            .find(|t| t.name.eq_ignore_ascii_case(texture_name))?;
+        Some(images.add(txd_texture_to_image(texture, supports_bc)))
+    };
+    model
+        .parts
+        .iter()
+        .map(|part| {
+            let mesh = meshes.add(part.mesh.clone());
+            let material = gta_materials.add(GtaMaterial {
+                base_color_texture: base_color_texture.clone(),
+                materials: submaterials.clone(),
+                frames: packed_texture.as_ref().map(|pt| pt.frames.clone()),
+                secondary_texture: effect_texture(&part.dual_texture),
+                env_map_texture: effect_texture(&part.env_map_texture),
+                ..default()
+            });
+            (mesh, material)
+        })
+        .collect()
+}
+
+/// Spawns the DFF as an entity at `transform`, with a child entity for each of its frames
+/// (following the frame hierarchy), each of which has a child entity for each of its models.
+fn spawn_dff(
+    commands: &mut Commands,
+    dff: &Dff,
+    model_handles: &[DffAssetHandles],
+    transform: Transform,
+    morph_rates: &MorphRates,
+) -> Entity {
+    let root = commands
+        .spawn_bundle(TransformBundle::from_transform(transform))
+        .insert(Name::new(dff.name.clone()))
+        .id();
+
+    let frames: Vec<Entity> = dff
+        .frames
+        .iter()
+        .map(|frame| {
+            let mut entity =
+                commands.spawn_bundle(TransformBundle::from_transform(frame.transform));
+            if let Some(name) = &frame.name {
+                entity.insert(Name::new(name.clone()));
+            }
+            entity.id()
+        })
+        .collect();
+
+    for (frame, entity) in dff.frames.iter().zip(frames.iter()) {
+        let parent = frame.parent.map(|p| frames[p]).unwrap_or(root);
+        commands.entity(parent).add_child(*entity);
+    }
+
+    for (model, parts) in dff.models.iter().zip(model_handles) {
+        for (part, (mesh, material)) in model.parts.iter().zip(parts) {
+            let mut entity = commands.spawn_bundle(GtaBundle {
                 mesh: mesh.clone(),
                 material: material.clone(),
-                transform,
                 ..default()
-            })
-            .collect(),
-    )
+            });
+            if let Some(skin) = &model.skin {
+                entity.insert(SkinnedMesh {
+                    inverse_bindposes: skin.inverse_bindposes.clone(),
+                    joints: skin.joints.iter().map(|j| frames[*j]).collect(),
+                });
+            }
+            if !part.morph_targets.is_empty() {
+                entity.insert(MorphAnimation {
+                    targets: part.morph_targets.clone(),
+                    targets_per_second: morph_rates
+                        .0
+                        .get(&dff.name.to_lowercase())
+                        .copied()
+                        .unwrap_or(1.0),
+                });
+            }
+            let entity = entity.id();
+            commands.entity(frames[model.frame_index]).add_child(entity);
+        }
+    }
+
+    for light in &dff.lights {
+        use renderware_format::dff::LightType;
+        let [r, g, b] = light.color;
+        let point_light = PointLight {
+            color: Color::rgb(r, g, b),
+            range: light.radius,
+            ..default()
+        };
+        let entity = match light.light_type {
+            // Bevy has no spot lights yet, so they light in every direction instead
+            LightType::Point | LightType::Spot | LightType::SoftSpot => commands
+                .spawn_bundle(PointLightBundle {
+                    point_light,
+                    ..default()
+                })
+                .id(),
+            // These would light the entire scene, which is the game's job, and there's no
+            // telling what types we don't know do
+            LightType::Directional | LightType::Ambient | LightType::Unknown(_) => continue,
+        };
+        commands.entity(frames[light.frame_index]).add_child(entity);
+    }
+
+    for camera in &dff.cameras {
+        use renderware_format::dff::CameraProjection;
+        if camera.projection != CameraProjection::Perspective {
+            continue;
+        }
+        let mut bundle = PerspectiveCameraBundle::<DffCamera>::new();
+        bundle.perspective_projection.fov = camera.vertical_fov();
+        bundle.perspective_projection.near = camera.near_plane;
+        bundle.perspective_projection.far = camera.far_plane;
+        bundle.camera.near = camera.near_plane;
+        bundle.camera.far = camera.far_plane;
+        // RenderWare cameras look along their frame's Z axis with Y up, which are Y and -Z
+        // in Bevy's basis; Bevy cameras look along -Z with Y up
+        bundle.transform = Transform::default().looking_at(Vec3::Y, -Vec3::Z);
+        let entity = commands.spawn_bundle(bundle).id();
+        commands
+            .entity(frames[camera.frame_index])
+            .add_child(entity);
+    }
+
+    root
 }
 
 fn process_pending_ides(
@@ -429,10 +744,109 @@ fn process_pending_ides(
     }
 }
 
+/// Spawns the BSP as an entity, with a child entity for each part of each of its sectors.
+fn spawn_bsp(commands: &mut Commands, bsp: &Bsp, model_handles: &[DffAssetHandles]) -> Entity {
+    let root = commands
+        .spawn_bundle(TransformBundle::identity())
+        .insert(Name::new(bsp.name.clone()))
+        .id();
+    // The sectors are already in world space
+    for (mesh, material) in model_handles.iter().flatten() {
+        let entity = commands
+            .spawn_bundle(GtaBundle {
+                mesh: mesh.clone(),
+                material: material.clone(),
+                ..default()
+            })
+            .id();
+        commands.entity(root).add_child(entity);
+    }
+    root
+}
+
+fn animate_morph_targets(
+    mut meshes: ResMut<Assets<Mesh>>,
+    query: Query<(&Handle<Mesh>, &MorphAnimation)>,
+    time: Res<Time>,
+) {
+    // Instances of a model share its mesh, so each mesh only needs updating once
+    let mut updated = std::collections::HashSet::new();
+    for (handle, animation) in query.iter() {
+        if !updated.insert(handle.id) {
+            continue;
+        }
+        let mesh = match meshes.get_mut(handle) {
+            Some(mesh) => mesh,
+            None => continue,
+        };
+
+        let count = animation.targets.len();
+        let position = time.seconds_since_startup() as f32 * animation.targets_per_second;
+        let index = position.floor() as usize % count;
+        let t = position.fract();
+        let (from, to) = (
+            &animation.targets[index],
+            &animation.targets[(index + 1) % count],
+        );
+        let lerp = |a: &[[f32; 3]], b: &[[f32; 3]]| -> Vec<Vec3> {
+            a.iter()
+                .zip(b)
+                .map(|(a, b)| Vec3::from(*a).lerp(Vec3::from(*b), t))
+                .collect()
+        };
+        let positions: Vec<[f32; 3]> = lerp(&from.positions, &to.positions)
+            .into_iter()
+            .map(|p| p.to_array())
+            .collect();
+        let normals: Vec<[f32; 3]> = lerp(&from.normals, &to.normals)
+            .into_iter()
+            .map(|n| n.normalize_or_zero().to_array())
+            .collect();
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    }
+}
+
 fn update_game_time(mut game_time: ResMut<GameTime>, time: Res<Time>) {
     game_time.0 = (game_time.0 + (time.delta_seconds() / 60.0)) % 24.0;
 }
 
+// Prelit meshes fade to their night colours between 20h and 21h, and back between 6h and 7h.
+fn day_night_balance(game_time: f32) -> f32 {
+    match game_time {
+        t if t < 6.0 => 1.0,
+        t if t < 7.0 => 7.0 - t,
+        t if t < 20.0 => 0.0,
+        t if t < 21.0 => t - 20.0,
+        _ => 1.0,
+    }
+}
+
+fn update_day_night_balance(
+    game_time: Res<GameTime>,
+    mut gta_materials: ResMut<Assets<GtaMaterial>>,
+    mut material_events: EventReader<AssetEvent<GtaMaterial>>,
+    mut last_balance: Local<f32>,
+) {
+    // Changing a material re-uploads it, so only do so when the change would be visible
+    let balance = (day_night_balance(game_time.0) * 64.0).round() / 64.0;
+    if balance != *last_balance {
+        *last_balance = balance;
+        for (_, material) in gta_materials.iter_mut() {
+            material.day_night_balance = balance;
+        }
+    }
+
+    // Materials created since then still need to be brought up to date
+    for event in material_events.iter() {
+        if let AssetEvent::Created { handle } = event {
+            if let Some(material) = gta_materials.get_mut(handle) {
+                material.day_night_balance = balance;
+            }
+        }
+    }
+}
+
 pub struct TimeEditorWindow;
 impl EditorWindow for TimeEditorWindow {
     type State = ();
@@ -45,8 +45,29 @@ struct SubmaterialData {
     color: vec4<f32>;
     uv_top_left: vec2<f32>;
     uv_bottom_right: vec2<f32>;
+    env_map_coefficient: f32;
+    flags: u32;
+    metallic: f32;
+    reflectance: f32;
+    src_blend: u32;
+    dst_blend: u32;
 };
 
+let GTA_SUBMATERIAL_FLAGS_DUAL_TEXTURE: u32 = 1u;
+
+// RenderWare's blend functions
+let GTA_BLEND_ZERO: u32            = 1u;
+let GTA_BLEND_ONE: u32             = 2u;
+let GTA_BLEND_SRC_COLOR: u32       = 3u;
+let GTA_BLEND_INV_SRC_COLOR: u32   = 4u;
+let GTA_BLEND_SRC_ALPHA: u32       = 5u;
+let GTA_BLEND_INV_SRC_ALPHA: u32   = 6u;
+let GTA_BLEND_DEST_ALPHA: u32      = 7u;
+let GTA_BLEND_INV_DEST_ALPHA: u32  = 8u;
+let GTA_BLEND_DEST_COLOR: u32      = 9u;
+let GTA_BLEND_INV_DEST_COLOR: u32  = 10u;
+let GTA_BLEND_SRC_ALPHA_SAT: u32   = 11u;
+
 struct GtaMaterial {
     emissive: vec4<f32>;
     perceptual_roughness: f32;
@@ -56,6 +77,7 @@ struct GtaMaterial {
     flags: u32;
     alpha_cutoff: f32;
     submaterial_count: u32;
+    day_night_balance: f32;
     submaterials: array<SubmaterialData, SUBMATERIAL_MAX_COUNT>;
 };
 
@@ -70,6 +92,8 @@ let GTA_MATERIAL_FLAGS_ALPHA_MODE_MASK: u32                = 128u;
 let GTA_MATERIAL_FLAGS_ALPHA_MODE_BLEND: u32               = 256u;
 let GTA_MATERIAL_FLAGS_TWO_COMPONENT_NORMAL_MAP: u32       = 512u;
 let GTA_MATERIAL_FLAGS_FLIP_NORMAL_MAP_Y: u32              = 1024u;
+let GTA_MATERIAL_FLAGS_SECONDARY_TEXTURE: u32              = 2048u;
+let GTA_MATERIAL_FLAGS_ENV_MAP_TEXTURE: u32                = 4096u;
 
 [[group(1), binding(0)]]
 var<uniform> material: GtaMaterial;
@@ -93,6 +117,14 @@ var occlusion_sampler: sampler;
 var normal_map_texture: texture_2d<f32>;
 [[group(1), binding(10)]]
 var normal_map_sampler: sampler;
+[[group(1), binding(11)]]
+var secondary_texture: texture_2d<f32>;
+[[group(1), binding(12)]]
+var secondary_sampler: sampler;
+[[group(1), binding(13)]]
+var env_map_texture: texture_2d<f32>;
+[[group(1), binding(14)]]
+var env_map_sampler: sampler;
 
 let PI: f32 = 3.141592653589793;
 
@@ -476,8 +508,44 @@ struct FragmentInput {
     [[location(3)]] world_tangent: vec4<f32>;
 #endif
     [[location(4)]] submaterial_id: u32;
+#ifdef VERTEX_PRELIT
+    [[location(5)]] day_color: vec4<f32>;
+    [[location(6)]] night_color: vec4<f32>;
+#endif
+#ifdef VERTEX_UV_1
+    [[location(7)]] uv_1: vec2<f32>;
+#endif
 };
 
+// The factor a blend function gives, where the source is the colour being drawn over the
+// destination
+fn blend_factor(blend: u32, src: vec4<f32>, dst: vec4<f32>) -> vec4<f32> {
+    let one = vec4<f32>(1.0, 1.0, 1.0, 1.0);
+    if (blend == GTA_BLEND_ZERO) {
+        return vec4<f32>(0.0, 0.0, 0.0, 0.0);
+    } else if (blend == GTA_BLEND_SRC_COLOR) {
+        return src;
+    } else if (blend == GTA_BLEND_INV_SRC_COLOR) {
+        return one - src;
+    } else if (blend == GTA_BLEND_SRC_ALPHA) {
+        return one * src.a;
+    } else if (blend == GTA_BLEND_INV_SRC_ALPHA) {
+        return one * (1.0 - src.a);
+    } else if (blend == GTA_BLEND_DEST_ALPHA) {
+        return one * dst.a;
+    } else if (blend == GTA_BLEND_INV_DEST_ALPHA) {
+        return one * (1.0 - dst.a);
+    } else if (blend == GTA_BLEND_DEST_COLOR) {
+        return dst;
+    } else if (blend == GTA_BLEND_INV_DEST_COLOR) {
+        return one - dst;
+    } else if (blend == GTA_BLEND_SRC_ALPHA_SAT) {
+        let f = min(src.a, 1.0 - dst.a);
+        return vec4<f32>(f, f, f, 1.0);
+    }
+    return one;
+}
+
 fn remap_uv(uv: vec2<f32>, tl: vec2<f32>, br: vec2<f32>) -> vec2<f32> {
     let size = br - tl;
     let uv = abs(uv % vec2<f32>(1.0, 1.0));
@@ -494,6 +562,20 @@ fn fragment(in: FragmentInput) -> [[location(0)]] vec4<f32> {
     } else {
         output_color = material.submaterials[in.submaterial_id].color;
     }
+    if ((material.flags & GTA_MATERIAL_FLAGS_SECONDARY_TEXTURE) != 0u) {
+#ifdef VERTEX_UV_1
+        let secondary_uv = in.uv_1;
+#else
+        let secondary_uv = in.uv;
+#endif
+        let secondary_color = textureSample(secondary_texture, secondary_sampler, secondary_uv);
+        // Only submaterials with a dual texture use it
+        let submaterial_data = material.submaterials[in.submaterial_id];
+        if ((submaterial_data.flags & GTA_SUBMATERIAL_FLAGS_DUAL_TEXTURE) != 0u) {
+            output_color = secondary_color * blend_factor(submaterial_data.src_blend, secondary_color, output_color)
+                + output_color * blend_factor(submaterial_data.dst_blend, secondary_color, output_color);
+        }
+    }
 
     // output_color = output_color * vec4<f32>(
     //     hsv2rgb(f32(in.submaterial_id) / f32(material.submaterial_count - 1u), 0.75, 0.75),
@@ -509,7 +591,7 @@ fn fragment(in: FragmentInput) -> [[location(0)]] vec4<f32> {
         }
 
         // calculate non-linear roughness from linear perceptualRoughness
-        var metallic: f32 = material.metallic;
+        var metallic: f32 = material.submaterials[in.submaterial_id].metallic;
         var perceptual_roughness: f32 = material.perceptual_roughness;
         if ((material.flags & GTA_MATERIAL_FLAGS_METALLIC_ROUGHNESS_TEXTURE_BIT) != 0u) {
             let metallic_roughness = textureSample(metallic_roughness_texture, metallic_roughness_sampler, in.uv);
@@ -595,7 +677,7 @@ fn fragment(in: FragmentInput) -> [[location(0)]] vec4<f32> {
 
         // Remapping [0,1] reflectance to F0
         // See https://google.github.io/filament/Filament.html#materialsystem/parameterization/remapping
-        let reflectance = material.reflectance;
+        let reflectance = material.submaterials[in.submaterial_id].reflectance;
         let F0 = 0.16 * reflectance * reflectance * (1.0 - metallic) + output_color.rgb * metallic;
 
         // Diffuse strength inversely related to metallicity
@@ -638,15 +720,33 @@ fn fragment(in: FragmentInput) -> [[location(0)]] vec4<f32> {
             light_accum = light_accum + light_contrib * shadow;
         }
 
+        // Prelit meshes have their ambient lighting baked into their vertex colours
+#ifdef VERTEX_PRELIT
+        let ambient_color = mix(in.day_color.rgb, in.night_color.rgb, material.day_night_balance);
+#else
+        let ambient_color = lights.ambient_color.rgb;
+#endif
+
         let diffuse_ambient = EnvBRDFApprox(diffuse_color, 1.0, NdotV);
         let specular_ambient = EnvBRDFApprox(F0, perceptual_roughness, NdotV);
 
         output_color = vec4<f32>(
             light_accum +
-                (diffuse_ambient + specular_ambient) * lights.ambient_color.rgb * occlusion +
+                (diffuse_ambient + specular_ambient) * ambient_color * occlusion +
                 emissive.rgb * output_color.a,
             output_color.a);
 
+        if ((material.flags & GTA_MATERIAL_FLAGS_ENV_MAP_TEXTURE) != 0u) {
+            // Sphere-mapped by the reflection vector, and added on top
+            let m = 2.0 * sqrt(R.x * R.x + R.y * R.y + (R.z + 1.0) * (R.z + 1.0));
+            let env_uv = vec2<f32>(R.x / m + 0.5, 0.5 - R.y / m);
+            // The alpha mask may have discarded fragments by now, so derivatives can't be
+            // used to pick a mip level
+            let env_color = textureSampleLevel(env_map_texture, env_map_sampler, env_uv, 0.0).rgb;
+            let coefficient = material.submaterials[in.submaterial_id].env_map_coefficient;
+            output_color = vec4<f32>(output_color.rgb + env_color * coefficient, output_color.a);
+        }
+
         // Cluster allocation debug (using 'over' alpha blending)
 #ifdef CLUSTERED_FORWARD_DEBUG_Z_SLICES
         // NOTE: This debug mode visualises the z-slices
@@ -20,6 +20,8 @@ use bevy::{
     },
 };
 
+use renderware_format::dff::BlendFunction;
+
 pub const SUBMATERIAL_MAX_COUNT: usize = 256;
 
 /// A material with "standard" properties used in PBR lighting
@@ -53,6 +55,12 @@ pub struct GtaMaterial {
     /// it to right-handed conventions.
     pub flip_normal_map_y: bool,
     pub occlusion_texture: Option<Handle<Image>>,
+    /// A second texture, sampled with the mesh's second UV set if it has one, and blended
+    /// over the base colour of the submaterials that have a dual texture.
+    pub secondary_texture: Option<Handle<Image>>,
+    /// A sphere map reflected by the submaterials that have an environment map, scaled by
+    /// their coefficients.
+    pub env_map_texture: Option<Handle<Image>>,
     /// Support two-sided lighting by automatically flipping the normals for "back" faces
     /// within the PBR lighting shader.
     /// Defaults to false.
@@ -66,6 +74,9 @@ pub struct GtaMaterial {
     pub alpha_mode: AlphaMode,
     pub materials: Vec<renderware_format::dff::Material>,
     pub frames: Option<Vec<renderware_format::packer::Frame>>,
+    /// How far to blend prelit meshes from their day colours to their night colours,
+    /// from [0.0, 1.0]
+    pub day_night_balance: f32,
 }
 
 impl Default for GtaMaterial {
@@ -89,6 +100,8 @@ impl Default for GtaMaterial {
             // <https://google.github.io/filament/Material%20Properties.pdf>
             reflectance: 0.5,
             occlusion_texture: None,
+            secondary_texture: None,
+            env_map_texture: None,
             normal_map_texture: None,
             flip_normal_map_y: false,
             double_sided: false,
@@ -97,10 +110,30 @@ impl Default for GtaMaterial {
             alpha_mode: AlphaMode::Opaque,
             materials: vec![],
             frames: None,
+            day_night_balance: 0.0,
         }
     }
 }
 
+impl GtaMaterial {
+    /// The metallic and reflectance of a submaterial, from its reflection and specular
+    /// plugins if it has them and the material's otherwise.
+    fn submaterial_metallic_and_reflectance(&self, index: usize) -> (f32, f32) {
+        let submaterial = &self.materials[index];
+        let metallic = submaterial
+            .reflection
+            .as_ref()
+            .map(|r| r.intensity.clamp(0.0, 1.0))
+            .unwrap_or(self.metallic);
+        let reflectance = submaterial
+            .specular
+            .as_ref()
+            .map(|s| s.level.clamp(0.0, 1.0))
+            .unwrap_or(self.reflectance);
+        (metallic, reflectance)
+    }
+}
+
 impl From<Handle<Image>> for GtaMaterial {
     fn from(texture: Handle<Image>) -> Self {
         GtaMaterial {
@@ -124,16 +157,36 @@ bitflags::bitflags! {
         const ALPHA_MODE_BLEND           = (1 << 8);
         const TWO_COMPONENT_NORMAL_MAP   = (1 << 9);
         const FLIP_NORMAL_MAP_Y          = (1 << 10);
+        const SECONDARY_TEXTURE          = (1 << 11);
+        const ENV_MAP_TEXTURE            = (1 << 12);
         const NONE                       = 0;
         const UNINITIALIZED              = 0xFFFF;
     }
 }
 
+bitflags::bitflags! {
+    #[repr(transparent)]
+    pub struct GtaSubmaterialFlags: u32 {
+        const DUAL_TEXTURE = (1 << 0);
+        const NONE         = 0;
+    }
+}
+
 #[derive(Copy, Clone, Default, AsStd140)]
 pub struct GtaMaterialSubmaterialData {
     pub color: Vec4,
     pub uv_top_left: Vec2,
     pub uv_bottom_right: Vec2,
+    /// How strongly the environment map is reflected; zero if the submaterial has none
+    pub env_map_coefficient: f32,
+    pub flags: u32,
+    /// The material's metallic and reflectance, unless the submaterial's reflection and
+    /// specular plugins override them
+    pub metallic: f32,
+    pub reflectance: f32,
+    /// How the dual texture is blended over the base colour, as RenderWare blend functions
+    pub src_blend: u32,
+    pub dst_blend: u32,
 }
 
 /// The GPU representation of the uniform data of a [`GtaMaterial`].
@@ -156,6 +209,7 @@ pub struct GtaMaterialUniformData {
     pub alpha_cutoff: f32,
     /// The number of submaterials.
     pub submaterial_count: u32,
+    pub day_night_balance: f32,
     pub submaterials: [GtaMaterialSubmaterialData; SUBMATERIAL_MAX_COUNT],
 }
 
@@ -234,6 +288,22 @@ impl RenderAsset for GtaMaterial {
         } else {
             return Err(PrepareAssetError::RetryNextUpdate(material));
         };
+        let (secondary_texture_view, secondary_sampler) = if let Some(result) = gta_pipeline
+            .mesh_pipeline
+            .get_image_texture(gpu_images, &material.secondary_texture)
+        {
+            result
+        } else {
+            return Err(PrepareAssetError::RetryNextUpdate(material));
+        };
+        let (env_map_texture_view, env_map_sampler) = if let Some(result) = gta_pipeline
+            .mesh_pipeline
+            .get_image_texture(gpu_images, &material.env_map_texture)
+        {
+            result
+        } else {
+            return Err(PrepareAssetError::RetryNextUpdate(material));
+        };
         let mut flags = GtaMaterialFlags::NONE;
         if material.base_color_texture.is_some() {
             flags |= GtaMaterialFlags::BASE_COLOR_TEXTURE;
@@ -247,6 +317,12 @@ impl RenderAsset for GtaMaterial {
         if material.occlusion_texture.is_some() {
             flags |= GtaMaterialFlags::OCCLUSION_TEXTURE;
         }
+        if material.secondary_texture.is_some() {
+            flags |= GtaMaterialFlags::SECONDARY_TEXTURE;
+        }
+        if material.env_map_texture.is_some() {
+            flags |= GtaMaterialFlags::ENV_MAP_TEXTURE;
+        }
         if material.double_sided {
             flags |= GtaMaterialFlags::DOUBLE_SIDED;
         }
@@ -294,6 +370,7 @@ impl RenderAsset for GtaMaterial {
             flags: flags.bits(),
             alpha_cutoff,
             submaterial_count: submaterials.len() as u32,
+            day_night_balance: material.day_night_balance,
             submaterials: [Default::default(); SUBMATERIAL_MAX_COUNT],
         };
         for (idx, submaterial) in submaterials.iter().enumerate() {
@@ -303,10 +380,30 @@ impl RenderAsset for GtaMaterial {
                 Some(f) => (f.top_left.into(), f.bottom_right.into()),
                 None => (Vec2::ZERO, Vec2::ZERO),
             };
+            let mut submaterial_flags = GtaSubmaterialFlags::NONE;
+            if submaterial.dual_texture().is_some() {
+                submaterial_flags |= GtaSubmaterialFlags::DUAL_TEXTURE;
+            }
+            // Without blend functions, the dual texture is multiplied in
+            let (src_blend, dst_blend) = match submaterial.dual_blend() {
+                Some((src, dst))
+                    if src != BlendFunction::NaBlend && dst != BlendFunction::NaBlend =>
+                {
+                    (src, dst)
+                }
+                _ => (BlendFunction::Zero, BlendFunction::SrcColor),
+            };
+            let (metallic, reflectance) = material.submaterial_metallic_and_reflectance(idx);
             value.submaterials[idx] = GtaMaterialSubmaterialData {
                 color: Color::rgba_u8(c.r, c.g, c.b, c.a).into(),
                 uv_top_left,
                 uv_bottom_right,
+                env_map_coefficient: submaterial.env_map().map(|(c, _)| c).unwrap_or_default(),
+                flags: submaterial_flags.bits(),
+                metallic,
+                reflectance,
+                src_blend: src_blend as u32,
+                dst_blend: dst_blend as u32,
             };
         }
         let value_std140 = value.as_std140();
@@ -361,6 +458,22 @@ impl RenderAsset for GtaMaterial {
                     binding: 10,
                     resource: BindingResource::Sampler(normal_map_sampler),
                 },
+                BindGroupEntry {
+                    binding: 11,
+                    resource: BindingResource::TextureView(secondary_texture_view),
+                },
+                BindGroupEntry {
+                    binding: 12,
+                    resource: BindingResource::Sampler(secondary_sampler),
+                },
+                BindGroupEntry {
+                    binding: 13,
+                    resource: BindingResource::TextureView(env_map_texture_view),
+                },
+                BindGroupEntry {
+                    binding: 14,
+                    resource: BindingResource::Sampler(env_map_sampler),
+                },
             ],
             label: Some("gta_material_bind_group"),
             layout: &gta_pipeline.material_layout,
@@ -429,6 +542,35 @@ impl SpecializedMaterial for GtaMaterial {
                 vertex_attributes.push(Mesh::ATTRIBUTE_JOINT_WEIGHT.at_shader_location(5));
             };
             vertex_attributes.push(super::ATTRIBUTE_MATERIAL_ID.at_shader_location(6));
+            if layout.contains(super::ATTRIBUTE_DAY_COLOR)
+                && layout.contains(super::ATTRIBUTE_NIGHT_COLOR)
+            {
+                vertex_attributes.push(super::ATTRIBUTE_DAY_COLOR.at_shader_location(7));
+                vertex_attributes.push(super::ATTRIBUTE_NIGHT_COLOR.at_shader_location(8));
+                descriptor
+                    .vertex
+                    .shader_defs
+                    .push(String::from("VERTEX_PRELIT"));
+                descriptor
+                    .fragment
+                    .as_mut()
+                    .unwrap()
+                    .shader_defs
+                    .push(String::from("VERTEX_PRELIT"));
+            }
+            if layout.contains(super::ATTRIBUTE_UV_1) {
+                vertex_attributes.push(super::ATTRIBUTE_UV_1.at_shader_location(9));
+                descriptor
+                    .vertex
+                    .shader_defs
+                    .push(String::from("VERTEX_UV_1"));
+                descriptor
+                    .fragment
+                    .as_mut()
+                    .unwrap()
+                    .shader_defs
+                    .push(String::from("VERTEX_UV_1"));
+            }
 
             layout.get_layout(&vertex_attributes)?
         };
@@ -557,6 +699,42 @@ impl SpecializedMaterial for GtaMaterial {
                     ty: BindingType::Sampler(SamplerBindingType::Filtering),
                     count: None,
                 },
+                // Secondary Texture
+                BindGroupLayoutEntry {
+                    binding: 11,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                // Secondary Texture Sampler
+                BindGroupLayoutEntry {
+                    binding: 12,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+                // Environment Map Texture
+                BindGroupLayoutEntry {
+                    binding: 13,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                // Environment Map Texture Sampler
+                BindGroupLayoutEntry {
+                    binding: 14,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
             ],
             label: Some("gta_material_layout"),
         })
@@ -567,3 +745,43 @@ impl SpecializedMaterial for GtaMaterial {
         render_asset.alpha_mode
     }
 }
+
+mod tests {
+    #[test]
+    fn takes_each_submaterials_reflection_and_specular() {
+        use renderware_format::dff::{Color, Material, Reflection, Specular};
+
+        let submaterial = |plugins: Option<(f32, f32)>| Material {
+            color: Color::new(255, 255, 255, 255),
+            is_textured: false,
+            lighting: None,
+            texture: None,
+            effects: vec![],
+            reflection: plugins.map(|(intensity, _)| Reflection {
+                scale: [1.0, 1.0],
+                offset: [0.0, 0.0],
+                intensity,
+            }),
+            specular: plugins.map(|(_, level)| Specular {
+                level,
+                texture_name: "vehiclespecdot64".to_string(),
+            }),
+        };
+        let material = super::GtaMaterial {
+            materials: vec![
+                submaterial(Some((0.75, 0.25))),
+                submaterial(None),
+                submaterial(Some((2.0, 0.5))),
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            material.submaterial_metallic_and_reflectance(0),
+            (0.75, 0.25)
+        );
+        let defaults = (material.metallic, material.reflectance);
+        assert_eq!(material.submaterial_metallic_and_reflectance(1), defaults);
+        assert_eq!(material.submaterial_metallic_and_reflectance(2), (1.0, 0.5));
+    }
+}
@@ -13,6 +13,13 @@ struct Vertex {
     [[location(5)]] joint_weights: vec4<f32>;
 #endif
     [[location(6)]] material_id: u32;
+#ifdef VERTEX_PRELIT
+    [[location(7)]] day_color: vec4<f32>;
+    [[location(8)]] night_color: vec4<f32>;
+#endif
+#ifdef VERTEX_UV_1
+    [[location(9)]] uv_1: vec2<f32>;
+#endif
 };
 
 struct VertexOutput {
@@ -24,6 +31,13 @@ struct VertexOutput {
     [[location(3)]] world_tangent: vec4<f32>;
 #endif
     [[location(4)]] material_id: u32;
+#ifdef VERTEX_PRELIT
+    [[location(5)]] day_color: vec4<f32>;
+    [[location(6)]] night_color: vec4<f32>;
+#endif
+#ifdef VERTEX_UV_1
+    [[location(7)]] uv_1: vec2<f32>;
+#endif
 };
 
 [[group(2), binding(0)]]
@@ -66,5 +80,12 @@ fn vertex(vertex: Vertex) -> VertexOutput {
     out.uv = vertex.uv;
     out.clip_position = view.view_proj * out.world_position;
     out.material_id = vertex.material_id;
+#ifdef VERTEX_PRELIT
+    out.day_color = vertex.day_color;
+    out.night_color = vertex.night_color;
+#endif
+#ifdef VERTEX_UV_1
+    out.uv_1 = vertex.uv_1;
+#endif
     return out;
 }
\ No newline at end of file
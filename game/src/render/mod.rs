@@ -20,6 +20,16 @@ pub const GTA_COMMON_SHADER_HANDLE: HandleUntyped =
 pub const ATTRIBUTE_MATERIAL_ID: MeshVertexAttribute =
     MeshVertexAttribute::new("MaterialId", 2708715425, VertexFormat::Uint32);
 
+pub const ATTRIBUTE_DAY_COLOR: MeshVertexAttribute =
+    MeshVertexAttribute::new("DayColor", 1480235313, VertexFormat::Unorm8x4);
+
+pub const ATTRIBUTE_NIGHT_COLOR: MeshVertexAttribute =
+    MeshVertexAttribute::new("NightColor", 3917513740, VertexFormat::Unorm8x4);
+
+// Bevy only has a single UV attribute, so the second UV set gets its own
+pub const ATTRIBUTE_UV_1: MeshVertexAttribute =
+    MeshVertexAttribute::new("Vertex_Uv_1", 2961463717, VertexFormat::Float32x2);
+
 pub type GtaBundle = MaterialMeshBundle<GtaMaterial>;
 
 #[derive(Default)]
@@ -0,0 +1,111 @@
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+use vice_city_formats::img::{Img, Version};
+
+#[derive(Parser)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// Path of the archive to operate on
+    #[clap()]
+    archive: PathBuf,
+
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Creates a new, empty archive
+    Create {
+        /// Create a San Andreas (VER2) archive instead of a .img/.dir pair
+        #[clap(long)]
+        ver2: bool,
+    },
+    /// Lists the entries of the archive
+    List,
+    /// Extracts entries to a directory; extracts everything if no names are given
+    Extract {
+        /// Where to deposit the extracted files
+        #[clap(short, long)]
+        output: PathBuf,
+
+        names: Vec<String>,
+    },
+    /// Adds files to the archive, replacing any entries with the same name
+    Add { paths: Vec<PathBuf> },
+    /// Deletes entries from the archive
+    Delete { names: Vec<String> },
+    /// Rewrites the archive to reclaim space left by deleted or replaced entries
+    Repack,
+}
+
+// Entry names come from the archive, so anything that would land outside `output` (a
+// parent directory, an absolute path or a drive prefix) is refused
+fn output_path(output: &Path, name: &str) -> anyhow::Result<PathBuf> {
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(file_name)), None) => Ok(output.join(file_name)),
+        _ => anyhow::bail!("refusing to extract `{}` outside of {:?}", name, output),
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+
+    if let Command::Create { ver2 } = args.command {
+        let version = if ver2 { Version::V2 } else { Version::V1 };
+        Img::create(&args.archive, version)?;
+        return Ok(());
+    }
+
+    let mut img =
+        Img::open(&args.archive).with_context(|| format!("failed to open {:?}", args.archive))?;
+    match args.command {
+        Command::Create { .. } => unreachable!(),
+        Command::List => {
+            for entry in &img.entries {
+                println!(
+                    "{:<24} offset {:>8} size {:>8}",
+                    entry.name,
+                    entry.byte_offset(),
+                    entry.byte_size()
+                );
+            }
+        }
+        Command::Extract { output, names } => {
+            std::fs::create_dir_all(&output)?;
+            let names = if names.is_empty() {
+                img.entries.iter().map(|e| e.name.clone()).collect()
+            } else {
+                names
+            };
+            for name in &names {
+                std::fs::write(output_path(&output, name)?, img.extract(name)?)?;
+            }
+        }
+        Command::Add { paths } => {
+            for path in &paths {
+                let name = path
+                    .file_name()
+                    .context("path has no file name")?
+                    .to_string_lossy();
+                let data = std::fs::read(path)?;
+                if img.entry(&name).is_some() {
+                    img.replace(&name, &data)?;
+                } else {
+                    img.add(&name, &data)?;
+                }
+            }
+        }
+        Command::Delete { names } => {
+            for name in &names {
+                img.delete(name)?;
+            }
+        }
+        Command::Repack => img.repack()?,
+    }
+
+    Ok(())
+}
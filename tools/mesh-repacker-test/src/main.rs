@@ -45,8 +45,8 @@ fn main() -> anyhow::Result<()> {
         .context("failed to find corresponding texture")?;
     let texture_path = model_path.with_file_name(&format!("{}.txd", texture_name));
 
-    let models = Model::from_raw(&BinaryStreamFile::open(&model_path)?);
-    let textures = Texture::from_raw(&BinaryStreamFile::open(&texture_path)?);
+    let models = Model::from_raw(&BinaryStreamFile::open(&model_path)?)?;
+    let textures = Texture::from_raw(&BinaryStreamFile::open(&texture_path)?)?;
 
     fs::create_dir_all(&args.output)?;
     for (index, (_, model)) in models.iter().enumerate() {
@@ -59,7 +59,7 @@ fn main() -> anyhow::Result<()> {
         let texture_output_path = args.output.join(format!("{}_{}.png", file_stem, index));
         image::save_buffer(
             texture_output_path,
-            &texture.data,
+            &texture.levels[0],
             texture.width as _,
             texture.height as _,
             image::ColorType::Rgba8,
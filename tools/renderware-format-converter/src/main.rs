@@ -1,11 +1,40 @@
 use std::path::PathBuf;
 
+use anyhow::Context;
 use clap::{ArgEnum, Parser};
 use renderware_format as rwf;
+use vice_city_formats::img;
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ArgEnum)]
 enum Format {
     Png,
+    Txd,
+}
+
+// How textures are stored when converting to TXD
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ArgEnum)]
+enum Encoding {
+    /// DXT1 for opaque images, and DXT5 for those with alpha
+    Auto,
+    Dxt1,
+    Dxt3,
+    Dxt5,
+    /// Uncompressed
+    #[clap(name = "8888")]
+    Rgba8888,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ArgEnum)]
+enum Filtering {
+    Nearest,
+    Linear,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ArgEnum)]
+enum Addressing {
+    Wrap,
+    Mirror,
+    Clamp,
 }
 
 #[derive(Parser)]
@@ -15,19 +44,40 @@ struct Args {
     #[clap(arg_enum, short, long)]
     format: Format,
 
-    /// Path of the file to convert
+    /// Path of the file to convert, or of an entry within an archive (`gta3.img:bar.txd`).
+    /// When converting to TXD, a PNG or a directory of them, named after their textures.
     #[clap()]
     path: PathBuf,
 
     /// Where to deposit the converted files
     #[clap()]
     output_directory: PathBuf,
+
+    /// How to store textures when converting to TXD
+    #[clap(arg_enum, long, default_value = "auto")]
+    encoding: Encoding,
+
+    /// Don't generate mipmaps when converting to TXD
+    #[clap(long)]
+    no_mipmaps: bool,
+
+    /// The filtering of textures when converting to TXD
+    #[clap(arg_enum, long, default_value = "linear")]
+    filtering: Filtering,
+
+    /// The addressing of textures in both directions when converting to TXD
+    #[clap(arg_enum, long, default_value = "wrap")]
+    addressing: Addressing,
 }
 
 fn main() -> anyhow::Result<()> {
     let args = Args::parse();
     std::fs::create_dir_all(&args.output_directory)?;
 
+    if args.format == Format::Txd {
+        return png_to_txd(&args);
+    }
+
     if args.path.extension().unwrap_or_default() != "txd" {
         unimplemented!(
             "renderware-format-converter does not support anything other than txd at this time"
@@ -36,15 +86,18 @@ fn main() -> anyhow::Result<()> {
 
     let extension = match args.format {
         Format::Png => "png",
+        Format::Txd => unreachable!(),
     };
 
-    let textures = rwf::txd::Texture::from_raw(&rwf::raw::BinaryStreamFile::open(&args.path)?);
+    let textures = rwf::txd::Texture::from_raw(&rwf::raw::BinaryStreamFile::from_bytes(
+        &img::read_path(&args.path)?,
+    )?)?;
     for texture in &textures {
         image::save_buffer(
             args.output_directory
                 .join(&texture.name)
                 .with_extension(extension),
-            &texture.data,
+            &texture.levels[0],
             texture.width as u32,
             texture.height as u32,
             image::ColorType::Rgba8,
@@ -53,3 +106,66 @@ fn main() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+fn png_to_txd(args: &Args) -> anyhow::Result<()> {
+    use rwf::txd::{PixelFormat, Texture, TextureAddressing, TextureFiltering};
+
+    let paths = if args.path.is_dir() {
+        let mut paths: Vec<_> = std::fs::read_dir(&args.path)?
+            .map(|entry| Ok(entry?.path()))
+            .collect::<anyhow::Result<_>>()?;
+        paths.retain(|path| path.extension().unwrap_or_default() == "png");
+        paths.sort();
+        paths
+    } else {
+        vec![args.path.clone()]
+    };
+
+    let filtering = match (args.filtering, args.no_mipmaps) {
+        (Filtering::Nearest, true) => TextureFiltering::Nearest,
+        (Filtering::Nearest, false) => TextureFiltering::MipNearest,
+        (Filtering::Linear, true) => TextureFiltering::Linear,
+        (Filtering::Linear, false) => TextureFiltering::LinearMipLinear,
+    };
+    let addressing = match args.addressing {
+        Addressing::Wrap => TextureAddressing::Wrap,
+        Addressing::Mirror => TextureAddressing::Mirror,
+        Addressing::Clamp => TextureAddressing::Clamp,
+    };
+
+    let mut textures = vec![];
+    for path in &paths {
+        let image = image::open(path)?.into_rgba8();
+        let (width, height) = (image.width().try_into()?, image.height().try_into()?);
+        let format = match args.encoding {
+            Encoding::Auto if image.pixels().any(|p| p[3] != 255) => PixelFormat::Bc3,
+            Encoding::Auto | Encoding::Dxt1 => PixelFormat::Bc1,
+            Encoding::Dxt3 => PixelFormat::Bc2,
+            Encoding::Dxt5 => PixelFormat::Bc3,
+            Encoding::Rgba8888 => PixelFormat::Rgba8,
+        };
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        let mut texture = Texture::from_rgba8(
+            &name,
+            filtering,
+            (addressing, addressing),
+            width,
+            height,
+            image.as_raw(),
+            format,
+        )
+        .with_context(|| format!("couldn't convert {}", path.display()))?;
+        if args.no_mipmaps {
+            texture.levels.truncate(1);
+        }
+        textures.push(texture);
+    }
+
+    let name = args.path.file_stem().unwrap_or_default();
+    std::fs::write(
+        args.output_directory.join(name).with_extension("txd"),
+        Texture::to_raw(&textures).to_bytes(),
+    )?;
+
+    Ok(())
+}
@@ -2,11 +2,14 @@ use std::path::PathBuf;
 
 use clap::{ArgEnum, Parser};
 use renderware_format as rwf;
+use vice_city_formats::img;
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ArgEnum)]
 enum Mode {
     Raw,
     Processed,
+    /// Check that writing the parsed file reproduces it exactly
+    RoundTrip,
 }
 
 #[derive(Parser)]
@@ -16,7 +19,8 @@ struct Args {
     #[clap(arg_enum, short, long)]
     mode: Mode,
 
-    /// Path of the file to inspect
+    /// Path of the file to inspect. Can be a directory, an IMG archive,
+    /// or an entry within an archive (`gta3.img:bar.dff`)
     #[clap()]
     path: PathBuf,
 }
@@ -24,7 +28,7 @@ struct Args {
 fn print_section(section: &rwf::raw::Section, depth: i32) {
     print!("{}", "  ".repeat(depth as usize));
     println!(
-        "{:?}({:X}): {:?}",
+        "{:?}({}): {:?}",
         section.section_type, section.version, section.data
     );
     for child in &section.children {
@@ -34,34 +38,73 @@ fn print_section(section: &rwf::raw::Section, depth: i32) {
 
 fn main() -> anyhow::Result<()> {
     let args = Args::parse();
-    let paths = if args.path.is_dir() {
+    let is_supported = |p: &PathBuf| {
+        let extension = p.extension().unwrap_or_default();
+        extension == "dff" || extension == "txd"
+    };
+    // An archive is opened once, and each of its entries read through the same handle
+    let mut archive = None;
+    let paths: Vec<(PathBuf, Option<img::Entry>)> = if args.path.is_dir() {
         std::fs::read_dir(&args.path)?
             .filter_map(Result::ok)
             .map(|d| d.path())
-            .filter(|p| {
-                let extension = p.extension().unwrap_or_default();
-                extension == "dff" || extension == "txd"
+            .filter(is_supported)
+            .map(|p| (p, None))
+            .collect()
+    } else if args.path.extension().unwrap_or_default() == "img" {
+        let img = img::Img::open(&args.path)?;
+        archive = Some(std::fs::File::open(&args.path)?);
+        img.entries
+            .into_iter()
+            .map(|e| {
+                (
+                    PathBuf::from(format!("{}:{}", args.path.display(), e.name)),
+                    e,
+                )
             })
+            .filter(|(p, _)| is_supported(p))
+            .map(|(p, e)| (p, Some(e)))
             .collect()
     } else {
-        vec![args.path]
+        vec![(args.path, None)]
     };
 
-    for path in &paths {
+    for (path, entry) in &paths {
         if paths.len() > 1 {
             println!("{:?}", path);
         }
 
-        let file = rwf::raw::BinaryStreamFile::open(path)?;
+        let bytes = match (entry, &mut archive) {
+            (Some(entry), Some(archive)) => entry.read(archive)?,
+            _ => img::read_path(path)?,
+        };
+        let file = rwf::raw::BinaryStreamFile::from_bytes(&bytes)?;
         match args.mode {
-            Mode::Raw => print_section(&file.sections[0], 0),
+            Mode::Raw => {
+                for section in &file.sections {
+                    print_section(section, 0);
+                }
+            }
             Mode::Processed => {
                 let extension = path.extension().unwrap_or_default();
 
                 if extension == "dff" {
-                    println!("{:?}", rwf::dff::Model::from_raw(&file));
+                    println!("{:?}", rwf::dff::Clump::from_raw(&file)?);
                 } else if extension == "txd" {
-                    println!("{:?}", rwf::txd::Texture::from_raw(&file));
+                    println!("{:?}", rwf::txd::Texture::from_raw(&file)?);
+                }
+            }
+            Mode::RoundTrip => {
+                let written = file.to_bytes();
+                match written.iter().zip(&bytes).position(|(a, b)| a != b) {
+                    Some(offset) => println!("{:?}: differs at offset {:#X}", path, offset),
+                    None if written.len() != bytes.len() => println!(
+                        "{:?}: wrote {} bytes, expected {}",
+                        path,
+                        written.len(),
+                        bytes.len()
+                    ),
+                    None => {}
                 }
             }
         }